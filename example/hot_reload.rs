@@ -1,34 +1,21 @@
-use std::fs;
-
-use command::handler::CommandResultEvent;
-use command_macros::Command;
 use valence::abilities::FlyingSpeed;
 use valence::command::scopes::CommandScopes;
-use valence::command::{self, AddCommand, CommandScopeRegistry};
+use valence::command::CommandScopeRegistry;
 use valence::op_level::OpLevel;
+use valence::prelude::*;
 use valence::spawn::IsFlat;
-use valence::{command_macros, prelude::*};
-use valence_terrain::{
-    SerializableTerrainGenConfig, TerrainGenConfig, TerrainGenerator, TerrainPlugin,
-};
+use valence_terrain::hot_reload::{load_config, TerrainHotReloadPlugin, WatchedConfig};
+use valence_terrain::{ReloadMode, TerrainGenerator, TerrainPlugin};
 
 const SPAWN_POS: DVec3 = DVec3::new(0.0, 150.0, 0.0);
 
-#[derive(Command, Debug, Clone)]
-#[paths("reload", "rl")]
-#[scopes("valence.command.reload")]
-struct ReloadCommand;
+const CONFIG_PATH: &str = "terrain.yml";
 
 fn main() {
     App::new()
-        .add_plugins((DefaultPlugins, TerrainPlugin))
+        .add_plugins((DefaultPlugins, TerrainPlugin, TerrainHotReloadPlugin))
         .add_systems(Startup, setup)
-        .add_systems(
-            Update,
-            (init_clients, despawn_disconnected_clients, handle_reload),
-        )
-        .add_command::<ReloadCommand>()
-        .add_systems(Update, handle_reload)
+        .add_systems(Update, (init_clients, despawn_disconnected_clients))
         .add_systems(
             Startup,
             |mut command_scopes: ResMut<CommandScopeRegistry>| {
@@ -38,16 +25,6 @@ fn main() {
         .run();
 }
 
-fn load_config() -> Result<TerrainGenConfig, String> {
-    match fs::read_to_string("terrain.yml") {
-        Ok(content) => match serde_yml::from_str::<SerializableTerrainGenConfig>(&content) {
-            Ok(cfg) => cfg.parse(),
-            Err(e) => Err(format!("error while reading yaml '{}'", e.to_string())),
-        },
-        Err(e) => Err(format!("error while reading file '{}'", e.to_string())),
-    }
-}
-
 fn setup(
     mut commands: Commands,
     server: Res<Server>,
@@ -59,7 +36,11 @@ fn setup(
     commands.spawn((
         layer,
         // server will immediatley crash if wrong config on startup
-        TerrainGenerator::new(load_config().expect("error in config"), 0),
+        TerrainGenerator::new(load_config(CONFIG_PATH).expect("error in config"), 0),
+        // ops are told in chat when the config is reloaded, or why it could not be
+        WatchedConfig(CONFIG_PATH.into()),
+        // the old chunks stay until they are replaced, and only surfaces are repainted if nothing else changed
+        ReloadMode::Incremental,
     ));
 }
 
@@ -105,28 +86,3 @@ fn init_clients(
         flyspeed.0 = 0.5;
     }
 }
-
-fn handle_reload(
-    mut events: EventReader<CommandResultEvent<ReloadCommand>>,
-    mut layer: Query<(&mut ChunkLayer, &mut TerrainGenerator)>,
-    mut client: Query<&mut Client>,
-) {
-    if events.is_empty() {
-        return;
-    }
-    let (mut layer, mut terrain_gen) = layer.single_mut();
-    let mut client = match client.get_mut(events.read().next().unwrap().executor) {
-        Ok(client) => client,
-        Err(_) => return,
-    };
-    events.clear();
-    layer.clear_chunks();
-    let config = match crate::load_config() {
-        Ok(cfg) => cfg,
-        Err(e) => {
-            client.send_chat_message(format!("error while loading terrain: {}", e));
-            return;
-        }
-    };
-    terrain_gen.reload(config);
-}
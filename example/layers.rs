@@ -0,0 +1,153 @@
+use std::fs;
+
+use command::handler::CommandResultEvent;
+use command_macros::Command;
+use valence::command::scopes::CommandScopes;
+use valence::command::{self, AddCommand};
+use valence::spawn::IsFlat;
+use valence::{command_macros, prelude::*};
+use valence_terrain::{TerrainGenConfig, TerrainGenerator, TerrainPlugin};
+
+/// Switches between the overworld and the skyblock layer
+#[derive(Command, Debug, Clone)]
+#[paths("layer")]
+#[scopes("valence.command.layer")]
+struct LayerCommand;
+
+/// Both layers have their own generator and config, the chunks of both are generated by the same workers
+#[derive(Resource)]
+struct Layers {
+    overworld: Entity,
+    skyblock: Entity,
+}
+
+fn main() {
+    App::new()
+        .add_plugins((DefaultPlugins, TerrainPlugin))
+        .add_systems(Startup, setup)
+        .add_systems(
+            Update,
+            (init_clients, despawn_disconnected_clients, handle_layer),
+        )
+        .add_command::<LayerCommand>()
+        .run();
+}
+
+fn load_config(path: &str) -> TerrainGenConfig {
+    let content = fs::read_to_string(path).unwrap_or_else(|e| panic!("could not read {path}: {e}"));
+    serde_yml::from_str(&content).unwrap_or_else(|e| panic!("invalid config {path}: {e}"))
+}
+
+fn setup(
+    mut commands: Commands,
+    server: Res<Server>,
+    dimensions: Res<DimensionTypeRegistry>,
+    biomes: Res<BiomeRegistry>,
+) {
+    let mut spawn = |path: &str| {
+        commands
+            .spawn((
+                LayerBundle::new(ident!("overworld"), &dimensions, &biomes, &server),
+                TerrainGenerator::new(load_config(path), 0),
+            ))
+            .id()
+    };
+    let layers = Layers {
+        overworld: spawn("example/layers/overworld.yml"),
+        skyblock: spawn("example/layers/skyblock.yml"),
+    };
+    commands.insert_resource(layers);
+}
+
+/// Moves a client to a layer, on top of the terrain at 0 0
+fn move_to(
+    layer: Entity,
+    generators: &Query<&TerrainGenerator>,
+    layer_id: &mut EntityLayerId,
+    visible_chunk_layer: &mut VisibleChunkLayer,
+    visible_entity_layers: &mut VisibleEntityLayers,
+    pos: &mut Position,
+) {
+    let old = visible_chunk_layer.0;
+    visible_entity_layers.0.remove(&old);
+    layer_id.0 = layer;
+    visible_chunk_layer.0 = layer;
+    visible_entity_layers.0.insert(layer);
+    let y = generators
+        .get(layer)
+        .map_or(150, |gen| gen.height_at(0, 0) + 1);
+    pos.set(DVec3::new(0.5, y as f64, 0.5));
+}
+
+fn init_clients(
+    mut clients: Query<
+        (
+            &mut EntityLayerId,
+            &mut VisibleChunkLayer,
+            &mut VisibleEntityLayers,
+            &mut Position,
+            &mut GameMode,
+            &mut IsFlat,
+            &mut CommandScopes,
+        ),
+        Added<Client>,
+    >,
+    layers: Res<Layers>,
+    generators: Query<&TerrainGenerator>,
+) {
+    for (
+        mut layer_id,
+        mut visible_chunk_layer,
+        mut visible_entity_layers,
+        mut pos,
+        mut game_mode,
+        mut is_flat,
+        mut scopes,
+    ) in &mut clients
+    {
+        move_to(
+            layers.overworld,
+            &generators,
+            &mut layer_id,
+            &mut visible_chunk_layer,
+            &mut visible_entity_layers,
+            &mut pos,
+        );
+        *game_mode = GameMode::Creative;
+        scopes.add("valence.command.layer");
+        is_flat.0 = true;
+    }
+}
+
+fn handle_layer(
+    mut events: EventReader<CommandResultEvent<LayerCommand>>,
+    mut clients: Query<(
+        &mut EntityLayerId,
+        &mut VisibleChunkLayer,
+        &mut VisibleEntityLayers,
+        &mut Position,
+    )>,
+    layers: Res<Layers>,
+    generators: Query<&TerrainGenerator>,
+) {
+    for event in events.read() {
+        let Ok((mut layer_id, mut visible_chunk_layer, mut visible_entity_layers, mut pos)) =
+            clients.get_mut(event.executor)
+        else {
+            continue;
+        };
+        let target = if visible_chunk_layer.0 == layers.overworld {
+            layers.skyblock
+        } else {
+            layers.overworld
+        };
+        move_to(
+            target,
+            &generators,
+            &mut layer_id,
+            &mut visible_chunk_layer,
+            &mut visible_entity_layers,
+            &mut pos,
+        );
+    }
+}
@@ -0,0 +1,45 @@
+//! Renders the terrain of a config to a PNG, without starting a server.  
+//! Usage: `preview <config.yml> <out.png> [size] [blocks per pixel] [center x] [center z]`
+
+use std::{env, fs, process::ExitCode};
+
+use valence_terrain::{preview::Preview, TerrainGenConfig};
+
+fn main() -> ExitCode {
+    let args = env::args().collect::<Vec<_>>();
+    if args.len() < 3 {
+        eprintln!(
+            "usage: preview <config.yml> <out.png> [size] [blocks per pixel] [center x] [center z]"
+        );
+        return ExitCode::FAILURE;
+    }
+    match run(&args[1..]) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let content =
+        fs::read_to_string(&args[0]).map_err(|e| format!("could not read {}: {e}", args[0]))?;
+    let config = serde_yml::from_str::<TerrainGenConfig>(&content)
+        .map_err(|e| format!("invalid config: {e}"))?;
+    let number = |i: usize, default: i32| -> Result<i32, String> {
+        match args.get(i) {
+            Some(arg) => arg.parse().map_err(|_| format!("'{arg}' is not a number")),
+            None => Ok(default),
+        }
+    };
+    let size = number(2, 512)?.unsigned_abs();
+    let scale = number(3, 1)?.unsigned_abs().max(1);
+    let center = [number(4, 0)?, number(5, 0)?];
+
+    let preview = Preview::centered(center, [size, size], scale, &config);
+    let pixels = preview.render_config(&config);
+    preview.write_png(&pixels, &args[1])?;
+    println!("wrote {}", args[1]);
+    Ok(())
+}
@@ -0,0 +1,318 @@
+//! Reading and writing chunks of Anvil worlds.
+//! Chunks are converted to and from NBT here instead of using the parser of valence, as it keeps its own cache of the
+//! region files, which would go out of date once chunks are written to them.
+
+use std::{collections::HashMap, fs, path::PathBuf, sync::Mutex};
+
+use valence::{
+    anvil::RegionFolder,
+    nbt::{Compound, List, Value},
+    prelude::*,
+};
+
+use crate::{structure::palette_entry, ChunkHeightmap};
+
+/// Data version of 1.20.1, the version valence supports
+const DATA_VERSION: i32 = 3465;
+
+/// Existing Anvil world that chunks are loaded from before falling back to generating them
+pub(crate) struct AnvilSource {
+    region: Mutex<RegionFolder>,
+    path: PathBuf,
+    save: bool,
+    /// Section at the bottom of the layer, the sections of the world are placed by their absolute `Y`
+    min_section: i32,
+    biome_ids: HashMap<String, BiomeId>,
+    biome_names: HashMap<BiomeId, String>,
+}
+
+impl AnvilSource {
+    /// `path` is the folder of a dimension, the one containing the `region` folder. `min_y` is the bottom of the layer
+    pub fn new(path: PathBuf, save: bool, min_y: i32, biomes: &BiomeRegistry) -> Self {
+        let path = path.join("region");
+        if save {
+            if let Err(e) = fs::create_dir_all(&path) {
+                tracing::error!("could not create {path:?}: {e}");
+            }
+        }
+        let biome_names = biomes
+            .iter()
+            .map(|(id, name, _)| (id, name.as_str().to_string()))
+            .collect::<HashMap<_, _>>();
+        Self {
+            region: Mutex::new(RegionFolder::new(path.clone())),
+            path,
+            save,
+            min_section: min_y.div_euclid(16),
+            biome_ids: biome_names
+                .iter()
+                .map(|(id, name)| (name.clone(), *id))
+                .collect(),
+            biome_names,
+        }
+    }
+
+    /// If chunks should be written back to the world
+    pub fn saves(&self) -> bool {
+        self.save
+    }
+
+    /// Loads a chunk if it exists in the world, chunks that fail to parse are logged and treated as missing
+    pub fn load(&self, pos: ChunkPos, height: u32) -> Option<(UnloadedChunk, ChunkHeightmap)> {
+        let raw = self.region.lock().unwrap().get_chunk(pos.x, pos.z);
+        let result = match raw {
+            Ok(Some(raw)) => self.chunk_from_nbt(raw.data, height).map(Some),
+            Ok(None) => Ok(None),
+            Err(e) => Err(e.to_string()),
+        };
+        match result {
+            Ok(chunk) => chunk.map(|chunk| {
+                let heightmap = ChunkHeightmap::from_chunk(&chunk);
+                (chunk, heightmap)
+            }),
+            Err(e) => {
+                tracing::warn!(
+                    "could not load chunk at {} {}, generating it instead: {e}",
+                    pos.x,
+                    pos.z
+                );
+                None
+            }
+        }
+    }
+
+    /// Writes a chunk to its region file, errors are logged
+    pub fn save(&self, pos: ChunkPos, chunk: &impl Chunk) {
+        let nbt = self.chunk_to_nbt(pos, chunk);
+        if let Err(e) = self.region.lock().unwrap().set_chunk(pos.x, pos.z, &nbt) {
+            tracing::error!("could not save chunk at {} {}: {e}", pos.x, pos.z);
+        }
+    }
+
+    /// Closes the open region files, so everything written so far is on disk
+    pub fn flush(&self) {
+        *self.region.lock().unwrap() = RegionFolder::new(self.path.clone());
+    }
+
+    fn chunk_from_nbt(&self, mut nbt: Compound, height: u32) -> Result<UnloadedChunk, String> {
+        let mut chunk = UnloadedChunk::with_height(height);
+        let sections = match nbt.remove("sections") {
+            Some(Value::List(List::Compound(sections))) => sections,
+            Some(Value::List(List::End)) => vec![],
+            _ => return Err("missing sections".into()),
+        };
+        let min_section = self.min_section;
+        for section in &sections {
+            let sect_y = match section.get("Y") {
+                Some(Value::Byte(y)) => *y as i32 - min_section,
+                _ => return Err("section without Y".into()),
+            };
+            // vanilla saves light in the sections around the chunk, these have no blocks
+            if sect_y < 0 || sect_y as u32 >= height / 16 {
+                continue;
+            }
+            let base_y = sect_y as u32 * 16;
+            if let Some(Value::Compound(states)) = section.get("block_states") {
+                let palette = match states.get("palette") {
+                    Some(Value::List(List::Compound(palette))) => palette
+                        .iter()
+                        .map(|entry| {
+                            palette_entry(entry).map(|block| block.unwrap_or(BlockState::AIR))
+                        })
+                        .collect::<Result<Vec<_>, _>>()?,
+                    _ => return Err("block states without palette".into()),
+                };
+                let indices = read_palette_data(states.get("data"), palette.len(), 4096, 4)?;
+                for (i, index) in indices.into_iter().enumerate() {
+                    let block = *palette.get(index).ok_or("block index outside palette")?;
+                    let (x, y, z) = (i as u32 % 16, i as u32 / 256, i as u32 / 16 % 16);
+                    chunk.set_block_state(x, base_y + y, z, block);
+                }
+            }
+            if let Some(Value::Compound(biomes)) = section.get("biomes") {
+                let palette = match biomes.get("palette") {
+                    Some(Value::List(List::String(palette))) => palette
+                        .iter()
+                        .map(|name| self.biome_ids.get(name).copied().unwrap_or_default())
+                        .collect::<Vec<_>>(),
+                    _ => return Err("biomes without palette".into()),
+                };
+                let indices = read_palette_data(biomes.get("data"), palette.len(), 64, 1)?;
+                for (i, index) in indices.into_iter().enumerate() {
+                    let biome = *palette.get(index).ok_or("biome index outside palette")?;
+                    let (x, y, z) = (i as u32 % 4, i as u32 / 16, i as u32 / 4 % 4);
+                    chunk.set_biome(x, base_y / 4 + y, z, biome);
+                }
+            }
+        }
+        if let Some(Value::List(List::Compound(entities))) = nbt.remove("block_entities") {
+            for mut entity in entities {
+                let (Some(Value::Int(x)), Some(Value::Int(y)), Some(Value::Int(z))) =
+                    (entity.remove("x"), entity.remove("y"), entity.remove("z"))
+                else {
+                    return Err("block entity without position".into());
+                };
+                let y = y - min_section * 16;
+                if y < 0 || y as u32 >= height {
+                    continue;
+                }
+                entity.remove("id");
+                entity.remove("keepPacked");
+                let (x, z) = (x.rem_euclid(16) as u32, z.rem_euclid(16) as u32);
+                chunk.set_block_entity(x, y as u32, z, Some(entity));
+            }
+        }
+        Ok(chunk)
+    }
+
+    fn chunk_to_nbt(&self, pos: ChunkPos, chunk: &impl Chunk) -> Compound {
+        let mut sections = vec![];
+        for sect_y in 0..chunk.height() / 16 {
+            let base_y = sect_y * 16;
+            let mut section = Compound::new();
+            section.insert("Y", Value::Byte((sect_y as i32 + self.min_section) as i8));
+
+            let mut palette = vec![];
+            let mut indices = Vec::with_capacity(4096);
+            for y in 0..16 {
+                for z in 0..16 {
+                    for x in 0..16 {
+                        let block = chunk.block_state(x, base_y + y, z);
+                        indices.push(palette_index(&mut palette, block));
+                    }
+                }
+            }
+            let palette = palette
+                .into_iter()
+                .map(|block: BlockState| {
+                    let mut entry = Compound::new();
+                    entry.insert("Name", format!("minecraft:{}", block.to_kind().to_str()));
+                    let mut props = Compound::new();
+                    for prop in block.to_kind().props() {
+                        if let Some(value) = block.get(*prop) {
+                            props.insert(prop.to_str(), value.to_str().to_string());
+                        }
+                    }
+                    if !props.is_empty() {
+                        entry.insert("Properties", props);
+                    }
+                    entry
+                })
+                .collect::<Vec<_>>();
+            section.insert(
+                "block_states",
+                palette_compound(List::Compound(palette), &indices, 4),
+            );
+
+            let mut palette = vec![];
+            let mut indices = Vec::with_capacity(64);
+            for y in 0..4 {
+                for z in 0..4 {
+                    for x in 0..4 {
+                        let biome = chunk.biome(x, base_y / 4 + y, z);
+                        indices.push(palette_index(&mut palette, biome));
+                    }
+                }
+            }
+            let palette = palette
+                .into_iter()
+                .map(|biome| {
+                    self.biome_names
+                        .get(&biome)
+                        .cloned()
+                        .unwrap_or_else(|| "minecraft:plains".into())
+                })
+                .collect();
+            section.insert(
+                "biomes",
+                palette_compound(List::String(palette), &indices, 1),
+            );
+            sections.push(section);
+        }
+
+        let mut entities = vec![];
+        for y in 0..chunk.height() {
+            for z in 0..16 {
+                for x in 0..16 {
+                    let Some(kind) = chunk.block_state(x, y, z).block_entity_kind() else {
+                        continue;
+                    };
+                    let mut entity = chunk.block_entity(x, y, z).cloned().unwrap_or_default();
+                    entity.insert("id", kind.ident().to_string());
+                    entity.insert("x", pos.x * 16 + x as i32);
+                    entity.insert("y", y as i32 + self.min_section * 16);
+                    entity.insert("z", pos.z * 16 + z as i32);
+                    entities.push(entity);
+                }
+            }
+        }
+
+        let mut nbt = Compound::new();
+        nbt.insert("DataVersion", DATA_VERSION);
+        nbt.insert("xPos", pos.x);
+        nbt.insert("zPos", pos.z);
+        nbt.insert("yPos", self.min_section);
+        nbt.insert("Status", "minecraft:full".to_string());
+        nbt.insert("sections", List::Compound(sections));
+        nbt.insert("block_entities", List::Compound(entities));
+        nbt
+    }
+}
+
+fn palette_index<T: PartialEq>(palette: &mut Vec<T>, value: T) -> usize {
+    match palette.iter().position(|v| *v == value) {
+        Some(index) => index,
+        None => {
+            palette.push(value);
+            palette.len() - 1
+        }
+    }
+}
+
+/// Bits used per index of a palette, the data is left out when there is only one entry
+fn palette_bits(len: usize, min_bits: u32) -> u32 {
+    (usize::BITS - (len.max(1) - 1).leading_zeros()).max(min_bits)
+}
+
+fn palette_compound(palette: List, indices: &[usize], min_bits: u32) -> Compound {
+    let len = palette.len();
+    let mut compound = Compound::new();
+    compound.insert("palette", palette);
+    if len > 1 {
+        let bits = palette_bits(len, min_bits) as usize;
+        // indices do not span multiple longs
+        let data = indices
+            .chunks(64 / bits)
+            .map(|indices| {
+                indices.iter().enumerate().fold(0u64, |long, (i, index)| {
+                    long | ((*index as u64) << (i * bits))
+                }) as i64
+            })
+            .collect::<Vec<_>>();
+        compound.insert("data", Value::LongArray(data));
+    }
+    compound
+}
+
+fn read_palette_data(
+    data: Option<&Value>,
+    palette_len: usize,
+    count: usize,
+    min_bits: u32,
+) -> Result<Vec<usize>, String> {
+    if palette_len <= 1 {
+        return Ok(vec![0; count]);
+    }
+    let Some(Value::LongArray(data)) = data else {
+        return Err("palette without data".into());
+    };
+    let bits = palette_bits(palette_len, min_bits) as usize;
+    let per_long = 64 / bits;
+    if data.len() < count.div_ceil(per_long) {
+        return Err("palette data is too short".into());
+    }
+    let mask = (1u64 << bits) - 1;
+    Ok((0..count)
+        .map(|i| ((data[i / per_long] as u64 >> (i % per_long * bits)) & mask) as usize)
+        .collect())
+}
@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+use valence::prelude::*;
+
+use crate::{config_serde, palette::position_hash};
+
+/// Layers of bedrock at the bottom or top of the chunks, so players can not fall out of the world
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BedrockLayers {
+    /// Defaults to bedrock
+    #[serde(default = "bedrock", with = "config_serde::block")]
+    pub block: BlockState,
+    /// Layers of the pattern, kept between 1 and 5
+    pub thickness: u32,
+    /// If set, only the outermost layer is full and each layer further in has fewer blocks, like vanilla
+    #[serde(default)]
+    pub noisy: bool,
+}
+
+fn bedrock() -> BlockState {
+    BlockState::BEDROCK
+}
+
+impl Default for BedrockLayers {
+    fn default() -> Self {
+        Self {
+            block: BlockState::BEDROCK,
+            thickness: 5,
+            noisy: true,
+        }
+    }
+}
+
+impl BedrockLayers {
+    /// Places the layers in a column at `x` and `z`, from the bottom up or from the top down if `ceiling` is set
+    pub(crate) fn place(
+        &self,
+        ceiling: bool,
+        x: i32,
+        z: i32,
+        seed: u64,
+        column: &mut [BlockState],
+    ) {
+        let thickness = self.thickness.clamp(1, 5).min(column.len() as u32);
+        for layer in 0..thickness {
+            let y = match ceiling {
+                true => column.len() - 1 - layer as usize,
+                false => layer as usize,
+            };
+            // the chance of a block goes from 1 in the outermost layer down to 1 / thickness
+            let placed = !self.noisy
+                || position_hash([x, y as i32, z], seed) % thickness as u64 >= layer as u64;
+            if placed {
+                column[y] = self.block;
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! Generates chunks into an Anvil world without running a server.  
+//! Usage: `pregen <config.yml> <world folder> <radius> [center x] [center z]`, the radius and center are in chunks
+
+use std::{env, fs, process::ExitCode};
+
+use valence::{
+    log::LogPlugin,
+    prelude::*,
+    registry::{biome::BiomePlugin, RegistryPlugin},
+};
+use valence_terrain::{pregen::pregenerate_world, PregenShape, TerrainGenConfig};
+
+fn main() -> ExitCode {
+    let args = env::args().collect::<Vec<_>>();
+    if args.len() < 4 {
+        eprintln!("usage: pregen <config.yml> <world folder> <radius> [center x] [center z]");
+        return ExitCode::FAILURE;
+    }
+    match run(&args[1..]) {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("{e}");
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn run(args: &[String]) -> Result<(), String> {
+    let content =
+        fs::read_to_string(&args[0]).map_err(|e| format!("could not read {}: {e}", args[0]))?;
+    let mut config = serde_yml::from_str::<TerrainGenConfig>(&content)
+        .map_err(|e| format!("invalid config: {e}"))?;
+    config.anvil = Some(args[1].clone().into());
+    let number = |i: usize| -> Result<i32, String> {
+        match args.get(i) {
+            Some(arg) => arg.parse().map_err(|_| format!("'{arg}' is not a number")),
+            None => Ok(0),
+        }
+    };
+    let radius = number(2)?.unsigned_abs();
+    let center = ChunkPos::new(number(3)?, number(4)?);
+
+    // the registries are filled with the vanilla biomes on startup, no server is needed for that
+    let mut app = App::new();
+    app.add_plugins((LogPlugin::default(), RegistryPlugin, BiomePlugin));
+    app.update();
+    let biomes = app.world.resource::<BiomeRegistry>();
+
+    let mut last_percent = None;
+    pregenerate_world(
+        config,
+        biomes,
+        center,
+        radius,
+        PregenShape::Square,
+        |done, total| {
+            let percent = done * 100 / total;
+            if last_percent != Some(percent) {
+                last_percent = Some(percent);
+                println!("{percent}% ({done}/{total} chunks)");
+            }
+        },
+    )
+}
@@ -0,0 +1,277 @@
+use noise::NoiseFn;
+use serde::{Deserialize, Serialize};
+use valence::prelude::*;
+
+use crate::{
+    block_from_str, config_serde, layers_from_strs,
+    noise_builder::{seed_tree, DynNoise, NoiseBuilder},
+    TerrainConfigError,
+};
+
+/// Decides the biome of each column
+#[derive(Serialize, Deserialize, Clone)]
+#[serde(rename_all = "snake_case")]
+pub enum BiomeSource {
+    /// Every column uses the same biome
+    Fixed(String),
+    /// Compares a single noise to a list of thresholds
+    Noise(BiomeNoise),
+    /// Picks biomes from temperature, humidity and continentalness, similar to vanilla
+    MultiNoise(ClimateNoise),
+}
+
+impl BiomeSource {
+    /// Applies a world seed to the noises, see [TerrainGenConfig::seed](crate::TerrainGenConfig::seed).
+    /// The climate noises use `salt` to `salt + 2`
+    pub(crate) fn seeded(self, seed: Option<u64>, salt: u64) -> Self {
+        match self {
+            Self::Fixed(_) => self,
+            Self::Noise(noise) => Self::Noise(BiomeNoise {
+                noise: seed_tree(seed, noise.noise, salt),
+                biomes: noise.biomes,
+            }),
+            Self::MultiNoise(climate) => Self::MultiNoise(ClimateNoise {
+                temperature: seed_tree(seed, climate.temperature, salt),
+                humidity: seed_tree(seed, climate.humidity, salt + 1),
+                continentalness: seed_tree(seed, climate.continentalness, salt + 2),
+                biomes: climate.biomes,
+            }),
+        }
+    }
+}
+
+/// Picks the biome of each column by comparing a noise value to a list of thresholds
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BiomeNoise {
+    #[serde(with = "config_serde::noise")]
+    pub noise: NoiseBuilder,
+    /// Upper noise bounds and the biome used below them, in any order as they are sorted by bound when built.  
+    /// The biome with the highest bound is also used for values above every bound
+    pub biomes: Vec<(f64, String)>,
+}
+
+/// Climate noises and the ranges of climate each biome is placed in
+#[derive(Serialize, Deserialize, Clone)]
+pub struct ClimateNoise {
+    #[serde(with = "config_serde::noise")]
+    pub temperature: NoiseBuilder,
+    #[serde(with = "config_serde::noise")]
+    pub humidity: NoiseBuilder,
+    #[serde(with = "config_serde::noise")]
+    pub continentalness: NoiseBuilder,
+    /// If the climate of a column is not inside any biome, the closest biome is used
+    pub biomes: Vec<ClimateBiome>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ClimateBiome {
+    pub biome: String,
+    pub temperature: (f64, f64),
+    pub humidity: (f64, f64),
+    pub continentalness: (f64, f64),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SerializableBiomeSource {
+    Fixed(String),
+    Noise(SerializableBiomeNoise),
+    MultiNoise(SerializableClimateNoise),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SerializableBiomeNoise {
+    pub noise: String,
+    pub biomes: Vec<(f64, String)>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SerializableClimateNoise {
+    pub temperature: String,
+    pub humidity: String,
+    pub continentalness: String,
+    pub biomes: Vec<ClimateBiome>,
+}
+
+impl SerializableBiomeSource {
+    pub fn parse(self) -> Result<BiomeSource, TerrainConfigError> {
+        self.parse_with(None)
+    }
+
+    /// Parses the noises with the seed of the config for their `$seed` placeholders, see [NoiseBuilder::parse_seeded]
+    pub(crate) fn parse_with(self, seed: Option<u64>) -> Result<BiomeSource, TerrainConfigError> {
+        match self {
+            Self::Fixed(biome) => Ok(BiomeSource::Fixed(biome)),
+            Self::Noise(noise) => {
+                if noise.biomes.is_empty() {
+                    return Err(TerrainConfigError::Empty("biome noise"));
+                }
+                Ok(BiomeSource::Noise(BiomeNoise {
+                    noise: NoiseBuilder::parse_with(&noise.noise, seed)
+                        .map_err(TerrainConfigError::noise("biome noise"))?,
+                    biomes: noise.biomes,
+                }))
+            }
+            Self::MultiNoise(climate) => {
+                if climate.biomes.is_empty() {
+                    return Err(TerrainConfigError::Empty("multi noise"));
+                }
+                Ok(BiomeSource::MultiNoise(ClimateNoise {
+                    temperature: NoiseBuilder::parse_with(&climate.temperature, seed)
+                        .map_err(TerrainConfigError::noise("temperature"))?,
+                    humidity: NoiseBuilder::parse_with(&climate.humidity, seed)
+                        .map_err(TerrainConfigError::noise("humidity"))?,
+                    continentalness: NoiseBuilder::parse_with(&climate.continentalness, seed)
+                        .map_err(TerrainConfigError::noise("continentalness"))?,
+                    biomes: climate.biomes,
+                }))
+            }
+        }
+    }
+}
+
+/// Overrides the base block and surface layers in a biome, fields that are not set use the values of the config
+#[derive(Serialize, Deserialize, Clone)]
+pub struct BiomeSurface {
+    pub biome: String,
+    #[serde(default, with = "config_serde::option_block")]
+    pub block: Option<BlockState>,
+    #[serde(default, with = "config_serde::option_layers")]
+    pub surface_layers: Option<Vec<(u16, BlockState)>>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SerializableBiomeSurface {
+    pub biome: String,
+    #[serde(default)]
+    pub block: Option<String>,
+    #[serde(default)]
+    pub surface_layers: Option<Vec<(u16, String)>>,
+}
+
+impl SerializableBiomeSurface {
+    pub fn parse(self) -> Result<BiomeSurface, TerrainConfigError> {
+        Ok(BiomeSurface {
+            biome: self.biome,
+            block: match self.block {
+                Some(block) => Some(block_from_str(&block)?),
+                None => None,
+            },
+            surface_layers: match self.surface_layers {
+                Some(layers) => Some(layers_from_strs(layers)?),
+                None => None,
+            },
+        })
+    }
+}
+
+/// Built [BiomeSource] with biomes looked up in the registry
+pub(crate) enum BiomePicker {
+    Fixed(BiomeId),
+    Noise {
+        noise: DynNoise,
+        biomes: Vec<(f64, BiomeId)>,
+    },
+    MultiNoise {
+        temperature: DynNoise,
+        humidity: DynNoise,
+        continentalness: DynNoise,
+        biomes: Vec<(ClimateBiome, BiomeId)>,
+    },
+}
+
+impl BiomePicker {
+    pub fn new(source: BiomeSource, registry: &BiomeRegistry) -> Self {
+        match source {
+            BiomeSource::Fixed(name) => Self::Fixed(biome_id(registry, &name)),
+            BiomeSource::Noise(config) => {
+                let mut biomes = config
+                    .biomes
+                    .into_iter()
+                    .map(|(bound, name)| (bound, biome_id(registry, &name)))
+                    .collect::<Vec<_>>();
+                // the first bound above the noise is picked
+                biomes.sort_by(|(a, _), (b, _)| a.total_cmp(b));
+                Self::Noise {
+                    noise: config.noise.build2(),
+                    biomes,
+                }
+            }
+            BiomeSource::MultiNoise(config) => Self::MultiNoise {
+                temperature: config.temperature.build2(),
+                humidity: config.humidity.build2(),
+                continentalness: config.continentalness.build2(),
+                biomes: config
+                    .biomes
+                    .into_iter()
+                    .map(|biome| {
+                        let id = biome_id(registry, &biome.biome);
+                        (biome, id)
+                    })
+                    .collect(),
+            },
+        }
+    }
+
+    /// Biome at a world position
+    pub fn get(&self, x: f64, z: f64) -> BiomeId {
+        match self {
+            Self::Fixed(biome) => *biome,
+            Self::Noise { noise, biomes } => {
+                let value = noise.get([x, z]);
+                biomes
+                    .iter()
+                    .find(|(bound, _)| value < *bound)
+                    .or(biomes.last())
+                    .map_or(BiomeId::default(), |(_, biome)| *biome)
+            }
+            Self::MultiNoise {
+                temperature,
+                humidity,
+                continentalness,
+                biomes,
+            } => {
+                let climate = [
+                    temperature.get([x, z]),
+                    humidity.get([x, z]),
+                    continentalness.get([x, z]),
+                ];
+                biomes
+                    .iter()
+                    .min_by(|(a, _), (b, _)| {
+                        climate_distance(a, climate).total_cmp(&climate_distance(b, climate))
+                    })
+                    .map_or(BiomeId::default(), |(_, biome)| *biome)
+            }
+        }
+    }
+}
+
+/// Squared distance from a climate to the ranges of a biome, 0 if inside all ranges
+fn climate_distance(biome: &ClimateBiome, climate: [f64; 3]) -> f64 {
+    [biome.temperature, biome.humidity, biome.continentalness]
+        .iter()
+        .zip(climate)
+        .map(|((min, max), value)| {
+            let dist = (min - value).max(value - max).max(0.0);
+            dist * dist
+        })
+        .sum()
+}
+
+/// Looks up a biome by name, the `minecraft` namespace may be left out.  
+/// Unknown biomes are logged and replaced by the default biome
+pub(crate) fn biome_id(registry: &BiomeRegistry, name: &str) -> BiomeId {
+    let name = if name.contains(':') {
+        name.to_string()
+    } else {
+        format!("minecraft:{name}")
+    };
+    match registry.iter().find(|(_, ident, _)| ident.as_str() == name) {
+        Some((id, _, _)) => id,
+        None => {
+            tracing::error!("unknown biome '{name}', using default biome");
+            BiomeId::default()
+        }
+    }
+}
@@ -0,0 +1,117 @@
+use serde::{Deserialize, Serialize};
+use valence::prelude::*;
+
+use crate::ChunkHeightmap;
+
+/// Sea level of [BorderFill::Ocean] in configs without a `sea_level`, the same as vanilla
+const OCEAN_SEA_LEVEL: i32 = 63;
+
+/// Blocks of water above the floor of [BorderFill::Ocean]
+const OCEAN_DEPTH: i32 = 24;
+
+/// Edge of a finite world, chunks outside of it are filled with `outside` instead of terrain
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct WorldBorder {
+    pub shape: BorderShape,
+    #[serde(default)]
+    pub outside: BorderFill,
+}
+
+/// Chunks inside a [WorldBorder]
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum BorderShape {
+    /// Chunks whose distance to the `center` chunk is at most `radius` chunks
+    Circle { center: [i32; 2], radius: u32 },
+    /// Chunks from `min` to `max`, inclusive
+    Rect { min: [i32; 2], max: [i32; 2] },
+}
+
+/// What chunks outside of a [WorldBorder] contain
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum BorderFill {
+    /// Chunks outside are not generated at all, and requests for them fail
+    #[default]
+    Void,
+    /// Empty chunks with a wall of barriers along the border, so players can not leave
+    Barrier,
+    /// The base block with the fluid above it up to the sea level, like an endless ocean around the world
+    Ocean,
+}
+
+impl WorldBorder {
+    pub fn contains(&self, pos: ChunkPos) -> bool {
+        match self.shape {
+            BorderShape::Circle {
+                center: [x, z],
+                radius,
+            } => {
+                let (dx, dz) = ((pos.x - x) as i64, (pos.z - z) as i64);
+                dx * dx + dz * dz <= radius as i64 * radius as i64
+            }
+            BorderShape::Rect { min, max } => {
+                (min[0]..=max[0]).contains(&pos.x) && (min[1]..=max[1]).contains(&pos.z)
+            }
+        }
+    }
+
+    /// If the column at block `x` and `z` is inside the border
+    pub fn contains_block(&self, x: i32, z: i32) -> bool {
+        self.contains(ChunkPos::new(x.div_euclid(16), z.div_euclid(16)))
+    }
+
+    /// If the chunk is outside of a border with [BorderFill::Void], so it is never generated
+    pub(crate) fn rejects(&self, pos: ChunkPos) -> bool {
+        self.outside == BorderFill::Void && !self.contains(pos)
+    }
+
+    /// Chunk outside of the border filled like `outside`, `block` and `fluid` are the ones of the config
+    pub(crate) fn fill(
+        &self,
+        pos: ChunkPos,
+        height: u32,
+        min_y: i32,
+        block: BlockState,
+        fluid: BlockState,
+        sea_level: Option<i32>,
+    ) -> (UnloadedChunk, ChunkHeightmap) {
+        let mut chunk = UnloadedChunk::with_height(height);
+        let mut heightmap = ChunkHeightmap::default();
+        match self.outside {
+            BorderFill::Void => {}
+            BorderFill::Barrier => {
+                for x in 0..16 {
+                    for z in 0..16 {
+                        let (world_x, world_z) = (pos.x * 16 + x as i32, pos.z * 16 + z as i32);
+                        let wall = [(-1, 0), (1, 0), (0, -1), (0, 1)]
+                            .iter()
+                            .any(|(dx, dz)| self.contains_block(world_x + dx, world_z + dz));
+                        if !wall {
+                            continue;
+                        }
+                        for y in 0..height {
+                            chunk.set_block_state(x, y, z, BlockState::BARRIER);
+                        }
+                        heightmap.set(x, z, height);
+                    }
+                }
+            }
+            BorderFill::Ocean => {
+                let sea_level =
+                    (sea_level.unwrap_or(OCEAN_SEA_LEVEL) - min_y).clamp(0, height as i32);
+                let floor = (sea_level - OCEAN_DEPTH).max(0);
+                for x in 0..16 {
+                    for z in 0..16 {
+                        for y in 0..sea_level {
+                            let block = if y < floor { block } else { fluid };
+                            chunk.set_block_state(x, y as u32, z, block);
+                        }
+                        heightmap.set(x, z, sea_level as u32);
+                    }
+                }
+            }
+        }
+        (chunk, heightmap)
+    }
+}
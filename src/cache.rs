@@ -0,0 +1,296 @@
+use std::{
+    collections::{BTreeMap, HashMap},
+    mem,
+};
+
+use serde::{Deserialize, Serialize};
+use valence::{nbt::Compound, prelude::*};
+
+use crate::ChunkHeightmap;
+
+/// Keeps recently unloaded chunks that were not changed, so chunks that come back into view are inserted again instead
+/// of generated. Chunks are evicted once either limit is reached, the ones unloaded the longest ago first
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct ChunkCacheConfig {
+    /// Max chunks in the cache
+    pub chunks: usize,
+    /// Max bytes of the chunks in the cache, unbounded if not set. Chunks that are not compressed are counted as if
+    /// every block and biome took its full size
+    pub bytes: Option<usize>,
+    /// Compression of the blocks of cached chunks, done by the workers when the chunks are unloaded
+    pub compression: CacheCompression,
+}
+
+impl Default for ChunkCacheConfig {
+    fn default() -> Self {
+        Self {
+            chunks: 1024,
+            bytes: None,
+            compression: CacheCompression::None,
+        }
+    }
+}
+
+/// Compression of cached chunks, each one needs the feature of the same name.
+/// Compressed chunks take a fraction of the memory, at the cost of compressing them on unload and decompressing them
+/// when they are inserted again
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum CacheCompression {
+    #[default]
+    None,
+    /// Fast, but compresses less than zstd
+    #[cfg(feature = "lz4")]
+    Lz4,
+    #[cfg(feature = "zstd")]
+    Zstd,
+}
+
+#[cfg(feature = "zstd")]
+const ZSTD_LEVEL: i32 = 3;
+
+enum CachedBlocks {
+    Chunk(UnloadedChunk),
+    #[cfg(any(feature = "lz4", feature = "zstd"))]
+    Compressed {
+        compression: CacheCompression,
+        height: u32,
+        /// Raw block states of the chunk in y, z, x order
+        blocks: Vec<u8>,
+        biomes: Vec<BiomeId>,
+        block_entities: Vec<([u32; 3], Compound)>,
+    },
+}
+
+/// Unloaded chunk ready to be cached, made by the workers
+pub(crate) struct CachedChunk {
+    blocks: CachedBlocks,
+    heightmap: ChunkHeightmap,
+    /// [block_hash](crate::worker::block_hash) of the chunk, it is still the generated one
+    hash: u64,
+    bytes: usize,
+}
+
+impl CachedChunk {
+    pub fn new(
+        chunk: UnloadedChunk,
+        heightmap: ChunkHeightmap,
+        hash: u64,
+        compression: CacheCompression,
+    ) -> Self {
+        let blocks = compress(chunk, compression);
+        let bytes = match &blocks {
+            CachedBlocks::Chunk(chunk) => {
+                let height = chunk.height() as usize;
+                height * 16 * 16 * mem::size_of::<BlockState>()
+                    + height / 4 * 4 * 4 * mem::size_of::<BiomeId>()
+            }
+            #[cfg(any(feature = "lz4", feature = "zstd"))]
+            CachedBlocks::Compressed {
+                blocks,
+                biomes,
+                block_entities,
+                ..
+            } => {
+                blocks.len()
+                    + biomes.len() * mem::size_of::<BiomeId>()
+                    + block_entities.len() * mem::size_of::<([u32; 3], Compound)>()
+            }
+        };
+        Self {
+            blocks,
+            heightmap,
+            hash,
+            bytes: bytes + mem::size_of::<ChunkHeightmap>(),
+        }
+    }
+
+    /// The chunk with its heightmap and hash, None if it could not be decompressed
+    fn into_chunk(self) -> Option<(UnloadedChunk, ChunkHeightmap, u64)> {
+        let chunk = match self.blocks {
+            CachedBlocks::Chunk(chunk) => chunk,
+            #[cfg(any(feature = "lz4", feature = "zstd"))]
+            CachedBlocks::Compressed {
+                compression,
+                height,
+                blocks,
+                biomes,
+                block_entities,
+            } => decompress(compression, height, &blocks, &biomes, block_entities)?,
+        };
+        Some((chunk, self.heightmap, self.hash))
+    }
+}
+
+#[cfg(not(any(feature = "lz4", feature = "zstd")))]
+fn compress(chunk: UnloadedChunk, _: CacheCompression) -> CachedBlocks {
+    CachedBlocks::Chunk(chunk)
+}
+
+#[cfg(any(feature = "lz4", feature = "zstd"))]
+fn compress(chunk: UnloadedChunk, compression: CacheCompression) -> CachedBlocks {
+    if compression == CacheCompression::None {
+        return CachedBlocks::Chunk(chunk);
+    }
+    let height = chunk.height();
+    let mut raw = Vec::with_capacity(height as usize * 16 * 16 * 2);
+    let mut block_entities = vec![];
+    for y in 0..height {
+        for z in 0..16 {
+            for x in 0..16 {
+                let block = chunk.block_state(x, y, z);
+                raw.extend(block.to_raw().to_le_bytes());
+                if block.block_entity_kind().is_some() {
+                    if let Some(entity) = chunk.block_entity(x, y, z) {
+                        block_entities.push(([x, y, z], entity.clone()));
+                    }
+                }
+            }
+        }
+    }
+    let mut biomes = vec![];
+    for y in 0..height / 4 {
+        for z in 0..4 {
+            for x in 0..4 {
+                biomes.push(chunk.biome(x, y, z));
+            }
+        }
+    }
+    let blocks = match compression {
+        CacheCompression::None => unreachable!(),
+        #[cfg(feature = "lz4")]
+        CacheCompression::Lz4 => lz4_flex::compress_prepend_size(&raw),
+        #[cfg(feature = "zstd")]
+        CacheCompression::Zstd => match zstd::encode_all(raw.as_slice(), ZSTD_LEVEL) {
+            Ok(blocks) => blocks,
+            Err(e) => {
+                tracing::error!("could not compress cached chunk: {e}");
+                return CachedBlocks::Chunk(chunk);
+            }
+        },
+    };
+    CachedBlocks::Compressed {
+        compression,
+        height,
+        blocks,
+        biomes,
+        block_entities,
+    }
+}
+
+#[cfg(any(feature = "lz4", feature = "zstd"))]
+fn decompress(
+    compression: CacheCompression,
+    height: u32,
+    blocks: &[u8],
+    biomes: &[BiomeId],
+    block_entities: Vec<([u32; 3], Compound)>,
+) -> Option<UnloadedChunk> {
+    let raw: Result<Vec<u8>, String> = match compression {
+        CacheCompression::None => return None,
+        #[cfg(feature = "lz4")]
+        CacheCompression::Lz4 => {
+            lz4_flex::decompress_size_prepended(blocks).map_err(|e| e.to_string())
+        }
+        #[cfg(feature = "zstd")]
+        CacheCompression::Zstd => zstd::decode_all(blocks).map_err(|e| e.to_string()),
+    };
+    let raw = match raw {
+        Ok(raw) => raw,
+        Err(e) => {
+            tracing::error!("could not decompress cached chunk: {e}");
+            return None;
+        }
+    };
+    let mut chunk = UnloadedChunk::with_height(height);
+    let mut raw = raw.chunks_exact(2);
+    for y in 0..height {
+        for z in 0..16 {
+            for x in 0..16 {
+                let block = u16::from_le_bytes(raw.next()?.try_into().ok()?);
+                chunk.set_block_state(x, y, z, BlockState::from_raw(block)?);
+            }
+        }
+    }
+    let mut biomes = biomes.iter();
+    for y in 0..height / 4 {
+        for z in 0..4 {
+            for x in 0..4 {
+                chunk.set_biome(x, y, z, *biomes.next()?);
+            }
+        }
+    }
+    for ([x, y, z], entity) in block_entities {
+        chunk.set_block_entity(x, y, z, Some(entity));
+    }
+    Some(chunk)
+}
+
+pub(crate) struct ChunkCache {
+    config: ChunkCacheConfig,
+    /// Cached chunks with when they were cached
+    chunks: HashMap<ChunkPos, (CachedChunk, u64)>,
+    /// Cached chunks by when they were cached
+    order: BTreeMap<u64, ChunkPos>,
+    next_stamp: u64,
+    bytes: usize,
+}
+
+impl ChunkCache {
+    pub fn new(config: ChunkCacheConfig) -> Self {
+        Self {
+            config,
+            chunks: HashMap::new(),
+            order: BTreeMap::new(),
+            next_stamp: 0,
+            bytes: 0,
+        }
+    }
+
+    /// Compression the workers should use for the chunks sent to the cache
+    pub fn compression(&self) -> CacheCompression {
+        self.config.compression
+    }
+
+    pub fn insert(&mut self, pos: ChunkPos, chunk: CachedChunk) {
+        self.remove(pos);
+        let bytes = chunk.bytes;
+        if self.config.chunks == 0 || self.config.bytes.is_some_and(|max| bytes > max) {
+            return;
+        }
+        while self.chunks.len() >= self.config.chunks
+            || self
+                .config
+                .bytes
+                .is_some_and(|max| self.bytes + bytes > max)
+        {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.remove(oldest);
+        }
+        let stamp = self.next_stamp;
+        self.next_stamp += 1;
+        self.order.insert(stamp, pos);
+        self.bytes += bytes;
+        self.chunks.insert(pos, (chunk, stamp));
+    }
+
+    /// Removes a chunk from the cache, with its heightmap and hash
+    pub fn take(&mut self, pos: ChunkPos) -> Option<(UnloadedChunk, ChunkHeightmap, u64)> {
+        self.remove(pos)?.into_chunk()
+    }
+
+    pub fn remove(&mut self, pos: ChunkPos) -> Option<CachedChunk> {
+        let (cached, stamp) = self.chunks.remove(&pos)?;
+        self.order.remove(&stamp);
+        self.bytes -= cached.bytes;
+        Some(cached)
+    }
+
+    /// Bytes of the cached chunks, see [ChunkCacheConfig::bytes]
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+}
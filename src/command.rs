@@ -0,0 +1,98 @@
+//! `/terrain eval <x> <z>` for debugging configs in game, reports what the [TerrainGenerator] of the layer of the
+//! client computes for a column.
+//! Add [TerrainCommandPlugin] to the app and give clients the `valence_terrain.command.terrain` scope to use it
+
+use valence::{
+    command::{handler::CommandResultEvent, AddCommand},
+    command_macros::Command,
+    prelude::*,
+};
+
+use crate::{block_to_string, worker::SurfaceRule, TerrainGenerator};
+
+/// Registers the `/terrain` command and answers it
+pub struct TerrainCommandPlugin;
+
+impl Plugin for TerrainCommandPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_command::<TerrainCommand>()
+            .add_systems(Update, handle_terrain_command);
+    }
+}
+
+#[derive(Command, Debug, Clone)]
+#[paths("terrain")]
+#[scopes("valence_terrain.command.terrain")]
+pub enum TerrainCommand {
+    /// Raw noise, clamped height, biome and surface rule of a column
+    #[paths = "eval {x} {z}"]
+    Eval { x: i32, z: i32 },
+}
+
+fn handle_terrain_command(
+    mut events: EventReader<CommandResultEvent<TerrainCommand>>,
+    mut clients: Query<(&mut Client, &VisibleChunkLayer)>,
+    layers: Query<&TerrainGenerator>,
+    biomes: Res<BiomeRegistry>,
+) {
+    for event in events.read() {
+        let Ok((mut client, visible_layer)) = clients.get_mut(event.executor) else {
+            continue;
+        };
+        let Ok(terrain_gen) = layers.get(visible_layer.0) else {
+            client.send_chat_message("there is no terrain generator in this layer");
+            continue;
+        };
+        match event.result {
+            TerrainCommand::Eval { x, z } => {
+                for line in eval_column(terrain_gen, &biomes, x, z) {
+                    client.send_chat_message(line);
+                }
+            }
+        }
+    }
+}
+
+/// Lines of the answer to `/terrain eval`
+fn eval_column(
+    terrain_gen: &TerrainGenerator,
+    biomes: &BiomeRegistry,
+    x: i32,
+    z: i32,
+) -> Vec<String> {
+    let mut lines = vec![
+        format!("column {x} {z}"),
+        format!("noise: {}", terrain_gen.noise_at(x, z)),
+        format!("height: {}", terrain_gen.height_at(x, z)),
+    ];
+    // biomes and surface rules are only known once the workers are started
+    let Some(state) = terrain_gen.worker_state() else {
+        lines.push("workers are not started yet".into());
+        return lines;
+    };
+    let biome = state.biome_at(x, z);
+    lines.push(match biome {
+        Some(biome) => match biomes.iter().find(|(id, _, _)| *id == biome) {
+            Some((_, name, _)) => format!("biome: {name}"),
+            None => format!("biome: {biome:?}"),
+        },
+        None => "biome: default".into(),
+    });
+    lines.push(format!(
+        "surface: {}",
+        describe_rule(state.surface_rule(biome))
+    ));
+    lines
+}
+
+/// Surface layers from the top down followed by the base block, like `grass_block x1, dirt x2, stone`
+fn describe_rule(rule: &SurfaceRule) -> String {
+    let mut parts = rule
+        .surface_layers
+        .iter()
+        .rev()
+        .map(|(amount, block)| format!("{} x{amount}", block_to_string(*block)))
+        .collect::<Vec<_>>();
+    parts.push(block_to_string(rule.block));
+    parts.join(", ")
+}
@@ -0,0 +1,235 @@
+use std::path::PathBuf;
+
+use valence::prelude::*;
+
+use crate::{
+    bedrock::BedrockLayers,
+    biome::{BiomeSource, BiomeSurface},
+    border::WorldBorder,
+    cache::ChunkCacheConfig,
+    decoration::Decoration,
+    islands::FloatingIslands,
+    noise_builder::NoiseBuilder,
+    ore::OreConfig,
+    palette::BlockPalette,
+    platform::SpawnPlatform,
+    shaper::TerrainShaper,
+    strata::Stratum,
+    structure::StructureConfig,
+    QueueOverflow, SpawnChunks, TerrainConfigError, TerrainGenConfig, TerrainMode,
+};
+
+/// Builds a [TerrainGenConfig] from its [default](Default) values, made with [TerrainGenConfig::builder].  
+/// Methods for lists like [ore](Self::ore) add one entry each time they are called
+#[derive(Clone, Default)]
+pub struct TerrainGenConfigBuilder {
+    config: TerrainGenConfig,
+    /// If the default surface layers were replaced by [surface_layer](Self::surface_layer)
+    layers_set: bool,
+}
+
+impl TerrainGenConfig {
+    pub fn builder() -> TerrainGenConfigBuilder {
+        TerrainGenConfigBuilder::default()
+    }
+}
+
+impl TerrainGenConfigBuilder {
+    pub fn mode(mut self, mode: TerrainMode) -> Self {
+        self.config.mode = mode;
+        self
+    }
+
+    /// The block solid parts of the terrain are filled with, see [TerrainGenConfig::block]
+    pub fn base_block(mut self, block: BlockState) -> Self {
+        self.config.block = block;
+        self
+    }
+
+    /// Adds `count` blocks of `block` on top of the previous surface layers, the first call replaces the default grass
+    pub fn surface_layer(mut self, count: u16, block: BlockState) -> Self {
+        if !self.layers_set {
+            self.config.surface_layers.clear();
+            self.layers_set = true;
+        }
+        // layers are stored from the bottom up, so new layers go on top
+        self.config.surface_layers.push((count, block));
+        self
+    }
+
+    /// Removes the surface layers, so the base block reaches the surface
+    pub fn no_surface_layers(mut self) -> Self {
+        self.config.surface_layers.clear();
+        self.layers_set = true;
+        self
+    }
+
+    pub fn noise(mut self, noise: NoiseBuilder) -> Self {
+        self.config.noise = noise;
+        self
+    }
+
+    /// Uses the shaper instead of the noise, see [TerrainShaper]
+    pub fn shaper(mut self, shaper: TerrainShaper) -> Self {
+        self.config.shaper = Some(shaper);
+        self
+    }
+
+    pub fn density(mut self, density: NoiseBuilder) -> Self {
+        self.config.density = Some(density);
+        self
+    }
+
+    pub fn height(mut self, height: u32) -> Self {
+        self.config.height = Some(height);
+        self
+    }
+
+    pub fn min_y(mut self, min_y: i32) -> Self {
+        self.config.min_y = Some(min_y);
+        self
+    }
+
+    pub fn surface_on_all_exposed(mut self, all_exposed: bool) -> Self {
+        self.config.surface_on_all_exposed = all_exposed;
+        self
+    }
+
+    pub fn biomes(mut self, biomes: BiomeSource) -> Self {
+        self.config.biomes = Some(biomes);
+        self
+    }
+
+    pub fn biome_surface(mut self, surface: BiomeSurface) -> Self {
+        self.config.biome_surfaces.push(surface);
+        self
+    }
+
+    pub fn sea_level(mut self, sea_level: i32) -> Self {
+        self.config.sea_level = Some(sea_level);
+        self
+    }
+
+    pub fn fluid(mut self, fluid: BlockState) -> Self {
+        self.config.fluid = fluid;
+        self
+    }
+
+    pub fn stratum(mut self, stratum: Stratum) -> Self {
+        self.config.strata.push(stratum);
+        self
+    }
+
+    pub fn palette(mut self, palette: BlockPalette) -> Self {
+        self.config.palettes.push(palette);
+        self
+    }
+
+    pub fn bedrock_floor(mut self, bedrock: BedrockLayers) -> Self {
+        self.config.bedrock_floor = Some(bedrock);
+        self
+    }
+
+    pub fn bedrock_ceiling(mut self, bedrock: BedrockLayers) -> Self {
+        self.config.bedrock_ceiling = Some(bedrock);
+        self
+    }
+
+    pub fn ore(mut self, ore: OreConfig) -> Self {
+        self.config.ores.push(ore);
+        self
+    }
+
+    pub fn decoration(mut self, decoration: Decoration) -> Self {
+        self.config.decorations.push(decoration);
+        self
+    }
+
+    pub fn structure(mut self, structure: StructureConfig) -> Self {
+        self.config.structures.push(structure);
+        self
+    }
+
+    /// Switches to [Caverns](TerrainMode::Caverns) mode with a ceiling at the heights of the noise
+    pub fn caverns(mut self, ceiling: NoiseBuilder) -> Self {
+        self.config.mode = TerrainMode::Caverns;
+        self.config.cavern_ceiling = ceiling;
+        self
+    }
+
+    /// Switches to [FloatingIslands](TerrainMode::FloatingIslands) mode with islands of this shape
+    pub fn floating_islands(mut self, islands: FloatingIslands) -> Self {
+        self.config.mode = TerrainMode::FloatingIslands;
+        self.config.floating_islands = islands;
+        self
+    }
+
+    pub fn spawn_platform(mut self, platform: SpawnPlatform) -> Self {
+        self.config.spawn_platform = Some(platform);
+        self
+    }
+
+    /// Loads chunks from an Anvil world, and saves generated chunks to it if `save` is set
+    pub fn anvil(mut self, path: impl Into<PathBuf>, save: bool) -> Self {
+        self.config.anvil = Some(path.into());
+        self.config.anvil_save = save;
+        self
+    }
+
+    pub fn queue_capacity(mut self, capacity: usize, overflow: QueueOverflow) -> Self {
+        self.config.queue_capacity = Some(capacity);
+        self.config.queue_overflow = overflow;
+        self
+    }
+
+    /// Keeps up to `chunks` unloaded chunks, see [TerrainGenConfig::chunk_cache]
+    pub fn chunk_cache(mut self, chunks: usize, bytes: Option<usize>) -> Self {
+        self.config.chunk_cache = Some(ChunkCacheConfig {
+            chunks,
+            bytes,
+            ..Default::default()
+        });
+        self
+    }
+
+    pub fn unload_delay(mut self, ticks: u32) -> Self {
+        self.config.unload_delay = ticks;
+        self
+    }
+
+    /// Keeps the chunks within `radius` of the block `x` `z` loaded, see [SpawnChunks]
+    pub fn spawn_chunks(mut self, x: i32, z: i32, radius: u32) -> Self {
+        self.config.spawn_chunks = Some(SpawnChunks {
+            position: [x, z],
+            radius,
+        });
+        self
+    }
+
+    pub fn world_border(mut self, border: WorldBorder) -> Self {
+        self.config.world_border = Some(border);
+        self
+    }
+
+    pub fn noise_cell(mut self, cell: u32) -> Self {
+        self.config.noise_cell = Some(cell);
+        self
+    }
+
+    /// Vanilla superflat code, see [TerrainGenConfig::superflat]
+    pub fn superflat(mut self, code: impl Into<String>) -> Self {
+        self.config.superflat = Some(code.into());
+        self
+    }
+
+    pub fn seed(mut self, seed: u64) -> Self {
+        self.config.seed = Some(seed);
+        self
+    }
+
+    /// The config if it is [valid](TerrainGenConfig::validate)
+    pub fn build(self) -> Result<TerrainGenConfig, TerrainConfigError> {
+        self.config.validate()?;
+        Ok(self.config)
+    }
+}
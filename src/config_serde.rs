@@ -0,0 +1,239 @@
+//! Serde support for the fields of the typed configs, used with `#[serde(with = "...")]`.
+//! Blocks are written by name and noise as an expression in the format of [NoiseBuilder::parse], like in the text
+//! configs. Noise can also be read as the tree written by the derived impls of [NoiseBuilder]
+
+use std::cell::Cell;
+
+use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+use valence::prelude::*;
+
+use crate::{block_from_str, block_to_string, noise_builder::NoiseBuilder};
+
+/// Block written by its name and properties, like `grass_block` or `oak_log[axis=z]`
+struct Block(BlockState);
+
+impl Serialize for Block {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&block_to_string(self.0))
+    }
+}
+
+impl<'de> Deserialize<'de> for Block {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let name = String::deserialize(deserializer)?;
+        block_from_str(&name).map(Block).map_err(D::Error::custom)
+    }
+}
+
+thread_local! {
+    /// Seed of the config being deserialized, the derived impls have no way to pass it to the noise fields
+    static SEED: Cell<Option<u64>> = const { Cell::new(None) };
+}
+
+/// Runs `f` with `$seed` placeholders in the noise replaced by `seed`, see [NoiseBuilder::parse_seeded]
+pub(crate) fn with_seed<T>(seed: Option<u64>, f: impl FnOnce() -> T) -> T {
+    let old = SEED.with(|cell| cell.replace(seed));
+    let result = f();
+    SEED.with(|cell| cell.set(old));
+    result
+}
+
+/// Noise written as an expression
+struct Noise(NoiseBuilder);
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum NoiseRepr {
+    Expression(String),
+    Tree(NoiseBuilder),
+}
+
+impl<'de> Deserialize<'de> for Noise {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        match NoiseRepr::deserialize(deserializer)? {
+            NoiseRepr::Expression(expression) => {
+                NoiseBuilder::parse_with(&expression, SEED.with(Cell::get))
+                    .map(Noise)
+                    .map_err(D::Error::custom)
+            }
+            NoiseRepr::Tree(tree) => Ok(Noise(tree)),
+        }
+    }
+}
+
+pub(crate) mod block {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(block: &BlockState, serializer: S) -> Result<S::Ok, S::Error> {
+        Block(*block).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BlockState, D::Error> {
+        Ok(Block::deserialize(deserializer)?.0)
+    }
+}
+
+pub(crate) mod option_block {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        block: &Option<BlockState>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        block.map(Block).serialize(serializer)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<BlockState>, D::Error> {
+        Ok(Option::<Block>::deserialize(deserializer)?.map(|block| block.0))
+    }
+}
+
+pub(crate) mod blocks {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        blocks: &[BlockState],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(blocks.iter().map(|block| Block(*block)))
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<BlockState>, D::Error> {
+        let blocks = Vec::<Block>::deserialize(deserializer)?;
+        Ok(blocks.into_iter().map(|block| block.0).collect())
+    }
+}
+
+/// Surface layers, `(amount, block)` from the bottom up
+pub(crate) mod layers {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        layers: &[(u16, BlockState)],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            layers
+                .iter()
+                .map(|(amount, block)| (*amount, Block(*block))),
+        )
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<(u16, BlockState)>, D::Error> {
+        let layers = Vec::<(u16, Block)>::deserialize(deserializer)?;
+        Ok(layers
+            .into_iter()
+            .map(|(amount, block)| (amount, block.0))
+            .collect())
+    }
+}
+
+pub(crate) mod option_layers {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        layers: &Option<Vec<(u16, BlockState)>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match layers {
+            Some(layers) => serializer.serialize_some(
+                &layers
+                    .iter()
+                    .map(|(amount, block)| (*amount, Block(*block)))
+                    .collect::<Vec<_>>(),
+            ),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<Vec<(u16, BlockState)>>, D::Error> {
+        let layers = Option::<Vec<(u16, Block)>>::deserialize(deserializer)?;
+        Ok(layers.map(|layers| {
+            layers
+                .into_iter()
+                .map(|(amount, block)| (amount, block.0))
+                .collect()
+        }))
+    }
+}
+
+/// Blocks of a palette, `(weight, block)`
+pub(crate) mod weighted_blocks {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        blocks: &[(f64, BlockState)],
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(
+            blocks
+                .iter()
+                .map(|(weight, block)| (*weight, Block(*block))),
+        )
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Vec<(f64, BlockState)>, D::Error> {
+        let blocks = Vec::<(f64, Block)>::deserialize(deserializer)?;
+        Ok(blocks
+            .into_iter()
+            .map(|(weight, block)| (weight, block.0))
+            .collect())
+    }
+}
+
+/// Expression of the noise, [Custom](NoiseBuilder::Custom) nodes are errors as their functions can not be written
+fn noise_string<E: serde::ser::Error>(noise: &NoiseBuilder) -> Result<String, E> {
+    if noise.has_custom() {
+        return Err(E::custom(
+            "noise with a custom function can not be serialized",
+        ));
+    }
+    Ok(noise.to_config_string())
+}
+
+pub(crate) mod noise {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        noise: &NoiseBuilder,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&noise_string::<S::Error>(noise)?)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<NoiseBuilder, D::Error> {
+        Ok(Noise::deserialize(deserializer)?.0)
+    }
+}
+
+pub(crate) mod option_noise {
+    use super::*;
+
+    pub fn serialize<S: Serializer>(
+        noise: &Option<NoiseBuilder>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        match noise {
+            Some(noise) => serializer.serialize_some(&noise_string::<S::Error>(noise)?),
+            None => serializer.serialize_none(),
+        }
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(
+        deserializer: D,
+    ) -> Result<Option<NoiseBuilder>, D::Error> {
+        Ok(Option::<Noise>::deserialize(deserializer)?.map(|noise| noise.0))
+    }
+}
@@ -0,0 +1,202 @@
+use noise::NoiseFn;
+use rand::{rngs::StdRng, Rng};
+use serde::{Deserialize, Serialize};
+use valence::prelude::*;
+
+use crate::{
+    block_from_str, config_serde,
+    noise_builder::{DynNoise, NoiseBuilder},
+    proto::{Placer, SurfaceInfo},
+    TerrainConfigError,
+};
+
+/// A feature placed on top of the terrain, like trees and flowers
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Decoration {
+    pub feature: Feature,
+    /// Chance of placing the feature on each column
+    pub chance: f64,
+    /// If set, the chance is multiplied by this noise clamped to `0..1`, so features can be grouped in forests or fields
+    #[serde(default, with = "config_serde::option_noise")]
+    pub noise: Option<NoiseBuilder>,
+    /// Blocks the feature can be placed on
+    #[serde(with = "config_serde::blocks")]
+    pub on: Vec<BlockState>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum Feature {
+    /// Trunk with a blob of leaves on top
+    Tree {
+        #[serde(with = "config_serde::block")]
+        log: BlockState,
+        #[serde(with = "config_serde::block")]
+        leaves: BlockState,
+        min_height: u32,
+        max_height: u32,
+    },
+    /// A single block, or two if the block is the lower half of a tall plant like `tall_grass`
+    Plant(#[serde(with = "config_serde::block")] BlockState),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SerializableFeature {
+    Tree {
+        log: String,
+        leaves: String,
+        min_height: u32,
+        max_height: u32,
+    },
+    Plant(String),
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SerializableDecoration {
+    pub feature: SerializableFeature,
+    pub chance: f64,
+    #[serde(default)]
+    pub noise: Option<String>,
+    pub on: Vec<String>,
+}
+
+impl SerializableDecoration {
+    pub fn parse(self) -> Result<Decoration, TerrainConfigError> {
+        self.parse_with(None)
+    }
+
+    /// Parses the noise with the seed of the config for its `$seed` placeholders, see [NoiseBuilder::parse_seeded]
+    pub(crate) fn parse_with(self, seed: Option<u64>) -> Result<Decoration, TerrainConfigError> {
+        Ok(Decoration {
+            feature: match self.feature {
+                SerializableFeature::Tree {
+                    log,
+                    leaves,
+                    min_height,
+                    max_height,
+                } => {
+                    if min_height > max_height {
+                        return Err(TerrainConfigError::InvalidRange {
+                            what: "tree".into(),
+                            min_field: "min_height",
+                            max_field: "max_height",
+                        });
+                    }
+                    Feature::Tree {
+                        log: block_from_str(&log)?,
+                        leaves: block_from_str(&leaves)?,
+                        min_height,
+                        max_height,
+                    }
+                }
+                SerializableFeature::Plant(block) => Feature::Plant(block_from_str(&block)?),
+            },
+            chance: self.chance,
+            noise: match self.noise {
+                Some(noise) => Some(
+                    NoiseBuilder::parse_with(&noise, seed)
+                        .map_err(TerrainConfigError::noise("decoration noise"))?,
+                ),
+                None => None,
+            },
+            on: self
+                .on
+                .iter()
+                .map(|block| block_from_str(block))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+/// [Decoration] with the noise built
+pub(crate) struct BuiltDecoration {
+    feature: Feature,
+    chance: f64,
+    noise: Option<DynNoise>,
+    on: Vec<BlockState>,
+}
+
+impl BuiltDecoration {
+    pub fn new(decoration: Decoration) -> Self {
+        Self {
+            feature: decoration.feature,
+            chance: decoration.chance,
+            noise: decoration.noise.map(NoiseBuilder::build2),
+            on: decoration.on,
+        }
+    }
+
+    /// Places the feature on the columns of the origin chunk, using the surface before features were placed
+    pub fn place(
+        &self,
+        origin: ChunkPos,
+        surface: &SurfaceInfo,
+        rng: &mut StdRng,
+        placer: &mut Placer,
+    ) {
+        for x in 0..16 {
+            for z in 0..16 {
+                let mut chance = self.chance;
+                if let Some(noise) = &self.noise {
+                    let value = noise.get([(origin.x * 16 + x) as f64, (origin.z * 16 + z) as f64]);
+                    chance *= value.clamp(0.0, 1.0);
+                }
+                // always roll so the chance of one column does not change the others
+                if rng.gen::<f64>() >= chance {
+                    continue;
+                }
+                let top = surface.heightmap.get(x as u32, z as u32);
+                if top == 0 || !self.on.contains(&surface.top_block(x as u32, z as u32)) {
+                    continue;
+                }
+                let pos = [x, top as i32, z];
+                match self.feature {
+                    Feature::Tree {
+                        log,
+                        leaves,
+                        min_height,
+                        max_height,
+                    } => {
+                        let height = rng.gen_range(min_height..=max_height);
+                        place_tree(placer, pos, height, log, leaves)
+                    }
+                    Feature::Plant(block) => {
+                        placer.place(pos, block);
+                        if block.get(PropName::Half) == Some(PropValue::Lower) {
+                            placer.place(
+                                [x, top as i32 + 1, z],
+                                block.set(PropName::Half, PropValue::Upper),
+                            );
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn place_tree(
+    placer: &mut Placer,
+    [x, y, z]: [i32; 3],
+    height: u32,
+    log: BlockState,
+    leaves: BlockState,
+) {
+    let height = height as i32;
+    for dy in 0..height {
+        placer.place([x, y + dy, z], log);
+    }
+    // two wide layers below the top of the trunk and two narrow ones at the top
+    for dy in height - 3..=height {
+        let radius = if dy >= height - 1 { 1 } else { 2 };
+        for dx in -radius..=radius {
+            for dz in -radius..=radius {
+                if dx.abs() == radius && dz.abs() == radius {
+                    continue;
+                }
+                placer.place([x + dx, y + dy, z + dz], leaves);
+            }
+        }
+    }
+}
@@ -0,0 +1,64 @@
+//! Measurements of all [TerrainGenerator]s registered with bevy's `DiagnosticsStore`, so they show up in the
+//! diagnostic log plugins next to the frame time
+
+use std::time::Instant;
+
+use bevy_diagnostic::{Diagnostic, DiagnosticId, Diagnostics, RegisterDiagnostic};
+use valence::prelude::*;
+
+use crate::{TerrainGenerator, TerrainSet};
+
+/// Chunks that are queued or being generated
+pub const PENDING_CHUNKS: DiagnosticId =
+    DiagnosticId::from_u128(0x2d0b_1f6c_41a8_4c55_9d4e_87a1_63f0_0001);
+/// Chunks inserted into the layers per second
+pub const CHUNKS_PER_SECOND: DiagnosticId =
+    DiagnosticId::from_u128(0x2d0b_1f6c_41a8_4c55_9d4e_87a1_63f0_0002);
+/// Milliseconds spent inserting finished chunks into the layers each tick
+pub const INSERT_TIME: DiagnosticId =
+    DiagnosticId::from_u128(0x2d0b_1f6c_41a8_4c55_9d4e_87a1_63f0_0003);
+/// Mebibytes used by the chunk caches of the generators
+pub const CACHE_MEMORY: DiagnosticId =
+    DiagnosticId::from_u128(0x2d0b_1f6c_41a8_4c55_9d4e_87a1_63f0_0004);
+
+const HISTORY: usize = 20;
+
+pub(crate) fn build(app: &mut App) {
+    app.register_diagnostic(Diagnostic::new(PENDING_CHUNKS, "terrain_pending", HISTORY))
+        .register_diagnostic(Diagnostic::new(
+            CHUNKS_PER_SECOND,
+            "terrain_chunks_per_second",
+            HISTORY,
+        ))
+        .register_diagnostic(
+            Diagnostic::new(INSERT_TIME, "terrain_insert_time", HISTORY).with_suffix("ms"),
+        )
+        .register_diagnostic(
+            Diagnostic::new(CACHE_MEMORY, "terrain_cache_memory", HISTORY).with_suffix("MiB"),
+        )
+        .add_systems(Update, measure.after(TerrainSet::Insert));
+}
+
+fn measure(
+    generators: Query<&TerrainGenerator>,
+    mut diagnostics: Diagnostics,
+    mut last_tick: Local<Option<Instant>>,
+) {
+    let (mut pending, mut inserted, mut insert_time, mut cache_bytes) = (0, 0, 0.0, 0);
+    for terrain_gen in &generators {
+        pending += terrain_gen.pending_chunks();
+        inserted += terrain_gen.stats().last_tick_inserted();
+        insert_time += terrain_gen.stats().last_tick_insert_time().as_secs_f64() * 1000.0;
+        cache_bytes += terrain_gen.stats().cache_bytes();
+    }
+    diagnostics.add_measurement(PENDING_CHUNKS, || pending as f64);
+    diagnostics.add_measurement(INSERT_TIME, || insert_time);
+    diagnostics.add_measurement(CACHE_MEMORY, || cache_bytes as f64 / (1024.0 * 1024.0));
+    let now = Instant::now();
+    if let Some(last_tick) = last_tick.replace(now) {
+        let secs = (now - last_tick).as_secs_f64();
+        if secs > 0.0 {
+            diagnostics.add_measurement(CHUNKS_PER_SECOND, || inserted as f64 / secs);
+        }
+    }
+}
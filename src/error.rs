@@ -0,0 +1,84 @@
+use thiserror::Error;
+
+use crate::noise_builder::ParseError;
+
+/// Reasons a [TerrainGenConfig](crate::TerrainGenConfig) can not be used, returned when parsing and validating configs
+#[derive(Error, Clone, PartialEq, Debug)]
+pub enum TerrainConfigError {
+    #[error("unknown block '{0}'")]
+    UnknownBlock(String),
+    /// Invalid syntax or properties of a block that exists
+    #[error("invalid block '{block}': {reason}")]
+    InvalidBlock { block: String, reason: String },
+    /// A surface layer of 0 blocks, `biome` is set for the layers of a [BiomeSurface](crate::biome::BiomeSurface)
+    #[error(
+        "surface layer {index}{} has no blocks",
+        .biome.as_ref().map_or(String::new(), |b| format!(" of biome {b}"))
+    )]
+    EmptySurfaceLayer { index: usize, biome: Option<String> },
+    /// Heights of chunks must be positive multiples of 16
+    #[error("height must be a positive multiple of 16, got {0}")]
+    InvalidHeight(u32),
+    #[error("min_y must be a multiple of 16, got {0}")]
+    InvalidMinY(i32),
+    /// Chunks reaching outside of the heights Minecraft supports, from -2032 to 2032
+    #[error("a height of {height} from y {min_y} reaches outside of y -2032 to 2032")]
+    HeightOverflow { height: u32, min_y: i32 },
+    #[error("noise_cell must divide 16, got {0}")]
+    InvalidNoiseCell(u32),
+    /// A noise expression that does not parse, `field` names the noise in the config
+    #[error("{field}: {source}")]
+    Noise { field: String, source: ParseError },
+    /// A noise tree with a node the parser would reject, like a spline without points built in Rust
+    #[error("{field}: {reason}")]
+    InvalidNoise { field: String, reason: String },
+    /// Heights set in the config that differ from the ones of the dimension of the layer
+    #[error(
+        "the config has a height of {} from y {}, but the dimension of the layer has a height of {layer_height} from y {layer_min_y}",
+        .height.map_or("any".into(), |h| h.to_string()),
+        .min_y.map_or("any".into(), |y| y.to_string())
+    )]
+    DimensionMismatch {
+        height: Option<u32>,
+        min_y: Option<i32>,
+        layer_height: u32,
+        layer_min_y: i32,
+    },
+    /// A range with its start above its end, like the heights of an ore
+    #[error("{what} has {min_field} above {max_field}")]
+    InvalidRange {
+        what: String,
+        min_field: &'static str,
+        max_field: &'static str,
+    },
+    #[error("floating_islands size must be positive, got {0}")]
+    InvalidIslandSize(f64),
+    #[error("invalid superflat code '{code}': {reason}")]
+    InvalidSuperflat { code: String, reason: String },
+    #[error("unknown preset '{0}', expected one of {}", crate::preset::PRESETS.join(", "))]
+    UnknownPreset(String),
+    /// Vanilla worldgen JSON that is invalid or can not be expressed, `at` is the path to the value in the JSON
+    #[error("{at}: {reason}")]
+    Vanilla { at: String, reason: String },
+    /// A list that needs at least one entry, like the biomes of a biome noise
+    #[error("{0} needs at least one entry")]
+    Empty(&'static str),
+    /// A palette whose weights add up to nothing, so no block can be picked
+    #[error("palette of '{0}' has no positive weights")]
+    EmptyPalette(String),
+}
+
+impl TerrainConfigError {
+    /// [Noise](Self::Noise) error of a field
+    pub(crate) fn noise(field: impl Into<String>) -> impl FnOnce(ParseError) -> Self {
+        let field = field.into();
+        |source| Self::Noise { field, source }
+    }
+}
+
+/// Lets functions returning `Result<_, String>` use `?` on config errors, like loading structures
+impl From<TerrainConfigError> for String {
+    fn from(error: TerrainConfigError) -> Self {
+        error.to_string()
+    }
+}
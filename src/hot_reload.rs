@@ -0,0 +1,178 @@
+//! Reloads generators when their config file is saved, add [TerrainHotReloadPlugin] to the app and a
+//! [WatchedConfig] to the layers whose config should be watched
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant},
+};
+
+use flume::Receiver;
+use notify::{RecommendedWatcher, RecursiveMode, Watcher};
+use valence::{op_level::OpLevel, prelude::*};
+
+use crate::{ReloadMode, TerrainGenConfig, TerrainGenerator, TerrainSet};
+
+/// Editors often write a file in several steps, so configs are only read once they have not changed for this long
+const DEBOUNCE: Duration = Duration::from_millis(250);
+
+/// Clients with at least this op level are told about reloads and errors in chat
+const REPORT_OP_LEVEL: u8 = 2;
+
+/// Watches the files of [WatchedConfig]s with `notify`, and reloads the generators of their layers when the files
+/// change. Layers are reloaded with their [ReloadMode] component, or with [ReloadMode::KeepEdits] if they have none
+pub struct TerrainHotReloadPlugin;
+
+impl Plugin for TerrainHotReloadPlugin {
+    fn build(&self, app: &mut App) {
+        let (sender, receiver) = flume::unbounded();
+        let watcher = notify::recommended_watcher(move |event| {
+            let _ = sender.send(event);
+        })
+        .expect("could not start watching terrain configs");
+        app.insert_resource(ConfigWatcher {
+            watcher: Mutex::new(watcher),
+            events: receiver,
+            changed: HashMap::new(),
+        })
+        .add_event::<ConfigReloaded>()
+        .add_systems(
+            Update,
+            (watch_configs, reload_configs)
+                .chain()
+                .before(TerrainSet::Unload),
+        );
+    }
+}
+
+/// Path of the config file of the [TerrainGenerator] of a layer
+#[derive(Component, Clone, Debug)]
+pub struct WatchedConfig(pub PathBuf);
+
+/// Sent after the config of a layer was read again, `error` is set if the old config is still used
+#[derive(Event, Clone, Debug)]
+pub struct ConfigReloaded {
+    pub layer: Entity,
+    pub path: PathBuf,
+    pub error: Option<String>,
+}
+
+#[derive(Resource)]
+struct ConfigWatcher {
+    watcher: Mutex<RecommendedWatcher>,
+    events: Receiver<notify::Result<notify::Event>>,
+    /// Changed files and when they were last changed
+    changed: HashMap<PathBuf, Instant>,
+}
+
+/// Reads, parses and [validates](TerrainGenConfig::validate) a config file
+pub fn load_config(path: impl AsRef<Path>) -> Result<TerrainGenConfig, String> {
+    let path = path.as_ref();
+    let content =
+        fs::read_to_string(path).map_err(|e| format!("could not read {}: {e}", path.display()))?;
+    let config = serde_yml::from_str::<TerrainGenConfig>(&content)
+        .map_err(|e| format!("invalid yaml in {}: {e}", path.display()))?;
+    config.validate()?;
+    Ok(config)
+}
+
+/// Absolute path used to match events to configs, events of files that were replaced have the absolute path as well
+fn watch_path(path: &Path) -> PathBuf {
+    let absolute = |path: &Path| path.canonicalize().unwrap_or_else(|_| path.to_path_buf());
+    match (path.parent(), path.file_name()) {
+        // the parent may be empty for files in the working directory
+        (Some(parent), Some(name)) => absolute(if parent.as_os_str().is_empty() {
+            Path::new(".")
+        } else {
+            parent
+        })
+        .join(name),
+        _ => absolute(path),
+    }
+}
+
+fn watch_configs(watcher: Res<ConfigWatcher>, added: Query<&WatchedConfig, Added<WatchedConfig>>) {
+    for config in &added {
+        let path = watch_path(&config.0);
+        // the folder is watched, as editors that save by replacing the file would end a watch of the file itself
+        let Some(folder) = path.parent() else {
+            continue;
+        };
+        let result = watcher
+            .watcher
+            .lock()
+            .unwrap()
+            .watch(folder, RecursiveMode::NonRecursive);
+        if let Err(e) = result {
+            tracing::error!("could not watch terrain config {}: {e}", path.display());
+        }
+    }
+}
+
+fn reload_configs(
+    mut watcher: ResMut<ConfigWatcher>,
+    mut layers: Query<(
+        Entity,
+        &WatchedConfig,
+        &mut TerrainGenerator,
+        &mut ChunkLayer,
+        Option<&ReloadMode>,
+    )>,
+    mut clients: Query<(&mut Client, &OpLevel)>,
+    mut reloaded: EventWriter<ConfigReloaded>,
+) {
+    let watcher = &mut *watcher;
+    let now = Instant::now();
+    for event in watcher.events.try_iter() {
+        match event {
+            Ok(event) if event.kind.is_create() || event.kind.is_modify() => {
+                for path in event.paths {
+                    watcher.changed.insert(path, now);
+                }
+            }
+            Ok(_) => {}
+            Err(e) => tracing::error!("error while watching terrain configs: {e}"),
+        }
+    }
+    let ready = watcher
+        .changed
+        .iter()
+        .filter(|(_, changed)| now - **changed >= DEBOUNCE)
+        .map(|(path, _)| path.clone())
+        .collect::<Vec<_>>();
+    for path in ready {
+        watcher.changed.remove(&path);
+        for (entity, config, mut terrain_gen, mut layer, mode) in &mut layers {
+            if watch_path(&config.0) != path {
+                continue;
+            }
+            let (message, error) = match load_config(&config.0) {
+                Ok(new_config) => {
+                    let mode = mode.copied().unwrap_or_default();
+                    terrain_gen.reload_with(new_config, mode, &mut layer);
+                    tracing::info!("reloaded terrain config {}", config.0.display());
+                    (
+                        format!("reloaded terrain config {}", config.0.display()),
+                        None,
+                    )
+                }
+                Err(e) => {
+                    tracing::error!("not reloading terrain config: {e}");
+                    (format!("not reloading terrain config: {e}"), Some(e))
+                }
+            };
+            for (mut client, op_level) in &mut clients {
+                if op_level.get() >= REPORT_OP_LEVEL {
+                    client.send_chat_message(message.clone());
+                }
+            }
+            reloaded.send(ConfigReloaded {
+                layer: entity,
+                path: config.0.clone(),
+                error,
+            });
+        }
+    }
+}
@@ -0,0 +1,70 @@
+use serde::{Deserialize, Serialize};
+
+use crate::noise_builder::NoiseBuilder;
+
+/// Shape of the islands of [FloatingIslands](crate::TerrainMode::FloatingIslands) mode, which float around `y` with
+/// nothing below them like the outer islands of the End.  
+/// The `density` of the config is added to the islands to roughen them up
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct FloatingIslands {
+    /// Rough width of the islands in blocks, must be positive
+    pub size: f64,
+    /// Part of the area covered by islands, from 0 to 1. Lower coverage gives fewer and smaller islands
+    pub coverage: f64,
+    /// World height of the flat middle of the islands
+    pub y: i32,
+    /// Blocks the middle of the largest islands rises above `y`
+    pub top: u32,
+    /// Blocks the largest islands hang below `y`
+    pub depth: u32,
+    pub seed: u32,
+}
+
+impl Default for FloatingIslands {
+    fn default() -> Self {
+        Self {
+            size: 96.0,
+            coverage: 0.25,
+            y: 100,
+            top: 8,
+            depth: 24,
+            seed: 0,
+        }
+    }
+}
+
+impl FloatingIslands {
+    /// 2D noise deciding where the islands are, used as the height noise of the config in this mode
+    pub(crate) fn mask(&self) -> NoiseBuilder {
+        NoiseBuilder::ScaleInput(
+            1.0 / self.size,
+            1.0 / self.size,
+            None,
+            Box::new(NoiseBuilder::Fbm {
+                seed: self.seed,
+                octaves: 3,
+                frequency: 1.0,
+                lacunarity: 2.0,
+                persistence: 0.5,
+            }),
+        )
+    }
+
+    /// World heights of the bottom and top of the island in a column with the value of the [mask](Self::mask),
+    /// `None` if the column has no island
+    pub(crate) fn extent(&self, mask: f64) -> Option<(f64, f64)> {
+        let threshold = 1.0 - 2.0 * self.coverage.clamp(0.0, 1.0);
+        if mask <= threshold {
+            return None;
+        }
+        // 0 at the edges of the island and 1 in the middle of the largest ones
+        let strength = ((mask - threshold) / (1.0 - threshold)).min(1.0);
+        let y = self.y as f64;
+        // the square root gives rounded undersides that hang lower than the tops rise
+        Some((
+            y - strength.sqrt() * self.depth as f64,
+            y + strength * self.top as f64,
+        ))
+    }
+}
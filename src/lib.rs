@@ -1,93 +1,1113 @@
 use std::{
-    collections::{hash_map::Entry, HashMap},
+    collections::{hash_map::Entry, HashMap, HashSet, VecDeque},
+    fmt::{self, Display},
+    path::PathBuf,
     sync::Arc,
     thread,
+    time::{Duration, Instant},
 };
 
-use flume::{Receiver, Sender};
-use noise::NoiseFn;
+pub use config_builder::TerrainGenConfigBuilder;
+pub use error::TerrainConfigError;
+use flume::{Receiver, Sender, TryRecvError};
 use serde::{Deserialize, Serialize};
-use valence::prelude::*;
+use valence::{app::AppExit, prelude::*};
 
-use noise_builder::{DynNoise, NoiseBuilder};
+use bedrock::BedrockLayers;
+use biome::{BiomeSource, BiomeSurface, SerializableBiomeSource, SerializableBiomeSurface};
+use border::{BorderShape, WorldBorder};
+use cache::{ChunkCache, ChunkCacheConfig};
+use decoration::{Decoration, Feature, SerializableDecoration};
+use islands::FloatingIslands;
+use map::MapJob;
+use noise_builder::{seed_tree, NoiseBuilder};
+use ore::{OreConfig, SerializableOreConfig};
+use palette::BlockPalette;
+use platform::SpawnPlatform;
+use proto::{neighborhood, ProtoChunk, SurfaceInfo};
+use shaper::TerrainShaper;
+use strata::Stratum;
+use structure::{SerializableStructureConfig, StructureConfig};
+use worker::{
+    block_hash, pool_worker, ChunkSource, ChunkWorkerState, FeatureJob, Generated, GeneratedChunk,
+    Job, JobQueue, OldSurfaces, RepaintJob, TerrainShape, UnloadJob, Unloaded, WorkerQueue,
+    NOISE_SALT,
+};
 
+mod anvil;
+pub mod bedrock;
+pub mod biome;
+pub mod border;
+pub mod cache;
+#[cfg(feature = "command")]
+pub mod command;
+mod config_builder;
+mod config_serde;
+pub mod decoration;
+#[cfg(feature = "diagnostics")]
+pub mod diagnostics;
+mod error;
+#[cfg(feature = "hot_reload")]
+pub mod hot_reload;
+pub mod islands;
+pub mod map;
 pub mod noise_builder;
+pub mod ore;
+pub mod palette;
+pub mod platform;
+#[cfg(feature = "pregen")]
+pub mod pregen;
+pub mod preset;
+pub mod preview;
+mod proto;
+pub mod shaper;
+pub mod strata;
+pub mod structure;
+mod superflat;
+pub mod vanilla;
+mod worker;
 
+/// Generates the chunks of every layer with a [TerrainGenerator], each layer can have its own config.  
+/// The generators share the workers of the [TerrainWorkerPool]
 pub struct TerrainPlugin;
 
 impl Plugin for TerrainPlugin {
     fn build(&self, app: &mut App) {
-        app.add_systems(
-            Update,
-            (
-                remove_unviewed_chunks,
-                update_client_views,
-                send_recv_chunks,
+        app.init_resource::<TerrainWorkerPool>()
+            .add_event::<PregenProgress>()
+            .add_event::<ChunkQueued>()
+            .add_event::<ChunkGenerated>()
+            .add_event::<TerrainStartFailed>()
+            .configure_sets(
+                Update,
+                (
+                    TerrainSet::Unload,
+                    TerrainSet::QueueViews,
+                    TerrainSet::Insert,
+                )
+                    .chain(),
             )
-                .chain(),
-        );
+            .add_systems(
+                Update,
+                (
+                    remove_unviewed_chunks.in_set(TerrainSet::Unload),
+                    update_client_views.in_set(TerrainSet::QueueViews),
+                    (start_workers, send_recv_chunks)
+                        .chain()
+                        .in_set(TerrainSet::Insert),
+                ),
+            )
+            .add_systems(Last, save_on_exit);
+        #[cfg(feature = "diagnostics")]
+        diagnostics::build(app);
     }
 }
 
+/// Sets of the systems of [TerrainPlugin] in [Update], run in the order of the variants
+#[derive(SystemSet, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum TerrainSet {
+    /// Removes chunks without viewers from the layers
+    Unload,
+    /// Queues the chunks in the view of clients
+    QueueViews,
+    /// Inserts generated chunks into the layers and sends queued chunks to the workers
+    Insert,
+}
+
+/// How chunks are filled by the workers
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum TerrainMode {
+    /// Columns are filled up to the height given by the noise
+    #[default]
+    Heightmap,
+    /// Chunks are left empty, for layers filled by other systems.  
+    /// Chunks are still loaded and unloaded like normal
+    Void,
+    /// Islands floating in the air with nothing below them, shaped by
+    /// [floating_islands](TerrainGenConfig::floating_islands) instead of `noise`
+    FloatingIslands,
+    /// Roofed caverns like the nether, `noise` gives the height of the floor and
+    /// [cavern_ceiling](TerrainGenConfig::cavern_ceiling) the height of the ceiling above it.  
+    /// Surface layers are placed on the floor, and the top of the heightmap is the floor
+    Caverns,
+}
+
+/// Ways of reloading a generator with [reload_with](TerrainGenerator::reload_with).  
+/// As a component of a layer it sets how the `TerrainHotReloadPlugin` reloads it
+#[derive(Component, Clone, Copy, PartialEq, Eq, Default, Debug)]
+pub enum ReloadMode {
+    /// Removes every chunk except [protected](TerrainGenerator::protect) ones and generates them again, the world is
+    /// empty until they are
+    Clear,
+    /// See [reload_keep_edits](TerrainGenerator::reload_keep_edits)
+    #[default]
+    KeepEdits,
+    /// See [reload_swap](TerrainGenerator::reload_swap)
+    Swap,
+    /// See [reload_incremental](TerrainGenerator::reload_incremental)
+    Incremental,
+}
+
+/// What [reload_incremental](TerrainGenerator::reload_incremental) does with the loaded chunks, see
+/// [TerrainGenConfig::reload_scope]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ReloadScope {
+    /// The configs are the same, the generator is left as it is
+    Unchanged,
+    /// Only `surface_layers` or `biome_surfaces` changed, the surfaces of loaded chunks are repainted in place
+    Surfaces,
+    /// Anything else changed, loaded chunks are kept until their new versions replace them
+    Terrain,
+}
+
+/// What happens to chunks coming into view once `queue_capacity` chunks are waiting to be generated
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Default, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum QueueOverflow {
+    /// Chunks are still queued, only sending them to the workers waits until there is room
+    #[default]
+    Block,
+    /// The chunk farthest from its viewer is dropped to make room, unless the new chunk is farther.
+    /// Dropped chunks are queued again once there is room, if they are still viewed
+    DropFarthest,
+    /// New chunks are not queued until there is room, if they are still viewed by then
+    Reject,
+}
+
+/// Square of chunks around the spawn that are generated as soon as the generator is created and never unloaded, so
+/// joining players do not wait for their first chunks and machines near spawn keep running
+#[derive(Serialize, Deserialize, Clone, Copy, Debug)]
+#[serde(default)]
+pub struct SpawnChunks {
+    /// Block x and z of the spawn
+    pub position: [i32; 2],
+    /// Chunks kept on each side of the chunk of the spawn
+    pub radius: u32,
+}
+
+impl Default for SpawnChunks {
+    fn default() -> Self {
+        Self {
+            position: [0, 0],
+            radius: 4,
+        }
+    }
+}
+
+impl SpawnChunks {
+    pub fn chunks(&self) -> impl Iterator<Item = ChunkPos> {
+        let [x, z] = self.position;
+        let center = ChunkPos::new(x.div_euclid(16), z.div_euclid(16));
+        pregen_area(center, self.radius, PregenShape::Square)
+    }
+}
+
+/// Height of the chunks of a config without a height, before it is taken from the layer. The same as the vanilla
+/// overworld
+pub const DEFAULT_HEIGHT: u32 = 384;
+
+/// Config of a [TerrainGenerator], can be read from YAML, JSON or any other format supported by serde.  
+/// Blocks are written by name, and noise as an expression read by [NoiseBuilder::parse] or as the tree of the
+/// serde impls of [NoiseBuilder]. `$seed` placeholders are replaced with the `seed` of the config, see
+/// [NoiseBuilder::parse_seeded]. Fields that are left out use their [default](Default) values, or the values of a
+/// [preset](Self::preset) if the config has a `preset` field.  
+/// Configs with [Custom](NoiseBuilder::Custom) noise can not be serialized, as the functions can not be written
+#[derive(Serialize, Deserialize, Clone)]
+// the derived impls are wrapped by the ones below, which handle presets
+#[serde(remote = "Self", default)]
 pub struct TerrainGenConfig {
+    pub mode: TerrainMode,
+    #[serde(with = "config_serde::block")]
     pub block: BlockState,
+    #[serde(with = "config_serde::layers")]
     pub surface_layers: Vec<(u16, BlockState)>,
+    #[serde(with = "config_serde::noise")]
     pub noise: NoiseBuilder,
-    pub height: u32,
-    //TODO impl structures
+    /// Height from continentalness, erosion and peaks and valleys noises like vanilla, replaces `noise` if set
+    pub shaper: Option<TerrainShaper>,
+    /// Optional 3D noise sampled at `[x, z, y]` that is added to the distance below the surface given by `noise`.  
+    /// A block is solid when the sum is positive, so this allows for overhangs, cliffs and floating ledges
+    #[serde(with = "config_serde::option_noise")]
+    pub density: Option<NoiseBuilder>,
+    /// Height of the chunks, taken from the dimension type of the layer if not set.  
+    /// The generator does not start if it is set to a different height than the one of the layer
+    pub height: Option<u32>,
+    /// World height of the bottom of the chunks, like -64 in the vanilla overworld. The noise, `density`, `sea_level`,
+    /// strata and ores all use world heights, so a noise of 64 gives a surface at y 64 for any `min_y`.  
+    /// Like `height` it is taken from the layer if not set, and must match it otherwise
+    pub min_y: Option<i32>,
+    /// If set, surface layers are placed below every solid block with air above it, instead of only the top one
+    pub surface_on_all_exposed: bool,
+    /// Biomes of the generated chunks, every chunk uses the default biome if not set
+    pub biomes: Option<BiomeSource>,
+    /// Replaces `block` and `surface_layers` in columns of specific biomes, e.g. sand in deserts
+    pub biome_surfaces: Vec<BiomeSurface>,
+    /// Air below this height is filled with `fluid`
+    pub sea_level: Option<i32>,
+    #[serde(with = "config_serde::block")]
+    pub fluid: BlockState,
+    /// Replace the base blocks below their heights, like deepslate deep below the stone
+    pub strata: Vec<Stratum>,
+    /// Blocks of the terrain replaced with a weighted random pick, like `block` mixed with some andesite and gravel.  
+    /// Ores are still placed in the blocks picked for a base block
+    pub palettes: Vec<BlockPalette>,
+    /// Bedrock at the bottom of the chunks, not placed in [Void](TerrainMode::Void) mode
+    pub bedrock_floor: Option<BedrockLayers>,
+    /// Bedrock at the top of the chunks, for closed dimensions like the nether. It is not part of the heightmap
+    pub bedrock_ceiling: Option<BedrockLayers>,
+    /// Ore veins placed in the base block, placed in order so later ores can replace earlier ones
+    pub ores: Vec<OreConfig>,
+    /// Features like trees and plants placed on the surface after ores
+    pub decorations: Vec<Decoration>,
+    /// Structure templates placed on the surface after decorations
+    pub structures: Vec<StructureConfig>,
+    /// World height of the ceiling in [Caverns](TerrainMode::Caverns) mode, everything above it is solid
+    #[serde(with = "config_serde::noise")]
+    pub cavern_ceiling: NoiseBuilder,
+    /// Shape of the islands in [FloatingIslands](TerrainMode::FloatingIslands) mode
+    pub floating_islands: FloatingIslands,
+    /// Platform placed after the terrain in any mode, for skyblock worlds using [Void](TerrainMode::Void) mode
+    pub spawn_platform: Option<SpawnPlatform>,
+    /// Folder of a dimension in an existing Anvil world, like `world` or `world/DIM-1`.  
+    /// Chunks saved in the world are loaded as they are, and only missing chunks are generated
+    pub anvil: Option<PathBuf>,
+    /// If set, generated chunks are saved to the `anvil` world, and saved again when they are unloaded so changes
+    /// made to them by other systems are kept. Chunks that are still loaded are saved on [AppExit].  
+    /// Chunks loaded from the world are never written back, changes to them are only kept with
+    /// [set_keep_edits](TerrainGenerator::set_keep_edits)
+    pub anvil_save: bool,
+    /// Max chunks waiting to be generated, also the max jobs sent to the workers at once. Unbounded if not set.  
+    /// Pregenerated and requested chunks are always queued
+    pub queue_capacity: Option<usize>,
+    pub queue_overflow: QueueOverflow,
+    /// If set, unloaded chunks that were not changed are kept and inserted again when they come back into view, so
+    /// players walking back and forth over a chunk border do not make them generate again
+    pub chunk_cache: Option<ChunkCacheConfig>,
+    /// Ticks a chunk stays loaded after its last viewer left, 0 unloads it right away
+    pub unload_delay: u32,
+    /// Chunks around the spawn that are always loaded
+    pub spawn_chunks: Option<SpawnChunks>,
+    /// Edge of the world for finite maps, everything is generated if not set
+    pub world_border: Option<WorldBorder>,
+    /// If set, `noise` and `density` are only sampled every this many blocks and interpolated in between, like vanilla
+    /// does with 4. This is a lot faster for deep noise trees, but smooths out details smaller than the cells.  
+    /// Must divide 16
+    pub noise_cell: Option<u32>,
+    /// Vanilla superflat code like `minecraft:bedrock,2*minecraft:dirt,minecraft:grass_block;minecraft:plains`.  
+    /// If set, the layers replace `block`, `surface_layers`, `noise` and `density`, and the biome replaces `biomes`.
+    /// The bottom layer starts at `min_y` like in vanilla
+    pub superflat: Option<String>,
+    /// World seed the seeds of every noise node are derived from, see [NoiseBuilder::seeded]. Nodes written with the
+    /// same seed are then no longer correlated. If not set, the seeds are used as written
+    pub seed: Option<u64>,
+}
+
+impl TerrainGenConfig {
+    /// Config that generates empty chunks, see [TerrainMode::Void]
+    pub fn void(height: u32) -> Self {
+        Self {
+            mode: TerrainMode::Void,
+            height: Some(height),
+            ..Default::default()
+        }
+    }
+
+    /// Sets the world [seed](Self::seed), reseeding every noise of the config without parsing it again
+    pub fn with_seed(mut self, seed: u64) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    /// Sets `height` and `min_y` to the ones of a layer, or errors if they are set to different ones
+    pub fn for_layer(mut self, layer: &ChunkLayer) -> Result<Self, TerrainConfigError> {
+        let (height, min_y) = (layer.height(), layer.min_y());
+        if self.height.is_some_and(|h| h != height) || self.min_y.is_some_and(|y| y != min_y) {
+            return Err(TerrainConfigError::DimensionMismatch {
+                height: self.height,
+                min_y: self.min_y,
+                layer_height: height,
+                layer_min_y: min_y,
+            });
+        }
+        self.height = Some(height);
+        self.min_y = Some(min_y);
+        Ok(self)
+    }
+
+    /// Checks for values the generator can not use, [TerrainGenerator::new] panics with the error.  
+    /// Blocks are already checked when they are parsed, noise is checked again as trees built in Rust are not parsed
+    pub fn validate(&self) -> Result<(), TerrainConfigError> {
+        if let Some(height) = self.height {
+            if height == 0 || height % 16 != 0 {
+                return Err(TerrainConfigError::InvalidHeight(height));
+            }
+        }
+        if let Some(min_y) = self.min_y {
+            if min_y % 16 != 0 {
+                return Err(TerrainConfigError::InvalidMinY(min_y));
+            }
+        }
+        let (height, min_y) = (self.chunk_height(), self.bottom_y());
+        if min_y < -2032 || min_y as i64 + height as i64 > 2032 {
+            return Err(TerrainConfigError::HeightOverflow { height, min_y });
+        }
+        if let Some(cell) = self.noise_cell {
+            if cell == 0 || 16 % cell != 0 {
+                return Err(TerrainConfigError::InvalidNoiseCell(cell));
+            }
+        }
+        if let Some(code) = &self.superflat {
+            superflat::parse_superflat(code)?;
+        }
+        if let Some(shaper) = &self.shaper {
+            shaper.validate()?;
+        }
+        if self.floating_islands.size <= 0.0 {
+            return Err(TerrainConfigError::InvalidIslandSize(
+                self.floating_islands.size,
+            ));
+        }
+        self.validate_noise()?;
+        if let Some(WorldBorder {
+            shape: BorderShape::Rect { min, max },
+            ..
+        }) = &self.world_border
+        {
+            if min[0] > max[0] || min[1] > max[1] {
+                return Err(TerrainConfigError::InvalidRange {
+                    what: "world_border".into(),
+                    min_field: "min",
+                    max_field: "max",
+                });
+            }
+        }
+        check_layers(&self.surface_layers, None)?;
+        for surface in &self.biome_surfaces {
+            if let Some(layers) = &surface.surface_layers {
+                check_layers(layers, Some(&surface.biome))?;
+            }
+        }
+        for palette in &self.palettes {
+            if palette.total_weight() <= 0.0 {
+                return Err(TerrainConfigError::EmptyPalette(block_to_string(
+                    palette.block,
+                )));
+            }
+        }
+        for ore in &self.ores {
+            if ore.min_y > ore.max_y {
+                return Err(TerrainConfigError::InvalidRange {
+                    what: format!("ore '{}'", block_to_string(ore.block)),
+                    min_field: "min_y",
+                    max_field: "max_y",
+                });
+            }
+        }
+        for decoration in &self.decorations {
+            if let Feature::Tree {
+                min_height,
+                max_height,
+                ..
+            } = decoration.feature
+            {
+                if min_height > max_height {
+                    return Err(TerrainConfigError::InvalidRange {
+                        what: "tree".into(),
+                        min_field: "min_height",
+                        max_field: "max_height",
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Checks every noise tree of the config, see [NoiseBuilder::validate]
+    fn validate_noise(&self) -> Result<(), TerrainConfigError> {
+        let height_field = match (&self.mode, &self.shaper) {
+            (TerrainMode::FloatingIslands, _) => "floating_islands",
+            (_, Some(_)) => "shaper",
+            (_, None) => "noise",
+        };
+        let height_noise = self.height_noise();
+        let mut noises = vec![(height_field, &height_noise)];
+        noises.extend(self.noises().into_iter().skip(1));
+        for (field, noise) in noises {
+            noise
+                .validate()
+                .map_err(|reason| TerrainConfigError::InvalidNoise {
+                    field: field.into(),
+                    reason,
+                })?;
+        }
+        Ok(())
+    }
+
+    /// Every noise tree written in the config with the field it is in, starting with `noise`
+    fn noises(&self) -> Vec<(&'static str, &NoiseBuilder)> {
+        let mut noises = vec![
+            ("noise", &self.noise),
+            ("cavern_ceiling", &self.cavern_ceiling),
+        ];
+        noises.extend(self.density.iter().map(|noise| ("density", noise)));
+        noises.extend(
+            self.strata
+                .iter()
+                .filter_map(|stratum| Some(("stratum noise", stratum.noise.as_ref()?))),
+        );
+        noises.extend(
+            self.decorations
+                .iter()
+                .filter_map(|decoration| Some(("decoration noise", decoration.noise.as_ref()?))),
+        );
+        match &self.biomes {
+            Some(BiomeSource::Noise(noise)) => noises.push(("biome noise", &noise.noise)),
+            Some(BiomeSource::MultiNoise(climate)) => noises.extend([
+                ("temperature", &climate.temperature),
+                ("humidity", &climate.humidity),
+                ("continentalness", &climate.continentalness),
+            ]),
+            _ => {}
+        }
+        noises
+    }
+
+    /// What has to be generated again to go from this config to `new`
+    pub fn reload_scope(&self, new: &TerrainGenConfig) -> ReloadScope {
+        // custom functions can not be compared, so they may have changed
+        let custom = |config: &Self| {
+            config.height_noise().has_custom()
+                || config.noises().iter().any(|(_, noise)| noise.has_custom())
+        };
+        if custom(self) || custom(new) {
+            return ReloadScope::Terrain;
+        }
+        // compared as they would be written, as noise can not be compared directly
+        let (Ok(serde_yml::Value::Mapping(old)), Ok(serde_yml::Value::Mapping(new))) =
+            (serde_yml::to_value(self), serde_yml::to_value(new))
+        else {
+            return ReloadScope::Terrain;
+        };
+        let changed = old
+            .iter()
+            .filter(|(key, value)| new.get(*key) != Some(*value))
+            .map(|(key, _)| key.as_str().unwrap_or_default())
+            .collect::<Vec<_>>();
+        if changed.is_empty() {
+            ReloadScope::Unchanged
+        } else if changed
+            .iter()
+            .all(|key| matches!(*key, "surface_layers" | "biome_surfaces"))
+        {
+            ReloadScope::Surfaces
+        } else {
+            ReloadScope::Terrain
+        }
+    }
+
+    /// Height of the chunks, [DEFAULT_HEIGHT] until it is taken from the layer
+    pub(crate) fn chunk_height(&self) -> u32 {
+        self.height.unwrap_or(DEFAULT_HEIGHT)
+    }
+
+    /// World height of the bottom of the chunks, 0 until it is taken from the layer
+    pub(crate) fn bottom_y(&self) -> i32 {
+        self.min_y.unwrap_or(0)
+    }
+
+    /// Noise giving the height of the terrain, the island mask in
+    /// [FloatingIslands](TerrainMode::FloatingIslands) mode and the [shaper](Self::shaper) if there is one
+    pub(crate) fn height_noise(&self) -> NoiseBuilder {
+        match (&self.mode, &self.shaper) {
+            (TerrainMode::FloatingIslands, _) => self.floating_islands.mask(),
+            (_, Some(shaper)) => shaper.noise(),
+            (_, None) => self.noise.clone(),
+        }
+    }
+}
+
+impl Serialize for TerrainGenConfig {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        TerrainGenConfig::serialize(self, serializer)
+    }
+}
+
+/// Reads the config as a map first, so the fields of a preset can be replaced by the fields that are written
+impl<'de> Deserialize<'de> for TerrainGenConfig {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use serde::de::Error;
+        use serde_yml::Value;
+
+        let mut value = Value::deserialize(deserializer)?;
+        let preset = match &mut value {
+            Value::Mapping(fields) => fields.remove("preset"),
+            _ => None,
+        };
+        let Some(preset) = preset else {
+            let seed = value.get("seed").and_then(Value::as_u64);
+            return config_serde::with_seed(seed, || TerrainGenConfig::deserialize(value))
+                .map_err(D::Error::custom);
+        };
+        let Some(name) = preset.as_str() else {
+            return Err(D::Error::custom("preset must be the name of a preset"));
+        };
+        let preset = TerrainGenConfig::preset(name).map_err(D::Error::custom)?;
+        let mut merged = serde_yml::to_value(&preset).map_err(D::Error::custom)?;
+        if let (Value::Mapping(merged), Value::Mapping(fields)) = (&mut merged, value) {
+            for (name, field) in fields {
+                merged.insert(name, field);
+            }
+        }
+        let seed = merged.get("seed").and_then(Value::as_u64);
+        config_serde::with_seed(seed, || TerrainGenConfig::deserialize(merged))
+            .map_err(D::Error::custom)
+    }
 }
 
 impl Default for TerrainGenConfig {
     fn default() -> Self {
         Self {
+            mode: TerrainMode::Heightmap,
             block: BlockState::DIRT,
             surface_layers: vec![(1, BlockState::GRASS_BLOCK)],
             noise: NoiseBuilder::Constant(64.0),
-            height: 384,
+            shaper: None,
+            density: None,
+            height: None,
+            min_y: None,
+            surface_on_all_exposed: false,
+            biomes: None,
+            biome_surfaces: vec![],
+            sea_level: None,
+            fluid: BlockState::WATER,
+            strata: vec![],
+            palettes: vec![],
+            bedrock_floor: None,
+            bedrock_ceiling: None,
+            ores: vec![],
+            decorations: vec![],
+            structures: vec![],
+            cavern_ceiling: NoiseBuilder::Constant(128.0),
+            floating_islands: FloatingIslands::default(),
+            spawn_platform: None,
+            anvil: None,
+            anvil_save: false,
+            queue_capacity: None,
+            queue_overflow: QueueOverflow::Block,
+            chunk_cache: None,
+            unload_delay: 0,
+            spawn_chunks: None,
+            world_border: None,
+            noise_cell: None,
+            superflat: None,
+            seed: None,
         }
     }
 }
 
+/// Config with blocks and noise as strings, parsed into a [TerrainGenConfig].  
+#[deprecated(note = "TerrainGenConfig can be deserialized directly")]
 #[derive(Serialize, Deserialize)]
 pub struct SerializableTerrainGenConfig {
+    #[serde(default)]
+    pub mode: TerrainMode,
     pub block: String,
     pub surface_layers: Vec<(u16, String)>,
     pub noise: String,
+    #[serde(default)]
+    pub shaper: Option<TerrainShaper>,
+    #[serde(default)]
+    pub density: Option<String>,
     pub height: u32,
+    #[serde(default)]
+    pub min_y: Option<i32>,
+    #[serde(default)]
+    pub surface_on_all_exposed: bool,
+    #[serde(default)]
+    pub biomes: Option<SerializableBiomeSource>,
+    #[serde(default)]
+    pub biome_surfaces: Vec<SerializableBiomeSurface>,
+    #[serde(default)]
+    pub sea_level: Option<i32>,
+    /// Defaults to water
+    #[serde(default)]
+    pub fluid: Option<String>,
+    #[serde(default)]
+    pub strata: Vec<Stratum>,
+    #[serde(default)]
+    pub palettes: Vec<BlockPalette>,
+    #[serde(default)]
+    pub bedrock_floor: Option<BedrockLayers>,
+    #[serde(default)]
+    pub bedrock_ceiling: Option<BedrockLayers>,
+    #[serde(default)]
+    pub ores: Vec<SerializableOreConfig>,
+    #[serde(default)]
+    pub decorations: Vec<SerializableDecoration>,
+    #[serde(default)]
+    pub structures: Vec<SerializableStructureConfig>,
+    /// Defaults to a ceiling at y 128
+    #[serde(default)]
+    pub cavern_ceiling: Option<String>,
+    #[serde(default)]
+    pub floating_islands: FloatingIslands,
+    #[serde(default)]
+    pub spawn_platform: Option<SpawnPlatform>,
+    #[serde(default)]
+    pub anvil: Option<PathBuf>,
+    #[serde(default)]
+    pub anvil_save: bool,
+    #[serde(default)]
+    pub queue_capacity: Option<usize>,
+    #[serde(default)]
+    pub queue_overflow: QueueOverflow,
+    #[serde(default)]
+    pub chunk_cache: Option<ChunkCacheConfig>,
+    #[serde(default)]
+    pub unload_delay: u32,
+    #[serde(default)]
+    pub spawn_chunks: Option<SpawnChunks>,
+    #[serde(default)]
+    pub world_border: Option<WorldBorder>,
+    #[serde(default)]
+    pub noise_cell: Option<u32>,
+    #[serde(default)]
+    pub superflat: Option<String>,
+    #[serde(default)]
+    pub seed: Option<u64>,
 }
 
+#[allow(deprecated)]
 impl SerializableTerrainGenConfig {
-    pub fn parse(self) -> Result<TerrainGenConfig, String> {
-        let mut surface_layers = vec![];
-        for (amt, layer) in self.surface_layers {
-            surface_layers.push(match block_from_str(&layer) {
-                Ok(block) => (amt, block),
-                Err(e) => return Err(e),
-            });
-        }
-        Ok(TerrainGenConfig {
+    /// Parses the blocks and noise, and [validates](TerrainGenConfig::validate) the config
+    pub fn parse(self) -> Result<TerrainGenConfig, TerrainConfigError> {
+        let config = TerrainGenConfig {
+            mode: self.mode,
             block: block_from_str(&self.block)?,
-            surface_layers,
-            noise: NoiseBuilder::parse(&self.noise)?,
-            height: self.height,
+            surface_layers: layers_from_strs(self.surface_layers)?,
+            noise: NoiseBuilder::parse_with(&self.noise, self.seed)
+                .map_err(TerrainConfigError::noise("noise"))?,
+            shaper: self.shaper,
+            density: match self.density {
+                Some(density) => Some(
+                    NoiseBuilder::parse_with(&density, self.seed)
+                        .map_err(TerrainConfigError::noise("density"))?,
+                ),
+                None => None,
+            },
+            height: Some(self.height),
+            min_y: self.min_y,
+            surface_on_all_exposed: self.surface_on_all_exposed,
+            biomes: match self.biomes {
+                Some(biomes) => Some(biomes.parse_with(self.seed)?),
+                None => None,
+            },
+            biome_surfaces: self
+                .biome_surfaces
+                .into_iter()
+                .map(SerializableBiomeSurface::parse)
+                .collect::<Result<_, _>>()?,
+            sea_level: self.sea_level,
+            fluid: match self.fluid {
+                Some(fluid) => block_from_str(&fluid)?,
+                None => BlockState::WATER,
+            },
+            strata: self.strata,
+            palettes: self.palettes,
+            bedrock_floor: self.bedrock_floor,
+            bedrock_ceiling: self.bedrock_ceiling,
+            ores: self
+                .ores
+                .into_iter()
+                .map(SerializableOreConfig::parse)
+                .collect::<Result<_, _>>()?,
+            decorations: self
+                .decorations
+                .into_iter()
+                .map(|decoration| decoration.parse_with(self.seed))
+                .collect::<Result<_, _>>()?,
+            structures: self
+                .structures
+                .into_iter()
+                .map(SerializableStructureConfig::parse)
+                .collect::<Result<_, _>>()?,
+            cavern_ceiling: match self.cavern_ceiling {
+                Some(ceiling) => NoiseBuilder::parse_with(&ceiling, self.seed)
+                    .map_err(TerrainConfigError::noise("cavern_ceiling"))?,
+                None => NoiseBuilder::Constant(128.0),
+            },
+            floating_islands: self.floating_islands,
+            spawn_platform: self.spawn_platform,
+            anvil: self.anvil,
+            anvil_save: self.anvil_save,
+            queue_capacity: self.queue_capacity,
+            queue_overflow: self.queue_overflow,
+            chunk_cache: self.chunk_cache,
+            unload_delay: self.unload_delay,
+            spawn_chunks: self.spawn_chunks,
+            world_border: self.world_border,
+            noise_cell: self.noise_cell,
+            superflat: self.superflat,
+            seed: self.seed,
+        };
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// Parses a block like `stone`, with optional vanilla style properties like `oak_log[axis=z]` or
+/// `snow[layers=3]`. The `minecraft` namespace may be written, properties that are left out keep their default values
+pub(crate) fn block_from_str(s: &str) -> Result<BlockState, TerrainConfigError> {
+    let invalid = |reason: String| TerrainConfigError::InvalidBlock {
+        block: s.to_string(),
+        reason,
+    };
+    let (name, props) = match s.split_once('[') {
+        Some((name, props)) => match props.strip_suffix(']') {
+            Some(props) => (name, Some(props)),
+            None => return Err(invalid("missing ']'".into())),
+        },
+        None => (s, None),
+    };
+    let name = name.trim();
+    let kind = match BlockKind::from_str(name.strip_prefix("minecraft:").unwrap_or(name)) {
+        Some(kind) => kind,
+        None => return Err(TerrainConfigError::UnknownBlock(name.to_string())),
+    };
+    let mut block = BlockState::from_kind(kind);
+    for prop in props.into_iter().flat_map(|props| props.split(',')) {
+        let Some((prop_name, value)) = prop.split_once('=') else {
+            return Err(invalid(format!(
+                "property '{prop}' is not written as name=value"
+            )));
+        };
+        let (prop_name, value) = (prop_name.trim(), value.trim());
+        let name = match PropName::from_str(prop_name) {
+            Some(name) if kind.props().contains(&name) => name,
+            _ => return Err(invalid(format!("no property '{prop_name}'"))),
+        };
+        // set ignores values the property can not have
+        let valid = PropValue::from_str(value)
+            .filter(|value| block.set(name, *value).get(name) == Some(*value));
+        match valid {
+            Some(value) => block = block.set(name, value),
+            None => {
+                return Err(invalid(format!(
+                    "invalid value '{value}' for property '{prop_name}'"
+                )))
+            }
+        }
+    }
+    Ok(block)
+}
+
+/// Writes a block in the format of [block_from_str], properties are only written if they differ from the default
+pub(crate) fn block_to_string(block: BlockState) -> String {
+    let kind = block.to_kind();
+    let default = BlockState::from_kind(kind);
+    let props = kind
+        .props()
+        .iter()
+        .filter_map(|name| {
+            let value = block.get(*name)?;
+            (default.get(*name) != Some(value))
+                .then(|| format!("{}={}", name.to_str(), value.to_str()))
         })
+        .collect::<Vec<_>>();
+    if props.is_empty() {
+        kind.to_str().to_string()
+    } else {
+        format!("{}[{}]", kind.to_str(), props.join(","))
+    }
+}
+
+pub(crate) fn layers_from_strs(
+    layers: Vec<(u16, String)>,
+) -> Result<Vec<(u16, BlockState)>, TerrainConfigError> {
+    let mut surface_layers = vec![];
+    for (amt, layer) in layers {
+        surface_layers.push(match block_from_str(&layer) {
+            Ok(block) => (amt, block),
+            Err(e) => return Err(e),
+        });
+    }
+    Ok(surface_layers)
+}
+
+/// Errors on surface layers of 0 blocks, `biome` is the biome of the layers if they are not the default ones
+fn check_layers(
+    layers: &[(u16, BlockState)],
+    biome: Option<&str>,
+) -> Result<(), TerrainConfigError> {
+    match layers.iter().position(|(amount, _)| *amount == 0) {
+        Some(index) => Err(TerrainConfigError::EmptySurfaceLayer {
+            index,
+            biome: biome.map(str::to_string),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Surface heights of a generated chunk.  
+/// Valence does not send heightmaps to clients, so they are kept here for systems that need them
+#[derive(Clone, Debug)]
+pub struct ChunkHeightmap([u32; 256]);
+
+impl ChunkHeightmap {
+    /// One above the highest non-air block of the column including fluids, 0 if the column is empty.  
+    /// x, z and the height are local to the chunk, add [min_y](TerrainGenConfig::min_y) for world heights
+    pub fn get(&self, x: u32, z: u32) -> u32 {
+        self.0[(x + z * 16) as usize]
+    }
+
+    pub(crate) fn set(&mut self, x: u32, z: u32, height: u32) {
+        self.0[(x + z * 16) as usize] = height;
+    }
+
+    /// Scans the columns of a chunk that was not generated, like chunks loaded from a world
+    pub(crate) fn from_chunk(chunk: &UnloadedChunk) -> Self {
+        let mut heightmap = Self::default();
+        for x in 0..16 {
+            for z in 0..16 {
+                let top = (0..chunk.height())
+                    .rev()
+                    .find(|y| !chunk.block_state(x, *y, z).is_air())
+                    .map_or(0, |y| y + 1);
+                heightmap.set(x, z, top);
+            }
+        }
+        heightmap
+    }
+}
+
+impl Default for ChunkHeightmap {
+    fn default() -> Self {
+        Self([0; 256])
     }
 }
 
-fn block_from_str(s: &str) -> Result<BlockState, String> {
-    match BlockKind::from_str(&s) {
-        Some(block) => Ok(BlockState::from_kind(block)),
-        None => Err(format!("Invalid block: '{}'", s)),
+/// A chunk that could not be generated because the worker panicked.  
+/// Chunks that are still wanted are generated again a few times, after that the chunk is removed from the pending
+/// chunks and will be retried once it comes into view again
+#[derive(Clone, Debug)]
+pub struct ChunkGenError {
+    pub pos: ChunkPos,
+    pub message: String,
+}
+
+impl Display for ChunkGenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "failed to generate chunk at {} {}: {}",
+            self.pos.x, self.pos.z, self.message
+        )
     }
 }
 
-struct ChunkWorkerState {
-    block: BlockState,
-    surface_layers: Vec<(u16, BlockState)>,
-    noise: DynNoise,
-    sender: Sender<(ChunkPos, UnloadedChunk)>,
-    receiver: Receiver<ChunkPos>,
-    height: u32,
+/// Amount of chunks used for the rolling average in [TerrainStats]
+const STATS_WINDOW: usize = 256;
+
+/// Generation statistics of a [TerrainGenerator], useful for finding slow noise configs
+#[derive(Default)]
+pub struct TerrainStats {
+    recent: VecDeque<Duration>,
+    recent_total: Duration,
+    generated: u64,
+    tick_inserted: u32,
+    tick_insert_time: Duration,
+    cache_hits: u64,
+    cache_misses: u64,
+    cache_bytes: usize,
+}
+
+impl TerrainStats {
+    fn record(&mut self, duration: Duration) {
+        if self.recent.len() == STATS_WINDOW {
+            self.recent_total -= self.recent.pop_front().unwrap();
+        }
+        self.recent.push_back(duration);
+        self.recent_total += duration;
+        self.generated += 1;
+    }
+
+    /// Average generation time of the last few hundred chunks, zero if none were generated
+    pub fn avg_chunk_time(&self) -> Duration {
+        if self.recent.is_empty() {
+            Duration::ZERO
+        } else {
+            self.recent_total / self.recent.len() as u32
+        }
+    }
+
+    /// Time it took to generate the most recent chunk
+    pub fn last_chunk_time(&self) -> Option<Duration> {
+        self.recent.back().copied()
+    }
+
+    /// Total amount of chunks generated since the generator was created
+    pub fn chunks_generated(&self) -> u64 {
+        self.generated
+    }
+
+    /// Chunks inserted into the layer during the last tick
+    pub fn last_tick_inserted(&self) -> u32 {
+        self.tick_inserted
+    }
+
+    /// Time spent inserting finished chunks into the layer during the last tick
+    pub fn last_tick_insert_time(&self) -> Duration {
+        self.tick_insert_time
+    }
+
+    /// Chunks taken from the chunk cache instead of being generated, see [TerrainGenConfig::chunk_cache]
+    pub fn cache_hits(&self) -> u64 {
+        self.cache_hits
+    }
+
+    /// Chunks that were generated while the chunk cache is enabled, as they were not in it
+    pub fn cache_misses(&self) -> u64 {
+        self.cache_misses
+    }
+
+    /// Memory used by the chunks in the chunk cache in bytes, see [ChunkCacheConfig::bytes]
+    pub fn cache_bytes(&self) -> usize {
+        self.cache_bytes
+    }
+}
+
+/// Overrides the render distance of the [TerrainGenerator] for a single client.  
+/// The distance is still limited by the render distance of the client
+#[derive(Component, Clone, Copy, Debug)]
+pub struct TerrainRenderDist(pub u8);
+
+/// Generates chunks on the calling thread, for tools and tests that need chunks without a layer.  
+/// Generates the same chunks as a [TerrainGenerator] with the same config. Without a layer the height is not taken
+/// from a dimension, use [for_layer](TerrainGenConfig::for_layer) or set it in the config
+pub struct ChunkGenerator(ChunkWorkerState);
+
+impl ChunkGenerator {
+    pub fn new(config: TerrainGenConfig, biomes: &BiomeRegistry) -> Self {
+        Self(ChunkWorkerState::new(config, biomes))
+    }
+
+    /// Generates a chunk including the features reaching into it from its neighbours
+    pub fn generate(&self, pos: ChunkPos) -> UnloadedChunk {
+        self.0.generate_inline(pos).0
+    }
+
+    /// Like [generate](Self::generate), also giving the heightmap of the chunk
+    pub fn generate_with_heightmap(&self, pos: ChunkPos) -> (UnloadedChunk, ChunkHeightmap) {
+        self.0.generate_inline(pos)
+    }
+}
+
+/// Shape of the area queued by [TerrainGenerator::pregenerate]
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum PregenShape {
+    Square,
+    Circle,
+}
+
+/// Sent when chunks queued by [TerrainGenerator::pregenerate] are done, failed chunks are counted as done
+#[derive(Event, Clone, Copy, Debug)]
+pub struct PregenProgress {
+    /// Layer of the [TerrainGenerator]
+    pub layer: Entity,
+    pub generated: u32,
+    pub total: u32,
+}
+
+/// Sent when a chunk is queued to be generated
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ChunkQueued {
+    /// Layer of the [TerrainGenerator]
+    pub layer: Entity,
+    pub pos: ChunkPos,
+}
+
+/// Sent when a chunk is inserted into the layer, including chunks loaded from an Anvil world and edited chunks
+#[derive(Event, Clone, Copy, Debug)]
+pub struct ChunkGenerated {
+    /// Layer of the [TerrainGenerator]
+    pub layer: Entity,
+    pub pos: ChunkPos,
+}
+
+/// Sent when the workers of a [TerrainGenerator] can not be started, like when its config has other dimensions than
+/// its layer. The generator does not generate anything until it is [reloaded](TerrainGenerator::reload)
+#[derive(Event, Clone, Debug)]
+pub struct TerrainStartFailed {
+    /// Layer of the [TerrainGenerator]
+    pub layer: Entity,
+    pub error: TerrainConfigError,
+}
+
+/// Returned by [TerrainGenerator::request_chunk], can be polled every tick or awaited
+pub struct ChunkRequest {
+    pos: ChunkPos,
+    receiver: Receiver<Result<(), ChunkGenError>>,
+    result: Option<Result<(), ChunkGenError>>,
+}
+
+impl ChunkRequest {
+    pub fn pos(&self) -> ChunkPos {
+        self.pos
+    }
+
+    /// Result once the chunk is in the layer or failed to generate, None while it is still generating
+    pub fn poll(&mut self) -> Option<Result<(), ChunkGenError>> {
+        if self.result.is_none() {
+            self.result = match self.receiver.try_recv() {
+                Ok(result) => Some(result),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => Some(Err(self.dropped())),
+            };
+        }
+        self.result.clone()
+    }
+
+    /// Waits until the chunk is in the layer or failed to generate
+    pub async fn wait(self) -> Result<(), ChunkGenError> {
+        if let Some(result) = self.result {
+            return result;
+        }
+        match self.receiver.recv_async().await {
+            Ok(result) => result,
+            Err(_) => Err(self.dropped()),
+        }
+    }
+
+    /// Error for requests of a generator that was reloaded or removed
+    fn dropped(&self) -> ChunkGenError {
+        ChunkGenError {
+            pos: self.pos,
+            message: "the terrain generator was reloaded".into(),
+        }
+    }
+}
+
+/// Chunks from `min` to `max`, inclusive
+fn chunk_region(min: ChunkPos, max: ChunkPos) -> impl Iterator<Item = ChunkPos> {
+    let xs = min.x.min(max.x)..=min.x.max(max.x);
+    let zs = min.z.min(max.z)..=min.z.max(max.z);
+    xs.flat_map(move |x| zs.clone().map(move |z| ChunkPos::new(x, z)))
+}
+
+/// Chunks within `radius` of `center`
+pub(crate) fn pregen_area(
+    center: ChunkPos,
+    radius: u32,
+    shape: PregenShape,
+) -> impl Iterator<Item = ChunkPos> {
+    let radius = radius as i32;
+    (-radius..=radius)
+        .flat_map(move |dx| (-radius..=radius).map(move |dz| (dx, dz)))
+        .filter(move |(dx, dz)| {
+            shape == PregenShape::Square || dx * dx + dz * dz <= radius * radius
+        })
+        .map(move |(dx, dz)| ChunkPos::new(center.x + dx, center.z + dz))
 }
 
 #[derive(Component)]
@@ -95,35 +1115,575 @@ pub struct TerrainGenerator {
     /// Chunks that need to be generated. Chunks without a priority have already
     /// been sent to the thread pool.
     pending: HashMap<ChunkPos, Option<u64>>,
-    sender: Sender<ChunkPos>,
-    receiver: Receiver<(ChunkPos, UnloadedChunk)>,
+    /// Pending chunks queued by [regenerate](Self::regenerate) or [reload_swap](Self::reload_swap) to replace a loaded
+    /// chunk, these are not cancelled when they leave every view
+    replacing: HashSet<ChunkPos>,
+    /// Chunks from [regenerate](Self::regenerate) that are generated even if they are saved in the Anvil world, until
+    /// the generated chunk is saved over it
+    regenerated: HashSet<ChunkPos>,
+    jobs: Arc<JobQueue>,
+    receiver: Receiver<GeneratedChunk>,
     render_dist: u8,
     needs_reload: bool,
+    stats: TerrainStats,
+    errors: Vec<ChunkGenError>,
+    /// Times chunks that are still pending failed to generate, see [chunk_failed](Self::chunk_failed)
+    retries: HashMap<ChunkPos, u32>,
+    paused: bool,
+    pause_on_error: bool,
+    /// Heightmaps of the chunks currently in the layer
+    heightmaps: HashMap<ChunkPos, ChunkHeightmap>,
+    /// Workers are started by the plugin, as biomes can only be looked up once the registry is available
+    unstarted: Option<UnstartedWorkers>,
+    /// Pool the jobs are submitted to once the workers are started
+    started: Option<(TerrainWorkerPool, Arc<WorkerQueue>)>,
+    /// Incremented on reload, results of other epochs were generated with an old config and are dropped
+    epoch: u64,
+    /// If chunks are generated in two passes, see [proto]
+    two_pass: bool,
+    /// Chunks that have been sent to the workers for their first pass
+    terrain_requested: HashSet<ChunkPos>,
+    /// Pending chunks waiting for the surfaces of their neighbours
+    protos: HashMap<ChunkPos, ProtoChunk>,
+    /// Surfaces of chunks needed for the second pass of nearby chunks
+    surfaces: HashMap<ChunkPos, Arc<SurfaceInfo>>,
+    /// If unloaded chunks are saved to the Anvil world
+    save_on_unload: bool,
+    /// Loaded chunks that were read from the Anvil world, these are not saved again
+    from_anvil: HashSet<ChunkPos>,
+    /// Unloaded chunks that are being saved or checked for edits, these are not loaded again until that is done
+    unloading: HashSet<ChunkPos>,
+    keep_edits: bool,
+    /// Hashes of the blocks of loaded chunks when they were generated, used to find edited chunks once they are unloaded
+    hashes: HashMap<ChunkPos, u64>,
+    /// Unloaded chunks that were changed after they were generated, these are inserted again instead of generated
+    edited: HashMap<ChunkPos, (UnloadedChunk, ChunkHeightmap)>,
+    /// Unloaded chunks that were not changed, if the config has a `chunk_cache`
+    cache: Option<ChunkCache>,
+    /// Copy of the noise of the workers for [height_at](Self::height_at)
+    shape: Arc<TerrainShape>,
+    /// Chunks that stay loaded without viewers
+    pinned: HashSet<ChunkPos>,
+    /// [SpawnChunks] of the config, these stay pinned
+    spawn_chunks: HashSet<ChunkPos>,
+    world_border: Option<WorldBorder>,
+    /// Chunks that are never unloaded or generated again once they are loaded, see [protect](Self::protect)
+    protected: HashSet<ChunkPos>,
+    /// Ticks counted by [remove_unviewed_chunks]
+    tick: u64,
+    /// Loaded chunks without viewers and the tick they were last viewed, they are unloaded after `unload_delay` ticks
+    unviewed_since: HashMap<ChunkPos, u64>,
+    unload_delay: u32,
+    /// Pregenerated chunks that are not done yet
+    pregen_remaining: HashSet<ChunkPos>,
+    pregen_total: u32,
+    pregen_done: u32,
+    /// If a [PregenProgress] event should be sent
+    pregen_changed: bool,
+    /// Senders of the [ChunkRequest]s waiting for each chunk
+    requests: HashMap<ChunkPos, Vec<Sender<Result<(), ChunkGenError>>>>,
+    /// Chunks queued since the last [ChunkQueued] events were sent
+    queued: Vec<ChunkPos>,
+    /// Views of the clients in the layer this tick, pending chunks outside all of them are cancelled
+    views: Vec<ChunkView>,
+    /// Max chunks inserted into the layer per tick, the other finished chunks are inserted in the next ticks
+    insert_budget: Option<u32>,
+    queue_capacity: Option<usize>,
+    queue_overflow: QueueOverflow,
+    /// Viewed chunks that were rejected or dropped while the queue was full, queued again once there is room
+    deferred: HashSet<ChunkPos>,
+    /// Config the generator was created with, kept for [set_seed](Self::set_seed)
+    config: TerrainGenConfig,
+}
+
+/// Times a chunk is generated again after a worker panicked, before the error is given to its requests
+const MAX_RETRIES: u32 = 2;
+
+/// Samples taken by [warn_flat]
+const RANGE_SAMPLES: usize = 1024;
+
+/// Warns when the noise never reaches the heights columns are clamped to, as that gives a flat world without errors.
+/// Density can still shape the terrain, so configs with it are not checked
+fn warn_flat(config: &TerrainGenConfig) {
+    if config.mode != TerrainMode::Heightmap || config.density.is_some() {
+        return;
+    }
+    let noise = seed_tree(config.seed, config.height_noise(), NOISE_SALT);
+    let stats = noise.estimate_range(RANGE_SAMPLES);
+    let min = config.bottom_y() as f64 + 1.0;
+    let max = (config.bottom_y() + config.chunk_height() as i32) as f64 - 1.0;
+    if stats.outside(min, max) {
+        tracing::warn!(
+            "the noise ranges from {:.1} to {:.1}, outside of the heights {min} to {max}, so the terrain will be flat",
+            stats.min,
+            stats.max
+        );
+    }
+}
+
+struct UnstartedWorkers {
+    config: TerrainGenConfig,
+    sender: Sender<GeneratedChunk>,
 }
 
 impl TerrainGenerator {
-    /// Set render_dist to 0 to always use client render distance
+    /// Set render_dist to 0 to always use client render distance.  
+    /// Can be overridden per client with [TerrainRenderDist].  
+    /// Panics if the config is [invalid](TerrainGenConfig::validate), see [try_new](Self::try_new)
     pub fn new(config: TerrainGenConfig, render_dist: u8) -> Self {
+        Self::try_new(config, render_dist).unwrap_or_else(|e| panic!("invalid terrain config: {e}"))
+    }
+
+    /// Like [new](Self::new), but returns the error if the config is invalid
+    pub fn try_new(config: TerrainGenConfig, render_dist: u8) -> Result<Self, TerrainConfigError> {
+        config.validate()?;
         let (finished_sender, finished_receiver) = flume::unbounded();
-        let (pending_sender, pending_receiver) = flume::unbounded();
-        let state = Arc::new(ChunkWorkerState {
-            block: config.block,
-            surface_layers: config.surface_layers,
-            noise: config.noise.build(),
-            sender: finished_sender,
-            receiver: pending_receiver,
-            height: config.height,
-        });
-        for _ in 0..thread::available_parallelism().unwrap().get() {
-            let state = state.clone();
-            thread::spawn(move || chunk_worker(state));
-        }
-        Self {
-            pending: HashMap::new(),
-            sender: pending_sender,
+        let two_pass = !config.decorations.is_empty() || !config.structures.is_empty();
+        let save_on_unload = config.anvil.is_some() && config.anvil_save;
+        let shape = Arc::new(TerrainShape::new(&config));
+        let (queue_capacity, queue_overflow) = (config.queue_capacity, config.queue_overflow);
+        let cache = config.chunk_cache.clone().map(ChunkCache::new);
+        let unload_delay = config.unload_delay;
+        let world_border = config.world_border.clone();
+        let spawn_chunks = config
+            .spawn_chunks
+            .iter()
+            .flat_map(SpawnChunks::chunks)
+            .filter(|pos| {
+                !world_border
+                    .as_ref()
+                    .is_some_and(|border| border.rejects(*pos))
+            })
+            .collect::<HashSet<_>>();
+        // queued right away, the layer does not need any viewers for them
+        let pending = spawn_chunks.iter().map(|pos| (*pos, Some(0))).collect();
+        let queued = spawn_chunks.iter().copied().collect();
+        Ok(Self {
+            config: config.clone(),
+            pending,
+            replacing: HashSet::new(),
+            regenerated: HashSet::new(),
+            jobs: Arc::new(JobQueue::default()),
             receiver: finished_receiver,
             needs_reload: true,
             render_dist,
+            stats: TerrainStats::default(),
+            errors: vec![],
+            retries: HashMap::new(),
+            paused: false,
+            pause_on_error: false,
+            heightmaps: HashMap::new(),
+            unstarted: Some(UnstartedWorkers {
+                config,
+                sender: finished_sender,
+            }),
+            started: None,
+            epoch: 0,
+            two_pass,
+            terrain_requested: HashSet::new(),
+            protos: HashMap::new(),
+            surfaces: HashMap::new(),
+            save_on_unload,
+            from_anvil: HashSet::new(),
+            unloading: HashSet::new(),
+            keep_edits: false,
+            hashes: HashMap::new(),
+            edited: HashMap::new(),
+            cache,
+            shape,
+            pinned: spawn_chunks.clone(),
+            spawn_chunks,
+            world_border,
+            protected: HashSet::new(),
+            tick: 0,
+            unviewed_since: HashMap::new(),
+            unload_delay,
+            pregen_remaining: HashSet::new(),
+            pregen_total: 0,
+            pregen_done: 0,
+            pregen_changed: false,
+            requests: HashMap::new(),
+            queued,
+            views: vec![],
+            insert_budget: None,
+            queue_capacity,
+            queue_overflow,
+            deferred: HashSet::new(),
+        })
+    }
+
+    /// Heightmap of a generated chunk, only available while the chunk is loaded
+    pub fn heightmap(&self, pos: ChunkPos) -> Option<&ChunkHeightmap> {
+        self.heightmaps.get(&pos)
+    }
+
+    /// Evaluates the noise of a column without generating it, giving one above the highest solid block.  
+    /// Features, fluids and chunks loaded from an Anvil world are not taken into account. The height is a world height,
+    /// [min_y](TerrainGenConfig::min_y) if the column is empty
+    pub fn height_at(&self, x: i32, z: i32) -> i32 {
+        self.shape.height_at(x, z)
+    }
+
+    /// Raw value of the `noise` of the config at a column, before it is interpolated and clamped to the height
+    pub fn noise_at(&self, x: i32, z: i32) -> f64 {
+        self.shape.noise_at(x, z)
+    }
+
+    /// Biome of a column without generating it.  
+    /// `None` if the config has no biomes, or the workers have not been started by the plugin yet
+    pub fn biome_at(&self, x: i32, z: i32) -> Option<BiomeId> {
+        self.worker_state()?.biome_at(x, z)
+    }
+
+    /// Top block of a column before features are placed, or the fluid if the column is below the sea level.  
+    /// `None` if the column is empty, or the workers have not been started by the plugin yet
+    pub fn surface_block_at(&self, x: i32, z: i32) -> Option<BlockState> {
+        self.worker_state()?.surface_block_at(x, z)
+    }
+
+    /// If the workers have been started by the plugin
+    pub(crate) fn started(&self) -> bool {
+        self.started.is_some()
+    }
+
+    /// Draws a map on the workers, the receiver gets the colors once it is drawn
+    pub(crate) fn draw_map(&self, center: [i32; 2], scale: i32) -> Receiver<Vec<u8>> {
+        let (sender, receiver) = flume::bounded(1);
+        self.send_job(
+            0,
+            Job::Map(Box::new(MapJob {
+                center,
+                scale,
+                sender,
+            })),
+        );
+        receiver
+    }
+
+    fn worker_state(&self) -> Option<&ChunkWorkerState> {
+        self.started.as_ref().map(|(_, queue)| &queue.state)
+    }
+
+    /// Chunks that are queued or being generated
+    pub fn pending_chunks(&self) -> usize {
+        self.pending.len()
+    }
+
+    pub fn stats(&self) -> &TerrainStats {
+        &self.stats
+    }
+
+    /// Returns all errors since last call, errors are also logged by the plugin
+    pub fn take_errors(&mut self) -> Vec<ChunkGenError> {
+        std::mem::take(&mut self.errors)
+    }
+
+    pub fn paused(&self) -> bool {
+        self.paused
+    }
+
+    /// While paused no new chunks are sent to the workers, chunks are still queued
+    pub fn set_paused(&mut self, paused: bool) {
+        self.paused = paused;
+    }
+
+    /// If set, generation is paused when a chunk fails to generate
+    pub fn set_pause_on_error(&mut self, pause_on_error: bool) {
+        self.pause_on_error = pause_on_error;
+    }
+
+    /// Limits how many generated chunks are inserted into the layer each tick, to spread out the cost of inserting
+    /// many chunks at once, like when joining. None by default, inserting all finished chunks right away
+    pub fn set_insert_budget(&mut self, budget: Option<u32>) {
+        self.insert_budget = budget;
+    }
+
+    /// If set, chunks that were changed are kept in memory when unloaded, so the changes are not lost by generating
+    /// them again. Off by default, as every unloaded chunk is then hashed by a worker to find the changes, and the
+    /// changed chunks stay in memory until they are loaded again. Generated chunks that are saved to Anvil keep their
+    /// changes in the world instead
+    pub fn set_keep_edits(&mut self, keep_edits: bool) {
+        self.keep_edits = keep_edits;
+        if !keep_edits {
+            self.edited.clear();
+        }
+    }
+
+    /// Queues the chunks within `radius` of `center` without any clients viewing them, progress is sent as
+    /// [PregenProgress] events.  
+    /// The chunks stay loaded until [release_pregenerated](Self::release_pregenerated) is called, reloading also
+    /// cancels pregeneration
+    pub fn pregenerate(&mut self, center: ChunkPos, radius: u32, shape: PregenShape) {
+        for pos in pregen_area(center, radius, shape) {
+            if self.outside_border(pos) || !self.pinned.insert(pos) {
+                continue;
+            }
+            self.pregen_total += 1;
+            self.pregen_changed = true;
+            // heightmaps are kept for every loaded chunk
+            if self.heightmaps.contains_key(&pos) {
+                self.pregen_done += 1;
+                continue;
+            }
+            self.pregen_remaining.insert(pos);
+            let dist = center.distance_squared(pos);
+            match self.pending.entry(pos) {
+                Entry::Occupied(mut oe) => {
+                    if let Some(priority) = oe.get_mut() {
+                        *priority = (*priority).min(dist);
+                    }
+                }
+                Entry::Vacant(ve) => {
+                    ve.insert(Some(dist));
+                    self.queued.push(pos);
+                }
+            }
+        }
+    }
+
+    /// Lets pregenerated and requested chunks unload once they have no viewers, spawn chunks stay loaded
+    pub fn release_pregenerated(&mut self) {
+        let spawn_chunks = &self.spawn_chunks;
+        self.pinned.retain(|pos| spawn_chunks.contains(pos));
+    }
+
+    /// Queues a chunk without any clients viewing it, the chunk stays loaded until
+    /// [release_chunk](Self::release_chunk) is called.  
+    /// The returned request is done once the chunk is in the layer, right away if it already is. It fails right away
+    /// if the chunk is outside of a [WorldBorder] that leaves the outside empty
+    pub fn request_chunk(&mut self, pos: ChunkPos) -> ChunkRequest {
+        let (sender, receiver) = flume::bounded(1);
+        if self.outside_border(pos) {
+            let _ = sender.send(Err(ChunkGenError {
+                pos,
+                message: "outside of the world border".into(),
+            }));
+            return ChunkRequest {
+                pos,
+                receiver,
+                result: None,
+            };
+        }
+        self.pinned.insert(pos);
+        if self.heightmaps.contains_key(&pos) {
+            let _ = sender.send(Ok(()));
+        } else {
+            if let Entry::Vacant(ve) = self.pending.entry(pos) {
+                ve.insert(Some(0));
+                self.queued.push(pos);
+            }
+            self.requests.entry(pos).or_default().push(sender);
+        }
+        ChunkRequest {
+            pos,
+            receiver,
+            result: None,
+        }
+    }
+
+    /// Lets a chunk from [request_chunk](Self::request_chunk) or [pregenerate](Self::pregenerate) unload once it has
+    /// no viewers, unless it is one of the [SpawnChunks] of the config
+    pub fn release_chunk(&mut self, pos: ChunkPos) {
+        if !self.spawn_chunks.contains(&pos) {
+            self.pinned.remove(&pos);
+        }
+    }
+
+    /// Generates the chunks from `min` to `max` (inclusive) again, discarding what they contain now like changes made
+    /// in game. Loaded chunks stay in the layer until they are replaced, the others are generated when they are
+    /// viewed again.  
+    /// Chunks saved to the Anvil world of the config are generated as well, and saved over it if the config saves.  
+    /// Protected chunks are skipped
+    pub fn regenerate(&mut self, min: ChunkPos, max: ChunkPos) {
+        for pos in chunk_region(min, max) {
+            if self.protected.contains(&pos) {
+                continue;
+            }
+            self.edited.remove(&pos);
+            if let Some(cache) = &mut self.cache {
+                cache.remove(pos);
+            }
+            if self.config.anvil.is_some() {
+                self.regenerated.insert(pos);
+            }
+            if self.heightmaps.contains_key(&pos) && !self.pending.contains_key(&pos) {
+                // the old chunk is replaced once the new one is inserted, see send_recv_chunks
+                self.pending.insert(pos, Some(u64::MAX));
+                self.replacing.insert(pos);
+                self.queued.push(pos);
+            }
+        }
+    }
+
+    /// Where a chunk is taken from when it is sent to the workers, see [regenerate](Self::regenerate)
+    fn chunk_source(&self, pos: ChunkPos) -> ChunkSource {
+        if self.regenerated.contains(&pos) {
+            ChunkSource::Generated
+        } else {
+            ChunkSource::Saved
+        }
+    }
+
+    /// Protects the chunks from `min` to `max` (inclusive), like spawn or the builds of players.  
+    /// Once protected chunks are loaded they stay in the layer without viewers and are kept as they are when the
+    /// generator is reloaded or regenerated. Chunks that are not loaded yet are generated like any other
+    pub fn protect(&mut self, min: ChunkPos, max: ChunkPos) {
+        for pos in chunk_region(min, max) {
+            self.protected.insert(pos);
+            // loaded chunks are not replaced by one that is still being generated
+            if self.heightmaps.contains_key(&pos) {
+                self.pending.remove(&pos);
+            }
+        }
+    }
+
+    /// Lets the chunks from `min` to `max` (inclusive) unload and be generated again, see [protect](Self::protect)
+    pub fn unprotect(&mut self, min: ChunkPos, max: ChunkPos) {
+        for pos in chunk_region(min, max) {
+            self.protected.remove(&pos);
+        }
+    }
+
+    /// If a chunk is [protected](Self::protect)
+    pub fn is_protected(&self, pos: ChunkPos) -> bool {
+        self.protected.contains(&pos)
+    }
+
+    /// If the chunk is outside of a [WorldBorder] that leaves the outside empty, these chunks are never queued
+    fn outside_border(&self, pos: ChunkPos) -> bool {
+        self.world_border
+            .as_ref()
+            .is_some_and(|border| border.rejects(pos))
+    }
+
+    /// Queues a chunk in the view of a client `dist` away from it, following the overflow policy once the queue is full
+    fn queue_viewed(&mut self, pos: ChunkPos, dist: u64) {
+        if self.outside_border(pos) {
+            return;
+        }
+        if let Entry::Occupied(mut oe) = self.pending.entry(pos) {
+            if let Some(priority) = oe.get_mut() {
+                *priority = (*priority).min(dist);
+            }
+            return;
+        }
+        let full = self
+            .queue_capacity
+            .is_some_and(|capacity| self.pending.len() >= capacity);
+        if full {
+            match self.queue_overflow {
+                QueueOverflow::Block => {}
+                QueueOverflow::Reject => {
+                    self.deferred.insert(pos);
+                    return;
+                }
+                QueueOverflow::DropFarthest => {
+                    // chunks that were sent already, are pinned or replace a loaded chunk are not dropped
+                    let farthest = self
+                        .pending
+                        .iter()
+                        .filter(|(pos, _)| {
+                            !self.pinned.contains(pos) && !self.replacing.contains(pos)
+                        })
+                        .filter_map(|(pos, priority)| Some(((*priority)?, *pos)))
+                        .max_by_key(|(priority, pos)| (*priority, pos.x, pos.z));
+                    match farthest {
+                        Some((priority, farthest)) if priority > dist => {
+                            self.pending.remove(&farthest);
+                            self.deferred.insert(farthest);
+                        }
+                        _ => {
+                            self.deferred.insert(pos);
+                            return;
+                        }
+                    }
+                }
+            }
+        }
+        self.deferred.remove(&pos);
+        self.pending.insert(pos, Some(dist));
+        self.queued.push(pos);
+    }
+
+    /// Queues the [deferred](Self::deferred) chunks that are still viewed and not loaded again, closest first, as long
+    /// as there is room in the queue
+    fn queue_deferred(&mut self) {
+        let mut deferred = std::mem::take(&mut self.deferred)
+            .into_iter()
+            .filter(|pos| !self.heightmaps.contains_key(pos))
+            .filter_map(|pos| Some((view_priority(&self.views, pos)?, pos)))
+            .collect::<Vec<_>>();
+        deferred.sort_unstable_by_key(|(priority, pos)| (*priority, pos.x, pos.z));
+        for (priority, pos) in deferred {
+            self.queue_viewed(pos, priority);
+        }
+    }
+
+    /// Takes the pending chunks that were not sent to the workers yet, sorted by priority and then position so the
+    /// order does not depend on the hashmap. Chunks that are still unloading are left for later ticks
+    fn take_to_send(&mut self) -> Vec<(u64, ChunkPos)> {
+        let mut to_send = vec![];
+        for (pos, priority) in &mut self.pending {
+            // priorities from when the chunk was queued are replaced by the current ones of all viewers,
+            // pinned chunks keep theirs
+            if let (Some(priority), Some(current), false) = (
+                priority.as_mut(),
+                view_priority(&self.views, *pos),
+                self.pinned.contains(pos),
+            ) {
+                *priority = current;
+            }
+            // chunks are loaded again once they are done unloading, neighbours as well for their surfaces
+            let unloading = if self.two_pass {
+                neighborhood(*pos).any(|pos| self.unloading.contains(&pos))
+            } else {
+                self.unloading.contains(pos)
+            };
+            if unloading {
+                continue;
+            }
+            if let Some(pri) = priority.take() {
+                to_send.push((pri, *pos));
+            }
+        }
+        to_send.sort_unstable_by_key(|(pri, pos)| (*pri, pos.x, pos.z));
+        to_send
+    }
+
+    /// Called when a worker panicked while generating a chunk. Chunks that are still pending are queued again up to
+    /// [MAX_RETRIES] times, so players standing still are not left with a hole in the world
+    fn chunk_failed(&mut self, pos: ChunkPos, message: String) {
+        let error = ChunkGenError { pos, message };
+        tracing::error!("{error}");
+        let retries = self.retries.entry(pos).or_default();
+        *retries += 1;
+        if *retries <= MAX_RETRIES && self.pending.contains_key(&pos) {
+            self.pending.insert(pos, Some(0));
+        } else {
+            self.retries.remove(&pos);
+            self.pending.remove(&pos);
+            self.chunk_done(pos, Some(&error));
+        }
+        self.errors.push(error);
+        if self.pause_on_error {
+            self.paused = true;
+        }
+    }
+
+    /// Called when a chunk is no longer pending
+    fn chunk_done(&mut self, pos: ChunkPos, error: Option<&ChunkGenError>) {
+        self.retries.remove(&pos);
+        self.replacing.remove(&pos);
+        // the generated chunk was saved by the worker, so it can be loaded from now on
+        if self.save_on_unload && error.is_none() {
+            self.regenerated.remove(&pos);
+        }
+        if self.pregen_remaining.remove(&pos) {
+            self.pregen_done += 1;
+            self.pregen_changed = true;
+        }
+        for sender in self.requests.remove(&pos).into_iter().flatten() {
+            let _ = sender.send(error.map_or(Ok(()), |error| Err(error.clone())));
         }
     }
 
@@ -136,54 +1696,438 @@ impl TerrainGenerator {
         self.needs_reload = true;
     }
 
+    /// Replaces the config, panics if it is invalid like [new](Self::new).  
+    /// [Protected](Self::protect) chunks stay protected, and [SpawnChunks] that are still loaded are kept
     pub fn reload(&mut self, config: TerrainGenConfig) {
+        let pause_on_error = self.pause_on_error;
+        let keep_edits = self.keep_edits;
+        let insert_budget = self.insert_budget;
+        let epoch = self.epoch + 1;
+        let protected = std::mem::take(&mut self.protected);
+        let mut heightmaps = std::mem::take(&mut self.heightmaps);
+        let mut hashes = std::mem::take(&mut self.hashes);
+        let mut from_anvil = std::mem::take(&mut self.from_anvil);
+        let regenerated = std::mem::take(&mut self.regenerated);
         *self = Self::new(config, self.render_dist);
+        // protected and spawn chunks stay loaded, so the generator still needs to know about them
+        let kept = |pos: &ChunkPos| protected.contains(pos) || self.spawn_chunks.contains(pos);
+        heightmaps.retain(|pos, _| kept(pos));
+        hashes.retain(|pos, _| kept(pos));
+        from_anvil.retain(|pos| kept(pos));
+        self.pause_on_error = pause_on_error;
+        self.keep_edits = keep_edits;
+        self.insert_budget = insert_budget;
+        self.epoch = epoch;
+        self.protected = protected;
+        self.heightmaps = heightmaps;
+        self.hashes = hashes;
+        self.from_anvil = from_anvil;
+        // regenerated chunks that were not saved yet would be loaded from the world again
+        if self.config.anvil.is_some() {
+            self.regenerated = regenerated;
+        }
+        self.skip_loaded_spawn_chunks();
+    }
+
+    /// Spawn chunks are queued by [new](Self::new), this drops the ones that are still loaded from before a reload
+    fn skip_loaded_spawn_chunks(&mut self) {
+        let heightmaps = &self.heightmaps;
+        self.pending.retain(|pos, _| !heightmaps.contains_key(pos));
+        self.queued.retain(|pos| !heightmaps.contains_key(pos));
+    }
+
+    /// World seed of the config, see [TerrainGenConfig::seed]
+    pub fn seed(&self) -> Option<u64> {
+        self.config.seed
+    }
+
+    /// [Reloads](Self::reload) the generator with the same config and a new world seed, like for starting a new world.
+    /// Like with reload, chunks already in the layer are kept until they are removed
+    pub fn set_seed(&mut self, seed: u64) {
+        let config = self.config.clone().with_seed(seed);
+        self.reload(config);
+    }
+
+    /// Like [reload](Self::reload), but only regenerates what the new config changes, see [ReloadScope].  
+    /// Chunks stay in the layer while they are repainted or generated again, so the world does not go blank.
+    /// Repainting keeps blocks that were changed in game, but chunks that are generated again lose their changes
+    pub fn reload_incremental(
+        &mut self,
+        config: TerrainGenConfig,
+        layer: &mut ChunkLayer,
+    ) -> ReloadScope {
+        let scope = self.config.reload_scope(&config);
+        if scope == ReloadScope::Unchanged {
+            return scope;
+        }
+        let old = self
+            .worker_state()
+            .map(|state| Arc::new(OldSurfaces::new(state)));
+        match (scope, old) {
+            (ReloadScope::Surfaces, Some(old)) => {
+                self.reload_loaded(config);
+                for (pos, _) in layer.chunks() {
+                    if self.protected.contains(&pos) {
+                        continue;
+                    }
+                    let old = old.clone();
+                    self.send_job(0, Job::Repaint(Box::new(RepaintJob { pos, old })));
+                }
+            }
+            _ => self.reload_swap(config, layer),
+        }
+        scope
+    }
+
+    /// Like [reload](Self::reload), but the chunks of the layer stay visible until the workers replace each of them
+    /// with its new version, so players do not fall through the world.  
+    /// Chunks that were changed in game are replaced as well
+    pub fn reload_swap(&mut self, config: TerrainGenConfig, layer: &ChunkLayer) {
+        self.reload_loaded(config);
+        for (pos, _) in layer.chunks() {
+            if self.protected.contains(&pos) {
+                continue;
+            }
+            // the old chunk is replaced once the new one is inserted, see send_recv_chunks
+            self.pending.insert(pos, Some(u64::MAX));
+            self.replacing.insert(pos);
+            self.queued.push(pos);
+        }
+    }
+
+    /// Reloads the config with the generator still knowing about the chunks that are in the layer
+    fn reload_loaded(&mut self, config: TerrainGenConfig) {
+        let heightmaps = std::mem::take(&mut self.heightmaps);
+        let hashes = std::mem::take(&mut self.hashes);
+        let edited = std::mem::take(&mut self.edited);
+        let from_anvil = std::mem::take(&mut self.from_anvil);
+        self.reload(config);
+        self.heightmaps = heightmaps;
+        self.hashes = hashes;
+        self.edited = edited;
+        self.from_anvil = from_anvil;
+        self.skip_loaded_spawn_chunks();
     }
+
+    /// Reloads the config in one of the ways of [ReloadMode]
+    pub fn reload_with(
+        &mut self,
+        config: TerrainGenConfig,
+        mode: ReloadMode,
+        layer: &mut ChunkLayer,
+    ) {
+        match mode {
+            ReloadMode::Clear => {
+                let removed = layer
+                    .chunks()
+                    .map(|(pos, _)| pos)
+                    .filter(|pos| !self.protected.contains(pos))
+                    .collect::<Vec<_>>();
+                for pos in removed {
+                    layer.remove_chunk(pos);
+                    self.heightmaps.remove(&pos);
+                }
+                self.reload(config);
+            }
+            ReloadMode::KeepEdits => self.reload_keep_edits(config, layer),
+            ReloadMode::Swap => self.reload_swap(config, layer),
+            ReloadMode::Incremental => {
+                self.reload_incremental(config, layer);
+            }
+        }
+    }
+
+    /// Jobs sent before the workers are started are submitted to the pool once they are.  
+    /// Jobs with a lower priority are taken first
+    fn send_job(&self, priority: u64, job: Job) {
+        self.jobs.push(priority, job);
+        if let Some((pool, queue)) = &self.started {
+            pool.submit(queue);
+        }
+    }
+
+    /// Like [reload](Self::reload), but chunks that were changed are kept instead of being generated with the new config.  
+    /// This removes the chunks of the layer itself except [protected](Self::protect) ones, so the layer should not be
+    /// cleared before
+    pub fn reload_keep_edits(&mut self, config: TerrainGenConfig, layer: &mut ChunkLayer) {
+        let hashes = self.hashes.clone();
+        let edited = std::mem::take(&mut self.edited);
+        self.reload(config);
+        self.edited = edited;
+        let loaded = layer
+            .chunks()
+            .map(|(pos, _)| pos)
+            .filter(|pos| !self.protected.contains(pos))
+            .collect::<Vec<_>>();
+        for pos in loaded {
+            let chunk = layer.remove_chunk(pos).unwrap();
+            // spawn chunks were kept by reload, but are loaded again now that they are removed
+            if self.heightmaps.remove(&pos).is_some() {
+                self.hashes.remove(&pos);
+                self.from_anvil.remove(&pos);
+                self.pending.insert(pos, Some(0));
+                self.queued.push(pos);
+            }
+            // checked by the new workers, the chunk is not generated again until the check is done
+            self.unloading.insert(pos);
+            self.send_job(
+                0,
+                Job::Unload(Box::new(UnloadJob {
+                    pos,
+                    chunk,
+                    save: false,
+                    keep_edits: true,
+                    cache: None,
+                    generated_hash: hashes.get(&pos).copied(),
+                })),
+            );
+        }
+    }
+}
+
+/// Stops the workers from generating the chunks that are still queued, when the entity is despawned or on [reload](TerrainGenerator::reload).  
+/// Chunks that are waiting to be saved are still saved
+impl Drop for TerrainGenerator {
+    fn drop(&mut self) {
+        if let Some((_, queue)) = &self.started {
+            queue.cancel();
+        }
+    }
+}
+
+/// Threads that generate the chunks of every [TerrainGenerator], created by [TerrainPlugin].  
+/// To limit the threads, insert a pool made with [with_threads](Self::with_threads) before adding the plugin
+#[derive(Resource, Clone)]
+pub struct TerrainWorkerPool {
+    sender: Sender<Arc<WorkerQueue>>,
 }
 
-fn remove_unviewed_chunks(mut layers: Query<&mut ChunkLayer, With<TerrainGenerator>>) {
-    for mut layer in layers.iter_mut() {
-        layer.retain_chunks(|_, chunk| chunk.viewer_count_mut() > 0)
+impl TerrainWorkerPool {
+    /// Starts a worker for every available thread, the workers stop once every clone of the pool is dropped
+    pub fn new() -> Self {
+        let threads = thread::available_parallelism().map_or(1, |n| n.get());
+        Self::with_threads(threads, "terrain-worker")
+    }
+
+    /// Starts `threads` workers (at least one), named `name_prefix` followed by their index
+    pub fn with_threads(threads: usize, name_prefix: &str) -> Self {
+        let (sender, receiver) = flume::unbounded();
+        for i in 0..threads.max(1) {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("{name_prefix}-{i}"))
+                .spawn(move || pool_worker(receiver))
+                .expect("could not spawn terrain worker");
+        }
+        Self { sender }
+    }
+
+    /// Lets a worker take one job from the queue
+    pub(crate) fn submit(&self, queue: &Arc<WorkerQueue>) {
+        let _ = self.sender.send(queue.clone());
     }
 }
 
+impl Default for TerrainWorkerPool {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Priority of a chunk from the clients viewing it, None if no client is.  
+/// This is the squared distance to the closest viewer divided by the amount of viewers, so chunks seen by several
+/// clients are generated first
+fn view_priority(views: &[ChunkView], pos: ChunkPos) -> Option<u64> {
+    let (closest, viewers) = views
+        .iter()
+        .filter(|view| view.contains(pos))
+        .fold((u64::MAX, 0), |(closest, viewers), view| {
+            (closest.min(view.pos.distance_squared(pos)), viewers + 1)
+        });
+    (viewers > 0).then(|| closest / viewers)
+}
+
+/// Starts the workers of new generators, with the height of the layer they are on
+fn start_workers(
+    mut layers: Query<(Entity, &mut TerrainGenerator, &ChunkLayer)>,
+    biomes: Res<BiomeRegistry>,
+    pool: Res<TerrainWorkerPool>,
+    mut failed: EventWriter<TerrainStartFailed>,
+) {
+    for (entity, mut terrain_gen, layer) in layers.iter_mut() {
+        let Some(unstarted) = terrain_gen.unstarted.take() else {
+            continue;
+        };
+        // the generator is left without workers, so it does not try again every tick
+        let config = match unstarted.config.for_layer(layer) {
+            Ok(config) => config,
+            Err(error) => {
+                tracing::error!("not starting terrain generator: {error}");
+                failed.send(TerrainStartFailed {
+                    layer: entity,
+                    error,
+                });
+                continue;
+            }
+        };
+        warn_flat(&config);
+        // the noise is only built again if the layer gave the config other dimensions
+        if !terrain_gen.shape.fits(&config) {
+            terrain_gen.shape = Arc::new(TerrainShape::new(&config));
+        }
+        let queue = Arc::new(WorkerQueue::new(
+            ChunkWorkerState::with_shape(config, terrain_gen.shape.clone(), &biomes),
+            terrain_gen.epoch,
+            terrain_gen.jobs.clone(),
+            unstarted.sender,
+        ));
+        for _ in 0..queue.jobs.len() {
+            pool.submit(&queue);
+        }
+        terrain_gen.started = Some((pool.clone(), queue));
+    }
+}
+
+/// Saves the generated chunks that are still loaded or waiting to be saved, as the workers stop with the process
+fn save_on_exit(mut exit: EventReader<AppExit>, layers: Query<(&ChunkLayer, &TerrainGenerator)>) {
+    if exit.read().count() == 0 {
+        return;
+    }
+    for (layer, terrain_gen) in &layers {
+        let Some(anvil) = terrain_gen
+            .worker_state()
+            .and_then(|state| state.anvil.as_ref())
+            .filter(|_| terrain_gen.save_on_unload)
+        else {
+            continue;
+        };
+        for job in terrain_gen.jobs.take_saves() {
+            anvil.save(job.pos, &job.chunk);
+        }
+        for (pos, chunk) in layer.chunks() {
+            if !terrain_gen.from_anvil.contains(&pos) {
+                anvil.save(pos, chunk);
+            }
+        }
+        anvil.flush();
+    }
+}
+
+#[cfg_attr(feature = "trace", tracing::instrument(skip_all))]
+fn remove_unviewed_chunks(mut layers: Query<(&mut ChunkLayer, &mut TerrainGenerator)>) {
+    for (mut layer, mut terrain_gen) in layers.iter_mut() {
+        let terrain_gen = &mut *terrain_gen;
+        terrain_gen.tick += 1;
+        let tick = terrain_gen.tick;
+        // only chunks that are still loaded and unviewed are kept, the others start over once they lose their viewers
+        let since = std::mem::take(&mut terrain_gen.unviewed_since);
+        let mut unviewed = vec![];
+        for (pos, chunk) in layer.chunks_mut() {
+            if chunk.viewer_count_mut() > 0
+                || terrain_gen.pinned.contains(&pos)
+                || terrain_gen.protected.contains(&pos)
+            {
+                continue;
+            }
+            let since = since.get(&pos).copied().unwrap_or(tick);
+            if tick - since >= terrain_gen.unload_delay as u64 {
+                unviewed.push(pos);
+            } else {
+                terrain_gen.unviewed_since.insert(pos, since);
+            }
+        }
+        let removed = !unviewed.is_empty();
+        for pos in unviewed {
+            let chunk = layer.remove_chunk(pos).unwrap();
+            terrain_gen.heightmaps.remove(&pos);
+            // there is nothing left to replace, the chunk is generated again once it is viewed and a result that is
+            // still on its way is dropped
+            if terrain_gen.replacing.remove(&pos) {
+                terrain_gen.pending.remove(&pos);
+                terrain_gen.protos.remove(&pos);
+            }
+            let generated_hash = terrain_gen.hashes.remove(&pos);
+            // chunks from the world are not written back, as that would drop the data the generator does not know
+            let from_anvil = terrain_gen.from_anvil.remove(&pos);
+            let save = terrain_gen.save_on_unload && !from_anvil;
+            let keep_edits = terrain_gen.keep_edits && !save;
+            // saved chunks are loaded from the world again instead
+            let cache = terrain_gen
+                .cache
+                .as_ref()
+                .filter(|_| !terrain_gen.save_on_unload)
+                .map(ChunkCache::compression);
+            if save || keep_edits || cache.is_some() {
+                terrain_gen.unloading.insert(pos);
+                terrain_gen.send_job(
+                    0,
+                    Job::Unload(Box::new(UnloadJob {
+                        pos,
+                        chunk,
+                        save,
+                        keep_edits,
+                        cache,
+                        generated_hash,
+                    })),
+                );
+            }
+        }
+        // surfaces are only kept next to chunks that are loaded or might be generated soon
+        if removed && terrain_gen.two_pass {
+            let pending = &terrain_gen.pending;
+            terrain_gen.surfaces.retain(|pos, _| {
+                neighborhood(*pos)
+                    .any(|pos| layer.chunk(pos).is_some() || pending.contains_key(&pos))
+            });
+        }
+    }
+}
+
+/// View of a client limited to its [TerrainRenderDist], or to the render distance of the generator without one.
+/// A distance of 0 leaves the view as it is
+fn clamp_view(view: ChunkView, client_dist: Option<u8>, render_dist: u8) -> ChunkView {
+    match client_dist.unwrap_or(render_dist) {
+        0 => view,
+        dist => view.with_dist(view.dist().min(dist)),
+    }
+}
+
+#[cfg_attr(feature = "trace", tracing::instrument(skip_all))]
 fn update_client_views(
     mut layers: Query<(&mut ChunkLayer, &mut TerrainGenerator)>,
-    mut clients: Query<(&mut Client, View, OldView, &VisibleChunkLayer)>,
+    mut clients: Query<(
+        &mut Client,
+        View,
+        OldView,
+        Ref<VisibleChunkLayer>,
+        Option<Ref<TerrainRenderDist>>,
+    )>,
 ) {
-    for (client, view, old_view, visible_layer) in &mut clients {
+    for (_, mut terrain_gen) in layers.iter_mut() {
+        terrain_gen.views.clear();
+    }
+    for (client, view, old_view, visible_layer, client_dist) in &mut clients {
         let (layer, mut terrain_gen) = match layers.get_mut(visible_layer.0) {
             Ok(v) => v,
             // not in layer with terrain gen, move on
             Err(_) => continue,
         };
 
-        let mut view = view.get();
-        let mut old_view = old_view.get();
-        if terrain_gen.render_dist != 0 {
-            view = view.with_dist(view.dist().min(terrain_gen.render_dist));
-            old_view = old_view.with_dist(old_view.dist().min(terrain_gen.render_dist));
-        }
-        let reload = terrain_gen.needs_reload;
+        let dist = client_dist.as_ref().map(|dist| dist.0);
+        let view = clamp_view(view.get(), dist, terrain_gen.render_dist);
+        let old_view = clamp_view(old_view.get(), dist, terrain_gen.render_dist);
+        terrain_gen.views.push(view);
+        let reload = terrain_gen.needs_reload
+            || client_dist.as_ref().is_some_and(|dist| dist.is_changed());
         let queue_pos = |pos: ChunkPos| {
             if layer.chunk(pos).is_none() {
-                match terrain_gen.pending.entry(pos) {
-                    Entry::Occupied(mut oe) => {
-                        if let Some(priority) = oe.get_mut() {
-                            let dist = view.pos.distance_squared(pos);
-                            *priority = (*priority).min(dist);
-                        }
-                    }
-                    Entry::Vacant(ve) => {
-                        let dist = view.pos.distance_squared(pos);
-                        ve.insert(Some(dist));
-                    }
-                }
+                terrain_gen.queue_viewed(pos, view.pos.distance_squared(pos));
             }
         };
 
         // Queue all the new chunks in the view to be sent to the thread pool.
-        if client.is_added() || reload {
+        // Clients moved to another layer need their whole view in the new one.
+        if client.is_added() || visible_layer.is_changed() || reload {
             view.iter().for_each(queue_pos);
         } else if old_view != view {
             view.diff(old_view).for_each(queue_pos);
@@ -195,76 +2139,444 @@ fn update_client_views(
     }
 }
 
-fn send_recv_chunks(mut layers: Query<(&mut ChunkLayer, &mut TerrainGenerator)>) {
-    for (mut layer, mut terrain_gen) in layers.iter_mut() {
+#[cfg_attr(feature = "trace", tracing::instrument(skip_all))]
+fn send_recv_chunks(
+    mut layers: Query<(Entity, &mut ChunkLayer, &mut TerrainGenerator)>,
+    mut progress: EventWriter<PregenProgress>,
+    mut queued: EventWriter<ChunkQueued>,
+    mut generated_events: EventWriter<ChunkGenerated>,
+) {
+    for (entity, mut layer, mut terrain_gen) in layers.iter_mut() {
+        let terrain_gen = &mut *terrain_gen;
+        queued.send_batch(
+            terrain_gen
+                .queued
+                .drain(..)
+                .map(|pos| ChunkQueued { layer: entity, pos }),
+        );
         // Insert the chunks that are finished generating into the instance.
-        // needs collect to not borrow
-        for (pos, chunk) in terrain_gen.receiver.drain().collect::<Vec<_>>() {
-            layer.insert_chunk(pos, chunk);
-            assert!(terrain_gen.pending.remove(&pos).is_some());
+        // The rest stays in the channel once the budget is used up.
+        let mut budget = terrain_gen
+            .insert_budget
+            .map_or(u32::MAX, |budget| budget.max(1));
+        let (budget_start, insert_start) = (budget, Instant::now());
+        while budget > 0 {
+            let Ok(generated) = terrain_gen.receiver.try_recv() else {
+                break;
+            };
+            let pos = generated.pos;
+            if generated.epoch != terrain_gen.epoch {
+                continue;
+            }
+            match generated.result {
+                // the chunk may have been dropped from pending while it was generated
+                Ok(Generated::Finished(..)) if !terrain_gen.pending.contains_key(&pos) => {}
+                Ok(Generated::Finished(chunk, heightmap, hash)) => {
+                    terrain_gen.pending.remove(&pos);
+                    terrain_gen.from_anvil.remove(&pos);
+                    terrain_gen.chunk_done(pos, None);
+                    terrain_gen.stats.record(generated.duration);
+                    terrain_gen.heightmaps.insert(pos, heightmap);
+                    terrain_gen.hashes.insert(pos, hash);
+                    layer.insert_chunk(pos, chunk);
+                    budget -= 1;
+                    generated_events.send(ChunkGenerated { layer: entity, pos });
+                }
+                Ok(Generated::Proto(proto)) => {
+                    terrain_gen.terrain_requested.remove(&pos);
+                    terrain_gen.surfaces.insert(pos, proto.surface.clone());
+                    // the terrain of chunks that are only neighbours is not needed
+                    if terrain_gen.pending.contains_key(&pos) {
+                        terrain_gen.protos.insert(pos, proto);
+                    }
+                }
+                Ok(Generated::Repainted(changes)) => {
+                    let Some(chunk) = layer.chunk_mut(pos) else {
+                        continue;
+                    };
+                    // chunks that were not edited are still not edited after repainting
+                    let generated = terrain_gen.hashes.get(&pos) == Some(&block_hash(&*chunk));
+                    for ([x, y, z], old, new) in changes {
+                        if chunk.block_state(x, y, z) == old {
+                            chunk.set_block_state(x, y, z, new);
+                        }
+                    }
+                    if generated {
+                        terrain_gen.hashes.insert(pos, block_hash(&*chunk));
+                    }
+                    budget -= 1;
+                }
+                Ok(Generated::Unloaded(unloaded)) => {
+                    terrain_gen.unloading.remove(&pos);
+                    match (unloaded, &mut terrain_gen.cache) {
+                        (Unloaded::Edited(chunk, heightmap), _) => {
+                            terrain_gen.edited.insert(pos, (chunk, heightmap));
+                        }
+                        (Unloaded::Cached(chunk), Some(cache)) => cache.insert(pos, chunk),
+                        _ => {}
+                    }
+                }
+                Ok(Generated::Loaded(chunk, heightmap, hash)) => {
+                    // loaded chunks are kept as they are, so no features are placed in them or grown from them
+                    terrain_gen.terrain_requested.remove(&pos);
+                    if terrain_gen.two_pass {
+                        terrain_gen
+                            .surfaces
+                            .insert(pos, Arc::new(SurfaceInfo::empty()));
+                    }
+                    if terrain_gen.pending.remove(&pos).is_some() {
+                        terrain_gen.from_anvil.insert(pos);
+                        terrain_gen.chunk_done(pos, None);
+                        terrain_gen.stats.record(generated.duration);
+                        terrain_gen.heightmaps.insert(pos, heightmap);
+                        terrain_gen.hashes.insert(pos, hash);
+                        layer.insert_chunk(pos, chunk);
+                        budget -= 1;
+                        generated_events.send(ChunkGenerated { layer: entity, pos });
+                    }
+                }
+                Err(message) if terrain_gen.unloading.remove(&pos) => {
+                    tracing::error!("failed to unload chunk at {} {}: {message}", pos.x, pos.z);
+                }
+                Err(message) => {
+                    // neighbours can still be generated, just without the features of this chunk
+                    if terrain_gen.terrain_requested.remove(&pos) {
+                        terrain_gen
+                            .surfaces
+                            .insert(pos, Arc::new(SurfaceInfo::empty()));
+                    }
+                    terrain_gen.chunk_failed(pos, message);
+                }
+            }
         }
-        // Collect all the new chunks that need to be loaded this tick.
-        let mut to_send = vec![];
-
-        for (pos, priority) in &mut terrain_gen.pending {
-            if let Some(pri) = priority.take() {
-                to_send.push((pri, *pos));
+        terrain_gen.stats.tick_inserted = budget_start - budget;
+        terrain_gen.stats.cache_bytes = terrain_gen.cache.as_ref().map_or(0, ChunkCache::bytes);
+        terrain_gen.stats.tick_insert_time = insert_start.elapsed();
+        if terrain_gen.pregen_changed {
+            terrain_gen.pregen_changed = false;
+            progress.send(PregenProgress {
+                layer: entity,
+                generated: terrain_gen.pregen_done,
+                total: terrain_gen.pregen_total,
+            });
+        }
+        if terrain_gen.paused {
+            continue;
+        }
+        // Cancel the chunks that left every view before they were generated, pinned chunks and chunks replacing a
+        // loaded one are generated anyway.
+        let left = terrain_gen
+            .pending
+            .keys()
+            .filter(|pos| {
+                !terrain_gen.pinned.contains(pos)
+                    && !terrain_gen.replacing.contains(pos)
+                    && !terrain_gen.views.iter().any(|view| view.contains(**pos))
+            })
+            .copied()
+            .collect::<Vec<_>>();
+        let mut sent = vec![];
+        for pos in left {
+            terrain_gen.retries.remove(&pos);
+            if terrain_gen.pending.remove(&pos) == Some(None) {
+                sent.push(pos);
             }
+            terrain_gen.protos.remove(&pos);
+        }
+        // the terrain of the neighbours is only cancelled once no pending chunk needs their surfaces
+        if terrain_gen.two_pass {
+            sent = sent
+                .into_iter()
+                .flat_map(neighborhood)
+                .collect::<HashSet<_>>()
+                .into_iter()
+                .filter(|pos| !neighborhood(*pos).any(|pos| terrain_gen.pending.contains_key(&pos)))
+                .collect();
         }
+        for pos in sent {
+            terrain_gen.jobs.cancel(pos);
+            terrain_gen.terrain_requested.remove(&pos);
+        }
+        // Jobs already sent are moved to where the clients are now.
+        terrain_gen
+            .jobs
+            .reprioritize(|pos| view_priority(&terrain_gen.views, pos));
+
+        // Chunks that did not fit in the queue before get the room freed since.
+        terrain_gen.queue_deferred();
 
-        // Sort chunks by ascending priority.
-        to_send.sort_unstable_by_key(|(pri, _)| *pri);
+        let to_send = terrain_gen.take_to_send();
 
         // Send the sorted chunks to be loaded.
-        for (_, pos) in to_send {
-            let _ = terrain_gen.sender.try_send(pos);
-        }
-    }
-}
-
-fn chunk_worker(state: Arc<ChunkWorkerState>) {
-    while let Ok(pos) = state.receiver.recv() {
-        let mut chunk = UnloadedChunk::with_height(state.height);
-        // pretty sure clone is a good idea to not lock state as much as possible
-        let layers = state.surface_layers.clone();
-        let block = state.block;
-        let surface_height = layers.iter().map(|(a, _)| a).sum::<u16>() as i32;
-        for offset_x in 0..16 {
-            for offset_z in 0..16 {
-                let height = (state.noise.get([
-                    (offset_x as i32 + pos.x * 16) as f64,
-                    (offset_z as i32 + pos.z * 16) as f64,
-                ]) as i32)
-                    .clamp(1, chunk.height() as i32 - 1);
-                // remaning blocks until change
-                let mut rem = height - surface_height;
-                // current block index, -1 means not surface
-                let mut curidx = -1i32;
-                while rem <= 0 {
-                    curidx += 1;
-                    rem += layers[curidx as usize].0 as i32
+        for (priority, pos) in to_send {
+            if let Some((chunk, heightmap)) = terrain_gen.edited.remove(&pos) {
+                // no hash is stored, so the chunk is kept again when it is unloaded
+                terrain_gen.pending.remove(&pos);
+                // with saving only chunks from the world are kept as edits
+                if terrain_gen.save_on_unload {
+                    terrain_gen.from_anvil.insert(pos);
                 }
-                for y in 0..chunk.height() {
-                    if rem == 0 {
-                        curidx += 1;
-                        rem = if (curidx as usize) < layers.len() {
-                            layers[curidx as usize].0 as i32
-                        } else {
-                            0
-                        };
-                    }
-                    rem -= 1;
-                    let res_block = if curidx == -1 {
-                        block
-                    } else if rem < 0 {
-                        BlockState::AIR
-                    } else {
-                        layers[curidx as usize].1
-                    };
-                    chunk.set_block(offset_x, y, offset_z, res_block);
+                terrain_gen.chunk_done(pos, None);
+                terrain_gen.heightmaps.insert(pos, heightmap);
+                layer.insert_chunk(pos, chunk);
+                generated_events.send(ChunkGenerated { layer: entity, pos });
+                continue;
+            }
+            if let Some((chunk, heightmap, hash)) =
+                terrain_gen.cache.as_mut().and_then(|cache| cache.take(pos))
+            {
+                terrain_gen.pending.remove(&pos);
+                terrain_gen.chunk_done(pos, None);
+                terrain_gen.stats.cache_hits += 1;
+                terrain_gen.heightmaps.insert(pos, heightmap);
+                terrain_gen.hashes.insert(pos, hash);
+                layer.insert_chunk(pos, chunk);
+                generated_events.send(ChunkGenerated { layer: entity, pos });
+                continue;
+            }
+            // the jobs sent at once are bounded as well, the rest is sent in later ticks
+            let capacity = terrain_gen.queue_capacity;
+            if capacity.is_some_and(|capacity| terrain_gen.jobs.len() >= capacity) {
+                terrain_gen.pending.insert(pos, Some(priority));
+                continue;
+            }
+            if terrain_gen.cache.is_some() {
+                terrain_gen.stats.cache_misses += 1;
+            }
+            if !terrain_gen.two_pass {
+                let source = terrain_gen.chunk_source(pos);
+                terrain_gen.send_job(priority, Job::Generate(pos, source));
+                continue;
+            }
+            for neighbor in neighborhood(pos) {
+                // the chunk itself needs its full terrain, even if the surface is known from being a neighbour
+                let needed = if neighbor == pos {
+                    !terrain_gen.protos.contains_key(&pos)
+                } else {
+                    !terrain_gen.surfaces.contains_key(&neighbor)
+                };
+                if needed && terrain_gen.terrain_requested.insert(neighbor) {
+                    let source = terrain_gen.chunk_source(neighbor);
+                    terrain_gen.send_job(priority, Job::Terrain(neighbor, source));
                 }
             }
         }
-        let _ = state.sender.send((pos, chunk));
+
+        // Start the second pass of chunks where all neighbours are known.
+        let ready = terrain_gen
+            .protos
+            .keys()
+            .filter(|pos| neighborhood(**pos).all(|pos| terrain_gen.surfaces.contains_key(&pos)))
+            .copied()
+            .collect::<Vec<_>>();
+        for pos in ready {
+            let proto = terrain_gen.protos.remove(&pos).unwrap();
+            let neighbors = neighborhood(pos)
+                .map(|pos| (pos, terrain_gen.surfaces[&pos].clone()))
+                .collect();
+            // pinned chunks without viewers are finished first
+            let priority = view_priority(&terrain_gen.views, pos).unwrap_or(0);
+            terrain_gen.send_job(
+                priority,
+                Job::Features(Box::new(FeatureJob {
+                    pos,
+                    proto,
+                    neighbors,
+                })),
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn void_chunk_is_pending() {
+        let mut generator = TerrainGenerator::new(TerrainGenConfig::void(32), 2);
+        let pos = ChunkPos::new(1, 1);
+        let _request = generator.request_chunk(pos);
+        // empty chunks are still queued, so they are inserted and the request finishes
+        assert_eq!(generator.pending.get(&pos), Some(&Some(0)));
+        assert_eq!(generator.queued, vec![pos]);
+        assert_eq!(generator.pending_chunks(), 1);
+    }
+
+    #[test]
+    fn failed_chunk_is_retried() {
+        let mut generator = TerrainGenerator::new(TerrainGenConfig::void(32), 2);
+        let pos = ChunkPos::new(0, 0);
+        let mut request = generator.request_chunk(pos);
+        generator.pending.insert(pos, None);
+        for _ in 0..MAX_RETRIES {
+            generator.chunk_failed(pos, "boom".into());
+            assert_eq!(generator.pending.get(&pos), Some(&Some(0)));
+            assert!(request.poll().is_none());
+            generator.pending.insert(pos, None);
+        }
+        generator.chunk_failed(pos, "boom".into());
+        assert!(!generator.pending.contains_key(&pos));
+        assert!(matches!(request.poll(), Some(Err(_))));
+        assert_eq!(generator.take_errors().len(), MAX_RETRIES as usize + 1);
+    }
+
+    #[test]
+    fn dispatch_order_is_deterministic() {
+        let equidistant = [
+            ChunkPos::new(1, 0),
+            ChunkPos::new(0, 1),
+            ChunkPos::new(-1, 0),
+            ChunkPos::new(0, -1),
+        ];
+        let dispatch = |order: &[ChunkPos]| {
+            let mut generator = TerrainGenerator::new(TerrainGenConfig::void(32), 0);
+            for pos in order {
+                generator.queue_viewed(*pos, 1);
+            }
+            generator.take_to_send()
+        };
+        let first = dispatch(&equidistant);
+        let mut reversed = equidistant;
+        reversed.reverse();
+        assert_eq!(first, dispatch(&reversed));
+        assert_eq!(first, dispatch(&equidistant));
+        let positions = first
+            .iter()
+            .map(|(_, pos)| (pos.x, pos.z))
+            .collect::<Vec<_>>();
+        assert_eq!(positions, vec![(-1, 0), (0, -1), (0, 1), (1, 0)]);
+    }
+
+    #[test]
+    fn render_dist_override() {
+        let client = ChunkView::new(ChunkPos::new(0, 0), 10);
+        let queued = |dist: Option<u8>| {
+            let mut generator = TerrainGenerator::new(TerrainGenConfig::void(32), 3);
+            let view = clamp_view(client, dist, generator.render_dist());
+            for pos in view.iter() {
+                generator.queue_viewed(pos, view.pos.distance_squared(pos));
+            }
+            generator.pending_chunks()
+        };
+        let (near, far, default) = (queued(Some(2)), queued(Some(6)), queued(None));
+        assert!(near < default && default < far);
+        // overrides are still limited by the distance of the client
+        assert_eq!(queued(Some(20)), client.iter().count());
+    }
+
+    #[test]
+    fn custom_noise_is_always_changed() {
+        let config = TerrainGenConfig {
+            noise: NoiseBuilder::custom(|_| 64.0),
+            ..TerrainGenConfig::void(32)
+        };
+        assert!(config.reload_scope(&config.clone()) == ReloadScope::Terrain);
+        let plain = TerrainGenConfig::void(32);
+        assert!(plain.reload_scope(&plain.clone()) == ReloadScope::Unchanged);
+    }
+
+    #[test]
+    fn rejected_chunk_is_deferred() {
+        let config = TerrainGenConfig {
+            queue_capacity: Some(1),
+            queue_overflow: QueueOverflow::Reject,
+            ..TerrainGenConfig::void(32)
+        };
+        let mut generator = TerrainGenerator::new(config, 2);
+        let (first, second) = (ChunkPos::new(0, 0), ChunkPos::new(1, 0));
+        generator.queue_viewed(first, 0);
+        generator.queue_viewed(second, 1);
+        assert!(!generator.pending.contains_key(&second));
+        generator.views = vec![ChunkView::new(first, 2)];
+        generator.queue_deferred();
+        assert!(!generator.pending.contains_key(&second));
+        // queued once the first chunk is done
+        generator.pending.remove(&first);
+        generator.queue_deferred();
+        assert_eq!(generator.pending.get(&second), Some(&Some(1)));
+    }
+
+    #[test]
+    fn replacing_chunk_is_not_dropped() {
+        let config = TerrainGenConfig {
+            queue_capacity: Some(1),
+            queue_overflow: QueueOverflow::DropFarthest,
+            ..TerrainGenConfig::void(32)
+        };
+        let mut generator = TerrainGenerator::new(config, 2);
+        let pos = ChunkPos::new(0, 0);
+        generator.heightmaps.insert(pos, ChunkHeightmap([0; 256]));
+        generator.regenerate(pos, pos);
+        generator.queue_viewed(ChunkPos::new(1, 0), 1);
+        assert_eq!(generator.pending.get(&pos), Some(&Some(u64::MAX)));
+        assert!(generator.deferred.contains(&ChunkPos::new(1, 0)));
+    }
+
+    #[test]
+    fn loaded_spawn_chunk_is_not_regenerated() {
+        let config = TerrainGenConfig {
+            spawn_chunks: Some(SpawnChunks {
+                position: [0, 0],
+                radius: 0,
+            }),
+            ..TerrainGenConfig::void(32)
+        };
+        let mut generator = TerrainGenerator::new(config.clone(), 2);
+        let pos = ChunkPos::new(0, 0);
+        assert_eq!(generator.pending.get(&pos), Some(&Some(0)));
+        generator.pending.remove(&pos);
+        generator.heightmaps.insert(pos, ChunkHeightmap([0; 256]));
+        generator.reload(config);
+        assert!(!generator.pending.contains_key(&pos));
+        assert!(generator.heightmaps.contains_key(&pos));
+    }
+
+    #[test]
+    fn regenerated_chunk_is_generated_after_reload() {
+        let config = TerrainGenConfig {
+            anvil: Some("world".into()),
+            ..TerrainGenConfig::void(32)
+        };
+        let mut generator = TerrainGenerator::new(config.clone(), 2);
+        let pos = ChunkPos::new(0, 0);
+        assert_eq!(generator.chunk_source(pos), ChunkSource::Saved);
+        generator.regenerate(pos, pos);
+        generator.reload(config);
+        assert_eq!(generator.chunk_source(pos), ChunkSource::Generated);
+    }
+
+    #[test]
+    fn palette_without_weights_is_rejected() {
+        let config = TerrainGenConfig {
+            palettes: vec![BlockPalette {
+                block: BlockState::STONE,
+                blocks: vec![(0.0, BlockState::ANDESITE), (-1.0, BlockState::GRAVEL)],
+            }],
+            ..TerrainGenConfig::void(32)
+        };
+        assert_eq!(
+            config.validate(),
+            Err(TerrainConfigError::EmptyPalette("stone".into()))
+        );
+    }
+
+    #[test]
+    fn custom_noise_is_not_serialized() {
+        let config = TerrainGenConfig {
+            noise: NoiseBuilder::custom(|_| 64.0),
+            ..TerrainGenConfig::void(32)
+        };
+        assert!(serde_yml::to_string(&config).is_err());
+        assert!(serde_yml::to_string(&TerrainGenConfig::void(32)).is_ok());
+    }
+
+    #[test]
+    fn seed_placeholder_is_replaced() {
+        let config =
+            serde_yml::from_str::<TerrainGenConfig>("seed: 5\nnoise: perlin $seed+1").unwrap();
+        let expected = NoiseBuilder::parse_seeded("perlin $seed+1", 5).unwrap();
+        assert_eq!(config.noise.to_config_string(), expected.to_config_string());
+        assert!(serde_yml::from_str::<TerrainGenConfig>("noise: perlin $seed").is_err());
     }
 }
@@ -0,0 +1,208 @@
+//! Map items showing the terrain around clients, for surveying the terrain in game.
+//! Add [TerrainMapPlugin] to the app and a [TerrainMap] to a client to give them a map
+
+use std::cmp::Ordering;
+
+use flume::{Receiver, Sender, TryRecvError};
+use valence::{
+    nbt::compound,
+    prelude::*,
+    protocol::{
+        packets::play::{map_update_s2c, MapUpdateS2c},
+        VarInt, WritePacket,
+    },
+};
+
+use crate::{worker::ChunkWorkerState, TerrainGenerator, TerrainSet};
+
+/// Width and height of a map in pixels
+const MAP_SIZE: i32 = 128;
+
+/// Base colors of the map color palette, each has 4 shades
+const GRASS: u8 = 1;
+const SAND: u8 = 2;
+const FIRE: u8 = 4;
+const ICE: u8 = 5;
+const PLANT: u8 = 7;
+const SNOW: u8 = 8;
+const CLAY: u8 = 9;
+const DIRT: u8 = 10;
+const STONE: u8 = 11;
+const WATER: u8 = 12;
+
+/// Renders the [TerrainMap]s of clients after the chunks are inserted
+pub struct TerrainMapPlugin;
+
+impl Plugin for TerrainMapPlugin {
+    fn build(&self, app: &mut App) {
+        app.add_systems(Update, update_maps.after(TerrainSet::Insert));
+    }
+}
+
+/// Gives the client a filled map of the terrain around them, drawn from the [TerrainGenerator] of their layer with
+/// [height_at](TerrainGenerator::height_at) and [surface_block_at](TerrainGenerator::surface_block_at).
+/// The map is drawn by the workers again once the client has moved a quarter of its width away from its center
+#[derive(Component, Clone, Debug)]
+pub struct TerrainMap {
+    /// Id of the map item, must not be used by other maps of the client
+    pub map_id: i32,
+    /// Blocks per pixel, a power of two from 1 to 16 as clients can not show other scales
+    pub scale: u32,
+    /// Inventory slot the map is put in when the component is added, the last hotbar slot by default
+    pub slot: u16,
+    /// Block at the center of the last drawing
+    center: Option<[i32; 2]>,
+    /// Colors of the drawing the workers are working on
+    drawing: Option<Receiver<Vec<u8>>>,
+}
+
+impl TerrainMap {
+    pub fn new(map_id: i32, scale: u32) -> Self {
+        Self {
+            map_id,
+            scale: map_scale(scale),
+            slot: 44,
+            center: None,
+            drawing: None,
+        }
+    }
+}
+
+/// Rounds a scale up to the next power of two, at most 16
+fn map_scale(scale: u32) -> u32 {
+    scale.clamp(1, 16).next_power_of_two()
+}
+
+/// Drawing of a [TerrainMap] sent to the workers
+pub(crate) struct MapJob {
+    pub center: [i32; 2],
+    pub scale: i32,
+    pub sender: Sender<Vec<u8>>,
+}
+
+fn update_maps(
+    layers: Query<&TerrainGenerator>,
+    mut clients: Query<(
+        &mut Client,
+        &mut TerrainMap,
+        &mut Inventory,
+        &Position,
+        &VisibleChunkLayer,
+    )>,
+) {
+    for (mut client, mut map, mut inventory, pos, visible_layer) in &mut clients {
+        let Ok(terrain_gen) = layers.get(visible_layer.0) else {
+            continue;
+        };
+        if map.is_added() {
+            let item = ItemStack::new(
+                ItemKind::FilledMap,
+                1,
+                Some(compound! { "map" => map.map_id }),
+            );
+            inventory.set_slot(map.slot, item);
+        }
+        let scale = map_scale(map.scale);
+        if let Some(drawing) = &map.drawing {
+            let colors = match drawing.try_recv() {
+                Ok(colors) => colors,
+                Err(TryRecvError::Empty) => continue,
+                // the job was dropped with the workers, the map is drawn again below
+                Err(TryRecvError::Disconnected) => {
+                    map.drawing = None;
+                    map.center = None;
+                    continue;
+                }
+            };
+            map.drawing = None;
+            client.write_packet(&MapUpdateS2c {
+                map_id: VarInt(map.map_id),
+                scale: scale.ilog2() as i8,
+                locked: true,
+                icons: None,
+                data: Some(map_update_s2c::Data {
+                    columns: MAP_SIZE as u8,
+                    rows: MAP_SIZE as u8,
+                    position: [0, 0],
+                    data: &colors,
+                }),
+            });
+        }
+        let block = [pos.0.x.floor() as i32, pos.0.z.floor() as i32];
+        let max = MAP_SIZE * scale as i32 / 4;
+        let moved = !map.center.is_some_and(|center| {
+            (block[0] - center[0]).abs() <= max && (block[1] - center[1]).abs() <= max
+        });
+        // nothing can be drawn before the workers are started
+        if !moved || !terrain_gen.started() {
+            continue;
+        }
+        map.center = Some(block);
+        map.drawing = Some(terrain_gen.draw_map(block, scale as i32));
+    }
+}
+
+/// Map colors of the area around `center`, in rows from north to south
+pub(crate) fn draw_map(state: &ChunkWorkerState, center: [i32; 2], scale: i32) -> Vec<u8> {
+    let origin = [
+        center[0] - MAP_SIZE * scale / 2,
+        center[1] - MAP_SIZE * scale / 2,
+    ];
+    let heights = (-1..MAP_SIZE)
+        .map(|row| {
+            (0..MAP_SIZE)
+                .map(|column| {
+                    let (x, z) = (origin[0] + column * scale, origin[1] + row * scale);
+                    (state.shape.height_at(x, z), state.surface_block_at(x, z))
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let mut colors = Vec::with_capacity((MAP_SIZE * MAP_SIZE) as usize);
+    // the first row is north of the map, it is only used for shading
+    for (north, row) in heights.iter().zip(&heights[1..]) {
+        for ((north_height, _), (height, block)) in north.iter().zip(row) {
+            let Some(block) = block else {
+                colors.push(0);
+                continue;
+            };
+            let base = map_color(*block);
+            // like vanilla, columns higher than the one north of them are lighter
+            let shade = match height.cmp(north_height) {
+                Ordering::Less => 0,
+                Ordering::Equal => 1,
+                Ordering::Greater => 2,
+            };
+            colors.push(base * 4 + shade);
+        }
+    }
+    colors
+}
+
+/// Base map color of a block, guessed from its name as valence does not know the colors of blocks
+fn map_color(block: BlockState) -> u8 {
+    if block.is_liquid() {
+        return match block.to_kind() {
+            BlockKind::Lava => FIRE,
+            _ => WATER,
+        };
+    }
+    let name = block.to_kind().to_str();
+    if name.contains("snow") {
+        SNOW
+    } else if name.contains("ice") {
+        ICE
+    } else if name.contains("grass") || name.contains("moss") {
+        GRASS
+    } else if name.contains("leaves") {
+        PLANT
+    } else if name.contains("sand") || name.contains("gravel") {
+        SAND
+    } else if name.contains("clay") {
+        CLAY
+    } else if name.contains("dirt") || name.contains("podzol") || name.contains("mud") {
+        DIRT
+    } else {
+        STONE
+    }
+}
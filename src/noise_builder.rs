@@ -1,15 +1,36 @@
-use std::str::{FromStr, SplitWhitespace};
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    collections::HashMap,
+    fmt,
+    str::FromStr,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, OnceLock, RwLock,
+    },
+    thread::LocalKey,
+};
 
 use noise::{
-    Abs, Add, Checkerboard, Clamp, Constant, Max, Min, Multiply, Negate, NoiseFn, Perlin, Power,
-    ScalePoint, Simplex,
+    core::worley::ReturnType, Abs, Add, Billow, Blend, Checkerboard, Clamp, Constant, Curve,
+    Exponent, Fbm, Max, Min, MultiFractal, Multiply, Negate, NoiseFn, OpenSimplex, Perlin, Power,
+    RidgedMulti, RotatePoint, ScaleBias, ScalePoint, Seedable, Select, Simplex, SuperSimplex,
+    Terrace, TranslatePoint, Turbulence, Value, Worley,
 };
 use serde::{Deserialize, Serialize};
 
-#[derive(Serialize, Deserialize)]
+mod print;
+mod seed;
+mod stats;
+mod validate;
+
+pub(crate) use seed::seed_tree;
+pub use stats::NoiseStats;
+
+#[derive(Serialize, Deserialize, Clone)]
 
 /// Tree of noise functions that function like expressions taking eachother as inputs
-/// Every function takes the z and x position as inputs by default
+/// Every function takes the z and x position as inputs by default, and the y position as well when built in 3D
 pub enum NoiseBuilder {
     Constant(f64),
     Abs(Box<NoiseBuilder>),
@@ -20,78 +41,580 @@ pub enum NoiseBuilder {
     Mul(Box<NoiseBuilder>, Box<NoiseBuilder>),
     Pow(Box<NoiseBuilder>, Box<NoiseBuilder>),
     PowI(i32, Box<NoiseBuilder>),
-    ScaleInput(f64, f64, Box<NoiseBuilder>),
+    /// x and y scale, the optional z scale is only used when built in 3D
+    ScaleInput(f64, f64, Option<f64>, Box<NoiseBuilder>),
     Clamp(f64, f64, Box<NoiseBuilder>),
+    /// `source * scale + bias`, for mapping noise to a range of heights
+    ScaleBias {
+        scale: f64,
+        bias: f64,
+        source: Box<NoiseBuilder>,
+    },
     Checkerboard,
     /// argument is seed
     Perlin(u32),
     /// argument is seed
     Simplex(u32),
+    /// argument is seed
+    OpenSimplex(u32),
+    /// argument is seed, smoother than [OpenSimplex](NoiseBuilder::OpenSimplex) but slower
+    SuperSimplex(u32),
+    /// argument is seed, blocky value noise that is cheaper than gradient noise
+    Value(u32),
+    /// Octaves of Perlin noise added together, each `lacunarity` times the frequency and `persistence` times the
+    /// amplitude of the previous one
+    Fbm {
+        seed: u32,
+        octaves: usize,
+        frequency: f64,
+        lacunarity: f64,
+        persistence: f64,
+    },
+    /// Like [Fbm](NoiseBuilder::Fbm), but the octaves are folded into sharp ridges, for mountain ranges and valleys.  
+    /// Higher `attenuation` makes the higher octaves weaker near the bottom of the ridges
+    Ridged {
+        seed: u32,
+        octaves: usize,
+        frequency: f64,
+        lacunarity: f64,
+        persistence: f64,
+        attenuation: f64,
+    },
+    /// Like [Fbm](NoiseBuilder::Fbm), but with the absolute value of the octaves, for puffy rolling hills
+    Billow {
+        seed: u32,
+        octaves: usize,
+        frequency: f64,
+        lacunarity: f64,
+        persistence: f64,
+    },
+    /// Cellular noise, giving either the distance to the closest cell point or a value for each cell
+    Worley {
+        seed: u32,
+        frequency: f64,
+        return_type: WorleyReturn,
+    },
+    /// Randomly displaces the input of `source` by up to `power` blocks, `roughness` is the amount of octaves of
+    /// the displacement
+    Turbulence {
+        seed: u32,
+        frequency: f64,
+        power: f64,
+        roughness: usize,
+        source: Box<NoiseBuilder>,
+    },
+    /// Domain warp, the x and z input of the last expression are offset by the first two expressions
+    Warp(Box<NoiseBuilder>, Box<NoiseBuilder>, Box<NoiseBuilder>),
+    /// Interpolates between the last two expressions, `a` where the first is -1 and `b` where it is 1
+    Blend(Box<NoiseBuilder>, Box<NoiseBuilder>, Box<NoiseBuilder>),
+    /// `b` where `control` is within `bounds` and `a` everywhere else, blended over `falloff` around the bounds
+    Select {
+        control: Box<NoiseBuilder>,
+        a: Box<NoiseBuilder>,
+        b: Box<NoiseBuilder>,
+        bounds: (f64, f64),
+        falloff: f64,
+    },
+    /// Maps the output of the expression through a cubic curve going through the `(input, output)` points,
+    /// at least 4 points are needed
+    Curve(Vec<(f64, f64)>, Box<NoiseBuilder>),
+    /// Flattens the output of the expression into steps at each level, at least 2 levels are needed
+    Terrace(Vec<f64>, Box<NoiseBuilder>),
+    /// Raises the expression to the exponent after mapping it from -1..1 to 0..1, the result is mapped back to -1..1
+    Exponent(f64, Box<NoiseBuilder>),
+    /// Rotates the input around the vertical axis by the angle in degrees
+    Rotate(f64, Box<NoiseBuilder>),
+    /// x and z offset added to the input, the optional y offset is only used when built in 3D
+    Translate(f64, f64, Option<f64>, Box<NoiseBuilder>),
+    /// Linear interpolation between the last two expressions, `a` where the first is 0 and `b` where it is 1.  
+    /// Unlike [Blend](NoiseBuilder::Blend) the factor is not clamped, so values outside 0..1 extrapolate
+    Lerp(Box<NoiseBuilder>, Box<NoiseBuilder>, Box<NoiseBuilder>),
+    /// Linearly maps the `from` range of the source to the `to` range, like `remap -1 1 60 120 perlin 0` for heights
+    /// from 60 to 120. Values outside of `from` are not clamped
+    Remap {
+        from: (f64, f64),
+        to: (f64, f64),
+        source: Box<NoiseBuilder>,
+    },
+    /// Cubic spline like the ones of vanilla, going through the `(input, output, slope)` points with the given slopes.  
+    /// Outside of the points the output continues in a straight line, at least 2 points are needed
+    Spline(Vec<(f64, f64, f64)>, Box<NoiseBuilder>),
+    /// 1 at the `center` x and z, falling off to 0 at `radius` blocks from it and beyond. Multiplied into a height it
+    /// gives a single island surrounded by ocean. The y position is ignored in 3D
+    DistanceFalloff {
+        center: (f64, f64),
+        radius: f64,
+        curve: FalloffCurve,
+    },
+    /// Function sampled at `[x, z]`, for things like distance fields or images that cannot be expressed as noise.  
+    /// Only available from Rust, it is not parsed and is written as `<custom>`. Serializing a tree or a
+    /// [TerrainGenConfig](crate::TerrainGenConfig) containing it with serde is an error. The y position is ignored in 3D
+    #[serde(skip)]
+    Custom(CustomFn),
+    /// Binds `value` to `name` within `body`, where it can be used any number of times with [Var](NoiseBuilder::Var).  
+    /// The bound noise is built once and only sampled once per point no matter how often it is used
+    Let {
+        name: String,
+        value: Box<NoiseBuilder>,
+        body: Box<NoiseBuilder>,
+    },
+    /// Noise bound to the name by an enclosing [Let](NoiseBuilder::Let)
+    Var(String),
+    /// Keeps the seeds of the expression as they are written when a world seed is applied, see
+    /// [seeded](NoiseBuilder::seeded)
+    Fixed(Box<NoiseBuilder>),
 }
 
-impl NoiseBuilder {
-    pub fn build(self) -> DynNoise {
+/// Function of a [Custom](NoiseBuilder::Custom) node
+pub type CustomFn = Arc<dyn Fn([f64; 2]) -> f64 + Send + Sync>;
+
+/// What [Worley](NoiseBuilder::Worley) noise returns, `distance` or `value` in the parser
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum WorleyReturn {
+    /// Distance to the closest cell point, for rounded cells
+    Distance,
+    /// Constant value per cell, for plateaus and partitioning
+    Value,
+}
+
+/// Shape of a [DistanceFalloff](NoiseBuilder::DistanceFalloff), by its name in the parser
+#[derive(Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Debug)]
+#[serde(rename_all = "snake_case")]
+pub enum FalloffCurve {
+    /// Straight slope from the center to the radius, like a cone
+    Linear,
+    /// Flat at the center and the radius with a smooth slope in between
+    Smooth,
+    /// Flat at the center and steepest at the radius, like a dome
+    Dome,
+}
+
+impl FalloffCurve {
+    fn name(self) -> &'static str {
         match self {
-            NoiseBuilder::Constant(v) => dynn(Constant::new(v)),
-            NoiseBuilder::Abs(builder) => dynn(Abs::new(builder.build())),
-            NoiseBuilder::Neg(builder) => dynn(Negate::new(builder.build())),
-            // i try to do some optimization for constants, buts its a bit messy
-            NoiseBuilder::Add(builder_a, builder_b) => match *builder_a {
-                NoiseBuilder::Constant(v) => dynn(Add::new(Constant::new(v), builder_b.build())),
-                _ => dynn(Add::new(builder_a.build(), builder_b.build())),
-            },
-            NoiseBuilder::Mul(builder_a, builder_b) => match *builder_a {
-                NoiseBuilder::Constant(v) => {
-                    dynn(Multiply::new(Constant::new(v), builder_b.build()))
+            Self::Linear => "linear",
+            Self::Smooth => "smooth",
+            Self::Dome => "dome",
+        }
+    }
+}
+
+/// Generates a build function for a [DynNoise] type, the dimension specific nodes are handled by
+/// the `scale_input` and `translate` functions on the output type
+macro_rules! build_fn {
+    ($(#[$meta:meta])* $name:ident -> $dyn_noise:ident) => {
+        $(#[$meta])*
+        pub fn $name(self) -> $dyn_noise {
+            match self {
+                NoiseBuilder::Constant(v) => $dyn_noise::new(Constant::new(v)),
+                NoiseBuilder::Abs(builder) => $dyn_noise::new(Abs::new(builder.$name())),
+                NoiseBuilder::Neg(builder) => $dyn_noise::new(Negate::new(builder.$name())),
+                // i try to do some optimization for constants, buts its a bit messy
+                NoiseBuilder::Add(builder_a, builder_b) => match *builder_a {
+                    NoiseBuilder::Constant(v) => {
+                        $dyn_noise::new(Add::new(Constant::new(v), builder_b.$name()))
+                    }
+                    _ => $dyn_noise::new(Add::new(builder_a.$name(), builder_b.$name())),
+                },
+                NoiseBuilder::Mul(builder_a, builder_b) => match *builder_a {
+                    NoiseBuilder::Constant(v) => {
+                        $dyn_noise::new(Multiply::new(Constant::new(v), builder_b.$name()))
+                    }
+                    _ => $dyn_noise::new(Multiply::new(builder_a.$name(), builder_b.$name())),
+                },
+                NoiseBuilder::Min(builder_a, builder_b) => match *builder_a {
+                    NoiseBuilder::Constant(v) => {
+                        $dyn_noise::new(Min::new(Constant::new(v), builder_b.$name()))
+                    }
+                    _ => $dyn_noise::new(Min::new(builder_a.$name(), builder_b.$name())),
+                },
+                NoiseBuilder::Max(builder_a, builder_b) => match *builder_a {
+                    NoiseBuilder::Constant(v) => {
+                        $dyn_noise::new(Max::new(Constant::new(v), builder_b.$name()))
+                    }
+                    _ => $dyn_noise::new(Max::new(builder_a.$name(), builder_b.$name())),
+                },
+                NoiseBuilder::PowI(i, builder) => $dyn_noise::new(PowINoise(builder.$name(), i)),
+                NoiseBuilder::Pow(builder_a, builder_b) => match *builder_a {
+                    NoiseBuilder::Constant(v) => {
+                        $dyn_noise::new(Power::new(Constant::new(v), builder_b.$name()))
+                    }
+                    _ => $dyn_noise::new(Power::new(builder_a.$name(), builder_b.$name())),
+                },
+                NoiseBuilder::ScaleInput(x, y, z, builder) => {
+                    $dyn_noise::scale_input(builder.$name(), x, y, z)
                 }
-                _ => dynn(Multiply::new(builder_a.build(), builder_b.build())),
-            },
-            NoiseBuilder::Min(builder_a, builder_b) => match *builder_a {
-                NoiseBuilder::Constant(v) => dynn(Min::new(Constant::new(v), builder_b.build())),
-                _ => dynn(Min::new(builder_a.build(), builder_b.build())),
-            },
-            NoiseBuilder::Max(builder_a, builder_b) => match *builder_a {
-                NoiseBuilder::Constant(v) => dynn(Max::new(Constant::new(v), builder_b.build())),
-                _ => dynn(Max::new(builder_a.build(), builder_b.build())),
-            },
-            NoiseBuilder::PowI(i, builder) => dynn(PowINoise(builder.build(), i)),
-            NoiseBuilder::Pow(builder_a, builder_b) => match *builder_a {
-                NoiseBuilder::Constant(v) => dynn(Power::new(Constant::new(v), builder_b.build())),
-                _ => dynn(Power::new(builder_a.build(), builder_b.build())),
-            },
-            NoiseBuilder::ScaleInput(x, y, builder) => dynn(
-                ScalePoint::new(builder.build())
-                    .set_x_scale(x)
-                    .set_y_scale(y),
-            ),
-            NoiseBuilder::Clamp(min, max, builder) => {
-                dynn(Clamp::new(builder.build()).set_bounds(min, max))
+                NoiseBuilder::Clamp(min, max, builder) => {
+                    $dyn_noise::new(Clamp::new(builder.$name()).set_bounds(min, max))
+                }
+                NoiseBuilder::ScaleBias {
+                    scale,
+                    bias,
+                    source,
+                } => $dyn_noise::new(
+                    ScaleBias::new(source.$name())
+                        .set_scale(scale)
+                        .set_bias(bias),
+                ),
+                NoiseBuilder::Checkerboard => $dyn_noise::new(Checkerboard::new(0)),
+                NoiseBuilder::Perlin(seed) => $dyn_noise::new(Perlin::new(seed)),
+                NoiseBuilder::Simplex(seed) => $dyn_noise::new(Simplex::new(seed)),
+                NoiseBuilder::OpenSimplex(seed) => $dyn_noise::new(OpenSimplex::new(seed)),
+                NoiseBuilder::SuperSimplex(seed) => $dyn_noise::new(SuperSimplex::new(seed)),
+                NoiseBuilder::Value(seed) => $dyn_noise::new(Value::new(seed)),
+                NoiseBuilder::Fbm {
+                    seed,
+                    octaves,
+                    frequency,
+                    lacunarity,
+                    persistence,
+                } => $dyn_noise::new(
+                    Fbm::<Perlin>::new(seed)
+                        .set_octaves(octaves)
+                        .set_frequency(frequency)
+                        .set_lacunarity(lacunarity)
+                        .set_persistence(persistence),
+                ),
+                NoiseBuilder::Ridged {
+                    seed,
+                    octaves,
+                    frequency,
+                    lacunarity,
+                    persistence,
+                    attenuation,
+                } => $dyn_noise::new(
+                    RidgedMulti::<Perlin>::new(seed)
+                        .set_octaves(octaves)
+                        .set_frequency(frequency)
+                        .set_lacunarity(lacunarity)
+                        .set_persistence(persistence)
+                        .set_attenuation(attenuation),
+                ),
+                NoiseBuilder::Billow {
+                    seed,
+                    octaves,
+                    frequency,
+                    lacunarity,
+                    persistence,
+                } => $dyn_noise::new(
+                    Billow::<Perlin>::new(seed)
+                        .set_octaves(octaves)
+                        .set_frequency(frequency)
+                        .set_lacunarity(lacunarity)
+                        .set_persistence(persistence),
+                ),
+                NoiseBuilder::Worley {
+                    seed,
+                    frequency,
+                    return_type,
+                } => $dyn_noise::new(Worley::new(seed).set_frequency(frequency).set_return_type(
+                    match return_type {
+                        WorleyReturn::Distance => ReturnType::Distance,
+                        WorleyReturn::Value => ReturnType::Value,
+                    },
+                )),
+                NoiseBuilder::Turbulence {
+                    seed,
+                    frequency,
+                    power,
+                    roughness,
+                    source,
+                } => $dyn_noise::new(
+                    Turbulence::<_, Perlin>::new(source.$name())
+                        .set_seed(seed)
+                        .set_frequency(frequency)
+                        .set_power(power)
+                        .set_roughness(roughness),
+                ),
+                NoiseBuilder::Warp(x, z, source) => {
+                    $dyn_noise::new(WarpNoise(source.$name(), x.$name(), z.$name()))
+                }
+                NoiseBuilder::Blend(control, a, b) => {
+                    $dyn_noise::new(Blend::new(a.$name(), b.$name(), control.$name()))
+                }
+                NoiseBuilder::Select {
+                    control,
+                    a,
+                    b,
+                    bounds,
+                    falloff,
+                } => $dyn_noise::new(
+                    Select::new(a.$name(), b.$name(), control.$name())
+                        .set_bounds(bounds.0, bounds.1)
+                        .set_falloff(falloff),
+                ),
+                NoiseBuilder::Curve(points, source) => $dyn_noise::new(
+                    points
+                        .into_iter()
+                        .fold(Curve::new(source.$name()), |curve, (input, output)| {
+                            curve.add_control_point(input, output)
+                        }),
+                ),
+                NoiseBuilder::Terrace(levels, source) => $dyn_noise::new(
+                    levels
+                        .into_iter()
+                        .fold(Terrace::new(source.$name()), |terrace, level| {
+                            terrace.add_control_point(level)
+                        }),
+                ),
+                NoiseBuilder::Exponent(exponent, source) => {
+                    $dyn_noise::new(Exponent::new(source.$name()).set_exponent(exponent))
+                }
+                NoiseBuilder::Rotate(angle, source) => {
+                    $dyn_noise::new(RotatePoint::new(source.$name()).set_z_angle(angle))
+                }
+                NoiseBuilder::Translate(x, z, y, source) => {
+                    $dyn_noise::translate(source.$name(), x, z, y)
+                }
+                NoiseBuilder::Lerp(t, a, b) => {
+                    $dyn_noise::new(LerpNoise(t.$name(), a.$name(), b.$name()))
+                }
+                NoiseBuilder::Remap { from, to, source } => {
+                    let scale = (to.1 - to.0) / (from.1 - from.0);
+                    $dyn_noise::new(
+                        ScaleBias::new(source.$name())
+                            .set_scale(scale)
+                            .set_bias(to.0 - from.0 * scale),
+                    )
+                }
+                NoiseBuilder::Spline(points, source) => {
+                    $dyn_noise::new(SplineNoise::new(source.$name(), points))
+                }
+                NoiseBuilder::DistanceFalloff {
+                    center,
+                    radius,
+                    curve,
+                } => $dyn_noise::new(FalloffNoise {
+                    center,
+                    radius,
+                    curve,
+                }),
+                NoiseBuilder::Custom(function) => $dyn_noise::new(CustomNoise(function)),
+                NoiseBuilder::Let { name, value, body } => {
+                    $dyn_noise::bind(name, value.$name(), || body.$name())
+                }
+                NoiseBuilder::Var(name) => match $dyn_noise::var(&name) {
+                    Some(shared) => $dyn_noise::new(shared),
+                    None => {
+                        tracing::error!(
+                            "noise '{name}' is used outside of its let, using 0 instead"
+                        );
+                        $dyn_noise::new(Constant::new(0.0))
+                    }
+                },
+                NoiseBuilder::Fixed(source) => source.$name(),
             }
-            NoiseBuilder::Checkerboard => dynn(Checkerboard::new(0)),
-            NoiseBuilder::Perlin(seed) => dynn(Perlin::new(seed)),
-            NoiseBuilder::Simplex(seed) => dynn(Simplex::new(seed)),
         }
+    };
+}
+
+impl NoiseBuilder {
+    /// Creates a [Custom](NoiseBuilder::Custom) node from a function of `[x, z]`
+    pub fn custom(function: impl Fn([f64; 2]) -> f64 + Send + Sync + 'static) -> Self {
+        Self::Custom(Arc::new(function))
+    }
+
+    build_fn!(
+        /// Builds the noise for sampling at `[x, z]`
+        build2 -> DynNoise
+    );
+
+    build_fn!(
+        /// Builds the noise for sampling at `[x, z, y]`.
+        /// The first two axes are the same as in [build2](Self::build2), so a tree can be shared between both
+        build3 -> DynNoise3
+    );
+
+    /// Adds a token to [parse](Self::parse), which is used when no built in token has the same name.  
+    /// The parser is given the tokens after the name, and should consume its arguments from them.
+    /// Registering a name again replaces the previous parser
+    pub fn register_token(
+        name: impl Into<String>,
+        parser: impl Fn(&mut Tokens) -> Result<NoiseBuilder, String> + Send + Sync + 'static,
+    ) {
+        token_registry()
+            .write()
+            .unwrap()
+            .insert(name.into(), Arc::new(parser));
     }
 
     /// Parses a simple format for defining noise.  
     /// Splits input into tokens by whitespace, and expects a single expression as input.
-    /// There are no parenthsies, so an expression could be something like `add {expr} {expr}`
-    /// Tokens are lowercase and named the same as their [NoiseBuilder] counterparts, except [Constant](NoiseBuilder::Constant) is just `c` and [ScaleInput](NoiseBuilder::ScaleInput) is `scalein`.  
+    /// Expressions are written in prefix form like `add {expr} {expr}`, or with the infix operators `+ - * / ^ min max`
+    /// like `perlin 0 * 20 + 64`. The arguments of prefix expressions do not contain infix operators unless they are
+    /// in parentheses, so `abs perlin 0 * 2` is `(abs perlin 0) * 2`.
+    /// Numbers can be used as constants, and `-` must be separated by whitespace when it is not part of a number  
+    /// Tokens are lowercase and named the same as their [NoiseBuilder] counterparts, except [Constant](NoiseBuilder::Constant) is just `c`, [ScaleInput](NoiseBuilder::ScaleInput) is `scalein` and [Exponent](NoiseBuilder::Exponent) is `exp`.  
     /// An example is given in 'terrain.yml', note that the formattig does not matter, as any whitspace causes a new token.  
-    /// When using an expression that takes 2 expressions with a constant, the constant should be supplied first
-    pub fn parse(string: &str) -> Result<Self, String> {
-        let mut tokens = string.split_whitespace();
-        let res = Self::from_tokens(&mut tokens);
-        if !tokens.next().is_none() {
-            Err("too many tokens".into())
-        } else {
-            res
+    /// `#` starts a comment that lasts until the end of the line, in YAML the noise must then be a block string (`|`)
+    /// so the line breaks are kept  
+    /// When using an expression that takes 2 expressions with a constant, the constant should be supplied first  
+    /// `scalein` and `translate` take an optional third value before their expression, which is only used for 3D noise.
+    /// A number right after the first two values is always read as the third value, so an expression starting with a
+    /// number has to be written with `c` or in parentheses, like `scalein 2 2 (4 * perlin 0)`  
+    /// `fbm`, `ridged` and `billow` take their arguments in the order of the fields of [Fbm](NoiseBuilder::Fbm),
+    /// [Ridged](NoiseBuilder::Ridged) and [Billow](NoiseBuilder::Billow)  
+    /// `worley` takes a seed, a frequency and `distance` or `value`  
+    /// `turbulence` takes the fields of [Turbulence](NoiseBuilder::Turbulence) in order  
+    /// `blend` and `lerp` take the control expression first, `select` takes the bounds and falloff before the control,
+    /// `a` and `b` expressions  
+    /// `curve`, `terrace` and `spline` take the amount of points first, followed by the points and the expression,
+    /// like `curve 4 -1 -1 0 0.2 0.5 0.4 1 1 {expr}`  
+    /// `remap` takes the start and end of the range it maps from, then the start and end of the range it maps to  
+    /// `distancefalloff` takes the x and z of the center, the radius and `linear`, `smooth` or `dome`  
+    /// Tokens added with [register_token](Self::register_token) are parsed by their registered parser  
+    /// Expressions can be preceded by `let {name} = {expr}` definitions, after which the name can be used as an
+    /// expression, like `let mask = perlin 0 * 0.5 mask * 40 + mask`. Lets at the start of parentheses are only bound
+    /// until the closing parenthesis  
+    /// `fixed {expr}` keeps the seeds of the expression when a world seed is applied  
+    /// `$seed` can only be used with [parse_seeded](Self::parse_seeded)
+    pub fn parse(string: &str) -> Result<Self, ParseError> {
+        Self::parse_with(string, None)
+    }
+
+    /// Like [parse](Self::parse), but numbers can be written as `$seed`, `$seed+1` or `$seed-1`, which are replaced
+    /// by `seed` plus the offset. This lets the same config be used for worlds with different seeds, like
+    /// `perlin $seed add simplex $seed+1`.
+    /// There is no whitespace around the offset, `$seed + 1` adds 1 to the expression instead
+    pub fn parse_seeded(string: &str, seed: u64) -> Result<Self, ParseError> {
+        Self::parse_with(string, Some(seed))
+    }
+
+    /// [parse_seeded](Self::parse_seeded) if there is a seed, for configs with an optional seed
+    pub(crate) fn parse_with(string: &str, seed: Option<u64>) -> Result<Self, ParseError> {
+        let mut tokens = Tokens {
+            tokens: tokenize(string),
+            pos: 0,
+            names: vec![],
+            seed,
+        };
+        Self::parse_tokens(&mut tokens).map_err(|expected| tokens.error(string, expected))
+    }
+
+    fn parse_tokens(tokens: &mut Tokens) -> Result<Self, String> {
+        let res = Self::scoped(tokens)?;
+        if tokens.next().is_some() {
+            return Err("the end of the expression".into());
+        }
+        Ok(res)
+    }
+
+    /// Parses `let` definitions followed by the expression they are bound in, the names are unbound after it
+    fn scoped(tokens: &mut Tokens) -> Result<Self, String> {
+        let bound = tokens.names.len();
+        let mut lets = vec![];
+        while tokens.peek() == Some(&"let") {
+            tokens.next();
+            let name = match tokens.next() {
+                Some(name) if is_name(name) => name,
+                _ => return Err("a name for the let".into()),
+            };
+            if tokens.next() != Some("=") {
+                return Err("'=' after the name of the let".into());
+            }
+            lets.push((name, Self::from_tokens(tokens)?));
+            tokens.names.push(name);
+        }
+        let res = Self::from_tokens(tokens)?;
+        tokens.names.truncate(bound);
+        Ok(lets
+            .into_iter()
+            .rev()
+            .fold(res, |body, (name, value)| Self::Let {
+                name: name.into(),
+                value: Box::new(value),
+                body: Box::new(body),
+            }))
+    }
+
+    fn from_tokens(tokens: &mut Tokens) -> Result<Self, String> {
+        Self::infix(tokens, 0)
+    }
+
+    /// Parses an expression where operators binding weaker than `min_precedence` are left for the caller
+    fn infix(tokens: &mut Tokens, min_precedence: u8) -> Result<Self, String> {
+        let mut lhs = Self::primary(tokens)?;
+        while let Some(&op) = tokens.peek() {
+            let Some(precedence) = precedence(op) else {
+                break;
+            };
+            if precedence < min_precedence {
+                break;
+            }
+            tokens.next();
+            // ^ is right associative, so its right side may contain another ^
+            let next = if op == "^" {
+                precedence
+            } else {
+                precedence + 1
+            };
+            let rhs = Self::infix(tokens, next)?;
+            lhs = Self::binary(op, lhs, rhs);
+        }
+        Ok(lhs)
+    }
+
+    /// Converts an infix operator to nodes, constants are put first so the optimizations of the build function apply
+    fn binary(op: &str, a: Self, b: Self) -> Self {
+        let (a, b) = match (a, b) {
+            (a, b @ Self::Constant(_))
+                if !matches!(a, Self::Constant(_)) && matches!(op, "+" | "*" | "min" | "max") =>
+            {
+                (b, a)
+            }
+            (a, b) => (a, b),
+        };
+        let (a, b) = (Box::new(a), Box::new(b));
+        match op {
+            "+" => Self::Add(a, b),
+            "-" => match *b {
+                Self::Constant(v) => Self::Add(Box::new(Self::Constant(-v)), a),
+                _ => Self::Add(a, Box::new(Self::Neg(b))),
+            },
+            "*" => Self::Mul(a, b),
+            "/" => match *b {
+                Self::Constant(v) => Self::Mul(Box::new(Self::Constant(1.0 / v)), a),
+                _ => Self::Mul(a, Box::new(Self::PowI(-1, b))),
+            },
+            "^" => match *b {
+                Self::Constant(v) if v.fract() == 0.0 => Self::PowI(v as i32, a),
+                _ => Self::Pow(a, b),
+            },
+            "min" => Self::Min(a, b),
+            "max" => Self::Max(a, b),
+            _ => unreachable!("unknown operator '{op}'"),
         }
     }
 
-    fn from_tokens(tokens: &mut SplitWhitespace) -> Result<Self, String> {
+    /// Parses an expression without infix operators, unless they are in parentheses
+    fn primary(tokens: &mut Tokens) -> Result<Self, String> {
+        match tokens.peek() {
+            Some(&"(") => {
+                tokens.next();
+                let expr = Self::scoped(tokens)?;
+                return match tokens.next() {
+                    Some(")") => Ok(expr),
+                    _ => Err("')'".into()),
+                };
+            }
+            Some(&"-") => {
+                tokens.next();
+                return Ok(Self::Neg(eval(tokens)?));
+            }
+            Some(&t) => {
+                // placeholders can be used as constants as well
+                let substituted = tokens.substitute(t);
+                if let Ok(v) = substituted.as_deref().unwrap_or(t).parse() {
+                    tokens.next();
+                    return Ok(Self::Constant(v));
+                }
+            }
+            None => {}
+        }
         let next = tokens.next();
         match next {
+            Some(t) if tokens.names.contains(&t) => Ok(Self::Var(t.into())),
             Some(t) => match t {
                 "c" => Ok(Self::Constant(parse(tokens)?)),
                 "abs" => Ok(Self::Abs(eval(tokens)?)),
@@ -105,53 +628,891 @@ impl NoiseBuilder {
                 "scalein" => Ok(Self::ScaleInput(
                     parse(tokens)?,
                     parse(tokens)?,
+                    parse_optional(tokens),
                     eval(tokens)?,
                 )),
                 "clamp" => Ok(Self::Clamp(parse(tokens)?, parse(tokens)?, eval(tokens)?)),
+                "scalebias" => Ok(Self::ScaleBias {
+                    scale: parse(tokens)?,
+                    bias: parse(tokens)?,
+                    source: eval(tokens)?,
+                }),
                 "checkerboard" => Ok(Self::Checkerboard),
                 "perlin" => Ok(Self::Perlin(parse(tokens)?)),
                 "simplex" => Ok(Self::Simplex(parse(tokens)?)),
-                _ => Err(format!("Invalid token: '{t}'")),
+                "opensimplex" => Ok(Self::OpenSimplex(parse(tokens)?)),
+                "supersimplex" => Ok(Self::SuperSimplex(parse(tokens)?)),
+                "value" => Ok(Self::Value(parse(tokens)?)),
+                "fbm" => Ok(Self::Fbm {
+                    seed: parse(tokens)?,
+                    octaves: parse(tokens)?,
+                    frequency: parse(tokens)?,
+                    lacunarity: parse(tokens)?,
+                    persistence: parse(tokens)?,
+                }),
+                "ridged" => Ok(Self::Ridged {
+                    seed: parse(tokens)?,
+                    octaves: parse(tokens)?,
+                    frequency: parse(tokens)?,
+                    lacunarity: parse(tokens)?,
+                    persistence: parse(tokens)?,
+                    attenuation: parse(tokens)?,
+                }),
+                "billow" => Ok(Self::Billow {
+                    seed: parse(tokens)?,
+                    octaves: parse(tokens)?,
+                    frequency: parse(tokens)?,
+                    lacunarity: parse(tokens)?,
+                    persistence: parse(tokens)?,
+                }),
+                "worley" => Ok(Self::Worley {
+                    seed: parse(tokens)?,
+                    frequency: parse(tokens)?,
+                    return_type: match tokens.next() {
+                        Some("distance") => WorleyReturn::Distance,
+                        Some("value") => WorleyReturn::Value,
+                        _ => return Err("`distance` or `value`".into()),
+                    },
+                }),
+                "turbulence" => Ok(Self::Turbulence {
+                    seed: parse(tokens)?,
+                    frequency: parse(tokens)?,
+                    power: parse(tokens)?,
+                    roughness: parse(tokens)?,
+                    source: eval(tokens)?,
+                }),
+                "warp" => Ok(Self::Warp(eval(tokens)?, eval(tokens)?, eval(tokens)?)),
+                "blend" => Ok(Self::Blend(eval(tokens)?, eval(tokens)?, eval(tokens)?)),
+                "select" => {
+                    let bounds = (parse(tokens)?, parse(tokens)?);
+                    let falloff = parse(tokens)?;
+                    Ok(Self::Select {
+                        control: eval(tokens)?,
+                        a: eval(tokens)?,
+                        b: eval(tokens)?,
+                        bounds,
+                        falloff,
+                    })
+                }
+                "curve" => {
+                    let count = parse_count(tokens, 4)?;
+                    let points = (0..count)
+                        .map(|_| Ok((parse(tokens)?, parse(tokens)?)))
+                        .collect::<Result<_, String>>()?;
+                    Ok(Self::Curve(points, eval(tokens)?))
+                }
+                "terrace" => {
+                    let count = parse_count(tokens, 2)?;
+                    let levels = (0..count)
+                        .map(|_| parse(tokens))
+                        .collect::<Result<_, _>>()?;
+                    Ok(Self::Terrace(levels, eval(tokens)?))
+                }
+                "exp" => Ok(Self::Exponent(parse(tokens)?, eval(tokens)?)),
+                "rotate" => Ok(Self::Rotate(parse(tokens)?, eval(tokens)?)),
+                "translate" => Ok(Self::Translate(
+                    parse(tokens)?,
+                    parse(tokens)?,
+                    parse_optional(tokens),
+                    eval(tokens)?,
+                )),
+                "lerp" => Ok(Self::Lerp(eval(tokens)?, eval(tokens)?, eval(tokens)?)),
+                "remap" => {
+                    let from: (f64, f64) = (parse(tokens)?, parse(tokens)?);
+                    if from.0 == from.1 {
+                        return Err("a range that is not empty".into());
+                    }
+                    Ok(Self::Remap {
+                        from,
+                        to: (parse(tokens)?, parse(tokens)?),
+                        source: eval(tokens)?,
+                    })
+                }
+                "spline" => {
+                    let count = parse_count(tokens, 2)?;
+                    let points = (0..count)
+                        .map(|_| Ok((parse(tokens)?, parse(tokens)?, parse(tokens)?)))
+                        .collect::<Result<_, String>>()?;
+                    Ok(Self::Spline(points, eval(tokens)?))
+                }
+                "distancefalloff" => {
+                    let center = (parse(tokens)?, parse(tokens)?);
+                    let radius: f64 = parse(tokens)?;
+                    if radius.is_nan() || radius <= 0.0 {
+                        return Err("a positive radius".into());
+                    }
+                    Ok(Self::DistanceFalloff {
+                        center,
+                        radius,
+                        curve: match tokens.next() {
+                            Some("linear") => FalloffCurve::Linear,
+                            Some("smooth") => FalloffCurve::Smooth,
+                            Some("dome") => FalloffCurve::Dome,
+                            _ => return Err("`linear`, `smooth` or `dome`".into()),
+                        },
+                    })
+                }
+                "fixed" => Ok(Self::Fixed(eval(tokens)?)),
+                _ => match registered_token(t) {
+                    Some(parser) => parser(tokens),
+                    None => Err("an expression".into()),
+                },
+            },
+            None => Err("an expression".into()),
+        }
+    }
+}
+
+/// Tokens of a noise expression being parsed, given to [TokenParser]s to read their arguments
+pub struct Tokens<'a> {
+    tokens: Vec<&'a str>,
+    /// Index of the next token, past the end once it has run out of tokens
+    pos: usize,
+    /// Names bound by lets so far
+    names: Vec<&'a str>,
+    /// Value of `$seed`, see [NoiseBuilder::parse_seeded]
+    seed: Option<u64>,
+}
+
+impl<'a> Tokens<'a> {
+    /// Parses the next expression, like the arguments of the built in tokens it only contains infix operators when
+    /// they are in parentheses
+    pub fn expr(&mut self) -> Result<NoiseBuilder, String> {
+        NoiseBuilder::primary(self)
+    }
+
+    /// Parses the next token as a value, like a number or a seed
+    pub fn value<T: FromStr>(&mut self) -> Result<T, String> {
+        parse(self)
+    }
+
+    /// Parses the next token as a value only if it can be parsed, for optional arguments
+    pub fn optional<T: FromStr>(&mut self) -> Option<T> {
+        parse_optional(self)
+    }
+
+    /// Replaces a `$seed` placeholder with the seed, folded into 32 bits like the seeds of the nodes.
+    /// Other tokens are returned as they are
+    fn substitute(&self, token: &'a str) -> Result<Cow<'a, str>, String> {
+        let Some(rest) = token.strip_prefix("$seed") else {
+            return Ok(Cow::Borrowed(token));
+        };
+        let Some(seed) = self.seed else {
+            return Err("a number, `$seed` needs a seed given to parse_seeded".into());
+        };
+        let offset = match rest {
+            "" => 0,
+            _ => match rest.strip_prefix('+').unwrap_or(rest).parse::<i64>() {
+                Ok(offset) => offset,
+                Err(_) => return Err("`$seed`, `$seed+{offset}` or `$seed-{offset}`".into()),
+            },
+        };
+        let seed = seed.wrapping_add_signed(offset);
+        Ok(Cow::Owned(((seed ^ (seed >> 32)) as u32).to_string()))
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let token = self.tokens.get(self.pos).copied();
+        self.pos += 1;
+        token
+    }
+
+    fn peek(&mut self) -> Option<&&'a str> {
+        self.tokens.get(self.pos)
+    }
+
+    /// Error at the last token that was consumed, or at the end if it ran out of tokens
+    fn error(&self, string: &str, expected: String) -> ParseError {
+        let last = self.pos.checked_sub(1);
+        match last.and_then(|i| self.tokens.get(i)) {
+            Some(token) => {
+                // tokens are slices of the string
+                let offset = token.as_ptr() as usize - string.as_ptr() as usize;
+                ParseError {
+                    index: self.pos - 1,
+                    offset,
+                    line: string[..offset].matches('\n').count() + 1,
+                    token: Some(token.to_string()),
+                    expected,
+                }
+            }
+            None => ParseError {
+                index: self.tokens.len(),
+                offset: string.len(),
+                line: string.trim_end().matches('\n').count() + 1,
+                token: None,
+                expected,
             },
-            None => Err("Not enough tokens".into()),
         }
     }
 }
 
-fn eval(tokens: &mut SplitWhitespace) -> Result<Box<NoiseBuilder>, String> {
-    match NoiseBuilder::from_tokens(tokens) {
+/// Splits the string at whitespace, and around parentheses and the operators other than `-`, as that would split
+/// negative numbers. Placeholders like `$seed+1` are only split around parentheses.
+/// Comments from `#` to the end of the line are skipped
+fn tokenize(string: &str) -> Vec<&str> {
+    let mut tokens = vec![];
+    let code = string.lines().flat_map(|line| {
+        line.split('#')
+            .next()
+            .unwrap_or_default()
+            .split_whitespace()
+    });
+    for word in code {
+        let mut start = 0;
+        let placeholder = word.starts_with('$');
+        for (i, c) in word.char_indices() {
+            let operator = matches!(c, '+' | '*' | '/' | '^') && !placeholder;
+            if matches!(c, '(' | ')') || operator {
+                if start < i {
+                    tokens.push(&word[start..i]);
+                }
+                tokens.push(&word[i..i + 1]);
+                start = i + 1;
+            }
+        }
+        if start < word.len() {
+            tokens.push(&word[start..]);
+        }
+    }
+    tokens
+}
+
+/// Tokens of the expressions built into the parser, see [NoiseBuilder::parse]
+const BUILTIN_TOKENS: &[&str] = &[
+    "c",
+    "abs",
+    "neg",
+    "add",
+    "mul",
+    "min",
+    "max",
+    "pow",
+    "powi",
+    "scalein",
+    "clamp",
+    "scalebias",
+    "checkerboard",
+    "perlin",
+    "simplex",
+    "opensimplex",
+    "supersimplex",
+    "value",
+    "fbm",
+    "ridged",
+    "billow",
+    "worley",
+    "turbulence",
+    "warp",
+    "blend",
+    "select",
+    "curve",
+    "terrace",
+    "exp",
+    "rotate",
+    "translate",
+    "lerp",
+    "remap",
+    "spline",
+    "distancefalloff",
+    "fixed",
+];
+
+/// If a token can be bound by a let, it can not be a number, placeholder, operator, parenthesis or built in token,
+/// as the name would hide the token
+fn is_name(token: &str) -> bool {
+    token != "let"
+        && token != "="
+        && !BUILTIN_TOKENS.contains(&token)
+        && precedence(token).is_none()
+        && !matches!(token, "(" | ")")
+        && !token.starts_with('$')
+        && token.parse::<f64>().is_err()
+}
+
+/// Precedence of an infix operator, higher binds stronger
+fn precedence(op: &str) -> Option<u8> {
+    match op {
+        "min" | "max" => Some(1),
+        "+" | "-" => Some(2),
+        "*" | "/" => Some(3),
+        "^" => Some(4),
+        _ => None,
+    }
+}
+
+/// Error from [NoiseBuilder::parse], pointing at the token that could not be parsed
+#[derive(Clone, PartialEq, Eq, Debug)]
+pub struct ParseError {
+    /// Index of the token, comments are not counted
+    pub index: usize,
+    /// Byte offset of the token in the parsed string, the length of the string if it ran out of tokens
+    pub offset: usize,
+    /// Line of the token, starting at 1
+    pub line: usize,
+    /// The token that could not be parsed, `None` if it ran out of tokens
+    pub token: Option<String>,
+    /// Description of what was expected instead, like `a number`
+    pub expected: String,
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.token {
+            Some(token) => write!(
+                f,
+                "expected {}, found '{token}' (token {} on line {})",
+                self.expected, self.index, self.line
+            ),
+            None => write!(f, "expected {}, but ran out of tokens", self.expected),
+        }
+    }
+}
+
+impl std::error::Error for ParseError {}
+
+/// Lets configs parse noise with `?` in functions returning `Result<_, String>`
+impl From<ParseError> for String {
+    fn from(error: ParseError) -> Self {
+        error.to_string()
+    }
+}
+
+/// Parses the arguments of a registered token, see [NoiseBuilder::register_token].  
+/// Errors describe what was expected at the last token that was consumed, like `a seed`
+pub type TokenParser = Arc<dyn Fn(&mut Tokens) -> Result<NoiseBuilder, String> + Send + Sync>;
+
+fn token_registry() -> &'static RwLock<HashMap<String, TokenParser>> {
+    static REGISTRY: OnceLock<RwLock<HashMap<String, TokenParser>>> = OnceLock::new();
+    REGISTRY.get_or_init(Default::default)
+}
+
+fn registered_token(token: &str) -> Option<TokenParser> {
+    token_registry().read().unwrap().get(token).cloned()
+}
+
+fn eval(tokens: &mut Tokens) -> Result<Box<NoiseBuilder>, String> {
+    match NoiseBuilder::primary(tokens) {
         Ok(v) => Ok(Box::new(v)),
         Err(e) => Err(e),
     }
 }
 
-fn parse<T: FromStr>(tokens: &mut SplitWhitespace) -> Result<T, String> {
+fn parse<T: FromStr>(tokens: &mut Tokens) -> Result<T, String> {
     match tokens.next() {
-        Some(v) => match v.parse() {
+        Some(v) => match tokens.substitute(v)?.parse() {
             Ok(v) => Ok(v),
-            Err(_) => Err(format!("could not parse float '{v}'")),
+            Err(_) => Err("a number".into()),
         },
-        None => Err("Expected float, but ran out of tokens".into()),
+        None => Err("a number".into()),
     }
 }
 
-pub struct DynNoise(Box<dyn NoiseFn<f64, 2> + Send + Sync>);
+/// Parses the length of a list of control points, which must be at least `min`
+fn parse_count(tokens: &mut Tokens, min: usize) -> Result<usize, String> {
+    let count = parse(tokens)?;
+    if count < min {
+        return Err(format!("at least {min} control points"));
+    }
+    Ok(count)
+}
+
+/// Consumes the next token only if it can be parsed, used for optional trailing arguments
+fn parse_optional<T: FromStr>(tokens: &mut Tokens) -> Option<T> {
+    let token = *tokens.peek()?;
+    let v = tokens.substitute(token).ok()?.parse().ok()?;
+    tokens.next();
+    Some(v)
+}
+
+pub struct DynNoise {
+    noise: Box<dyn NoiseFn<f64, 2> + Send + Sync>,
+}
+
+impl DynNoise {
+    fn new(source: impl NoiseFn<f64, 2> + 'static + Send + Sync) -> Self {
+        Self {
+            noise: Box::new(source),
+        }
+    }
+
+    fn bind(name: String, value: Self, body: impl FnOnce() -> Self) -> Self {
+        bind(&SCOPE2, name, value, body)
+    }
+
+    fn var(name: &str) -> Option<SharedNoise<Self>> {
+        var(&SCOPE2, name)
+    }
+
+    fn scale_input(self, x: f64, y: f64, _z: Option<f64>) -> Self {
+        Self::new(ScalePoint::new(self).set_x_scale(x).set_y_scale(y))
+    }
+
+    fn translate(self, x: f64, y: f64, _z: Option<f64>) -> Self {
+        Self::new(
+            TranslatePoint::new(self)
+                .set_x_translation(x)
+                .set_y_translation(y),
+        )
+    }
+}
 
 impl NoiseFn<f64, 2> for DynNoise {
     #[inline]
     fn get(&self, point: [f64; 2]) -> f64 {
-        self.0.get(point)
+        self.noise.get(point)
     }
 }
 
-fn dynn(source: impl NoiseFn<f64, 2> + 'static + Send + Sync) -> DynNoise {
-    DynNoise(Box::new(source))
+/// 3D version of [DynNoise], sampled at `[x, z, y]`
+pub struct DynNoise3 {
+    noise: Box<dyn NoiseFn<f64, 3> + Send + Sync>,
 }
-struct PowINoise<T: NoiseFn<f64, 2>>(T, i32);
 
-impl<T: NoiseFn<f64, 2>> NoiseFn<f64, 2> for PowINoise<T> {
+impl DynNoise3 {
+    fn new(source: impl NoiseFn<f64, 3> + 'static + Send + Sync) -> Self {
+        Self {
+            noise: Box::new(source),
+        }
+    }
+
+    fn bind(name: String, value: Self, body: impl FnOnce() -> Self) -> Self {
+        bind(&SCOPE3, name, value, body)
+    }
+
+    fn var(name: &str) -> Option<SharedNoise<Self>> {
+        var(&SCOPE3, name)
+    }
+
+    fn scale_input(self, x: f64, y: f64, z: Option<f64>) -> Self {
+        Self::new(
+            ScalePoint::new(self)
+                .set_x_scale(x)
+                .set_y_scale(y)
+                .set_z_scale(z.unwrap_or(1.0)),
+        )
+    }
+
+    fn translate(self, x: f64, y: f64, z: Option<f64>) -> Self {
+        Self::new(
+            TranslatePoint::new(self)
+                .set_x_translation(x)
+                .set_y_translation(y)
+                .set_z_translation(z.unwrap_or(0.0)),
+        )
+    }
+}
+
+impl NoiseFn<f64, 3> for DynNoise3 {
     #[inline]
-    fn get(&self, point: [f64; 2]) -> f64 {
+    fn get(&self, point: [f64; 3]) -> f64 {
+        self.noise.get(point)
+    }
+}
+
+struct PowINoise<T>(T, i32);
+
+impl<T: NoiseFn<f64, D>, const D: usize> NoiseFn<f64, D> for PowINoise<T> {
+    #[inline]
+    fn get(&self, point: [f64; D]) -> f64 {
         self.0.get(point).powi(self.1)
     }
 }
+
+/// Offsets the x and z input of the first noise by the other two, see [NoiseBuilder::Warp]
+struct WarpNoise<T>(T, T, T);
+
+impl<T: NoiseFn<f64, D>, const D: usize> NoiseFn<f64, D> for WarpNoise<T> {
+    #[inline]
+    fn get(&self, point: [f64; D]) -> f64 {
+        let mut warped = point;
+        warped[0] += self.1.get(point);
+        warped[1] += self.2.get(point);
+        self.0.get(warped)
+    }
+}
+
+/// Interpolates between the last two noises by the first, see [NoiseBuilder::Lerp]
+struct LerpNoise<T>(T, T, T);
+
+impl<T: NoiseFn<f64, D>, const D: usize> NoiseFn<f64, D> for LerpNoise<T> {
+    #[inline]
+    fn get(&self, point: [f64; D]) -> f64 {
+        let t = self.0.get(point);
+        let a = self.1.get(point);
+        a + (self.2.get(point) - a) * t
+    }
+}
+
+/// Vanilla style cubic spline, see [NoiseBuilder::Spline]
+struct SplineNoise<T> {
+    source: T,
+    /// Sorted by input
+    points: Vec<(f64, f64, f64)>,
+}
+
+impl<T> SplineNoise<T> {
+    fn new(source: T, mut points: Vec<(f64, f64, f64)>) -> Self {
+        points.sort_by(|a, b| a.0.total_cmp(&b.0));
+        Self { source, points }
+    }
+}
+
+impl<T: NoiseFn<f64, D>, const D: usize> NoiseFn<f64, D> for SplineNoise<T> {
+    /// 0 without points, which only trees that were not [validated](NoiseBuilder::validate) can have
+    fn get(&self, point: [f64; D]) -> f64 {
+        if self.points.is_empty() {
+            return 0.0;
+        }
+        let x = self.source.get(point);
+        let i = self.points.partition_point(|p| p.0 <= x);
+        if i == 0 || i == self.points.len() {
+            let (x0, y0, d0) = self.points[i.saturating_sub(1)];
+            return y0 + d0 * (x - x0);
+        }
+        let ((x0, y0, d0), (x1, y1, d1)) = (self.points[i - 1], self.points[i]);
+        let width = x1 - x0;
+        let t = (x - x0) / width;
+        let lerp = |t: f64, a: f64, b: f64| a + (b - a) * t;
+        lerp(t, y0, y1) + t * (1.0 - t) * lerp(t, d0 * width - (y1 - y0), -d1 * width + (y1 - y0))
+    }
+}
+
+/// See [NoiseBuilder::DistanceFalloff]
+struct FalloffNoise {
+    center: (f64, f64),
+    radius: f64,
+    curve: FalloffCurve,
+}
+
+impl<const D: usize> NoiseFn<f64, D> for FalloffNoise {
+    fn get(&self, point: [f64; D]) -> f64 {
+        let (dx, dz) = (point[0] - self.center.0, point[1] - self.center.1);
+        // 0 at the center and 1 at the radius
+        let t = ((dx * dx + dz * dz).sqrt() / self.radius).min(1.0);
+        match self.curve {
+            FalloffCurve::Linear => 1.0 - t,
+            FalloffCurve::Smooth => 1.0 - t * t * (3.0 - 2.0 * t),
+            FalloffCurve::Dome => 1.0 - t * t,
+        }
+    }
+}
+
+/// See [NoiseBuilder::Custom]
+struct CustomNoise(CustomFn);
+
+impl<const D: usize> NoiseFn<f64, D> for CustomNoise {
+    #[inline]
+    fn get(&self, point: [f64; D]) -> f64 {
+        (self.0)([point[0], point[1]])
+    }
+}
+
+/// Names bound by the [Let](NoiseBuilder::Let)s around the node being built
+type Scope<N> = RefCell<Vec<(String, SharedNoise<N>)>>;
+
+thread_local! {
+    static SCOPE2: Scope<DynNoise> = const { RefCell::new(Vec::new()) };
+    static SCOPE3: Scope<DynNoise3> = const { RefCell::new(Vec::new()) };
+    /// Last point and value of each [SharedNoise] sampled on this thread
+    static SHARED_CACHE: RefCell<Vec<(usize, [f64; 3], f64)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Entries of the cache of shared noise, old entries are dropped as they are left behind by reloads
+const SHARED_CACHE_SIZE: usize = 32;
+
+fn bind<N>(
+    scope: &'static LocalKey<Scope<N>>,
+    name: String,
+    value: N,
+    body: impl FnOnce() -> N,
+) -> N {
+    let shared = SharedNoise::new(value);
+    scope.with(|scope| scope.borrow_mut().push((name, shared)));
+    let body = body();
+    scope.with(|scope| scope.borrow_mut().pop());
+    body
+}
+
+fn var<N>(scope: &'static LocalKey<Scope<N>>, name: &str) -> Option<SharedNoise<N>> {
+    scope.with(|scope| {
+        let scope = scope.borrow();
+        let (_, shared) = scope.iter().rev().find(|(bound, _)| bound == name)?;
+        Some(shared.clone())
+    })
+}
+
+/// Noise bound by a [Let](NoiseBuilder::Let), sampling the same point again gives the cached value
+struct SharedNoise<N> {
+    noise: Arc<N>,
+    id: usize,
+}
+
+impl<N> SharedNoise<N> {
+    fn new(noise: N) -> Self {
+        static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+        Self {
+            noise: Arc::new(noise),
+            id: NEXT_ID.fetch_add(1, Ordering::Relaxed),
+        }
+    }
+}
+
+impl<N> Clone for SharedNoise<N> {
+    fn clone(&self) -> Self {
+        Self {
+            noise: self.noise.clone(),
+            id: self.id,
+        }
+    }
+}
+
+impl<N: NoiseFn<f64, D>, const D: usize> NoiseFn<f64, D> for SharedNoise<N> {
+    fn get(&self, point: [f64; D]) -> f64 {
+        let mut key = [0.0; 3];
+        key[..D].copy_from_slice(&point);
+        let cached = SHARED_CACHE.with(|cache| {
+            let cache = cache.borrow();
+            let (_, _, value) = cache
+                .iter()
+                .find(|(id, p, _)| *id == self.id && *p == key)?;
+            Some(*value)
+        });
+        if let Some(value) = cached {
+            return value;
+        }
+        // the cache is not borrowed while sampling, as the noise can contain other shared noise
+        let value = self.noise.get(point);
+        SHARED_CACHE.with(|cache| {
+            let mut cache = cache.borrow_mut();
+            match cache.iter_mut().find(|(id, _, _)| *id == self.id) {
+                Some(entry) => *entry = (self.id, key, value),
+                None => {
+                    if cache.len() >= SHARED_CACHE_SIZE {
+                        cache.remove(0);
+                    }
+                    cache.push((self.id, key, value));
+                }
+            }
+        });
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const POINTS: [[f64; 2]; 4] = [[0.3, 0.7], [-4.2, 1.9], [12.5, -3.1], [100.25, 40.75]];
+
+    /// `perlin 0 + 2 * perlin 1`
+    fn tree() -> NoiseBuilder {
+        NoiseBuilder::Add(
+            Box::new(NoiseBuilder::Perlin(0)),
+            Box::new(NoiseBuilder::Mul(
+                Box::new(NoiseBuilder::Constant(2.0)),
+                Box::new(NoiseBuilder::Perlin(1)),
+            )),
+        )
+    }
+
+    #[test]
+    fn build2_and_build3_share_nodes() {
+        let (noise2, noise3) = (tree().build2(), tree().build3());
+        let (a, b) = (Perlin::new(0), Perlin::new(1));
+        for [x, z] in POINTS {
+            assert_eq!(noise2.get([x, z]), a.get([x, z]) + 2.0 * b.get([x, z]));
+            for y in [-1.5, 0.4, 8.8] {
+                let point = [x, z, y];
+                assert_eq!(noise3.get(point), a.get(point) + 2.0 * b.get(point));
+            }
+        }
+    }
+
+    #[test]
+    fn build2_and_build3_agree_on_lattice() {
+        // gradient noise is 0 on integer points in any arity
+        let (noise2, noise3) = (tree().build2(), tree().build3());
+        for x in -3..3 {
+            for z in -3..3 {
+                let (x, z) = (x as f64, z as f64);
+                assert_eq!(noise2.get([x, z]), 0.0);
+                assert_eq!(noise3.get([x, z, 5.0]), 0.0);
+            }
+        }
+    }
+
+    #[test]
+    fn scale_input_scales_shared_axes() {
+        let scaled = |z| NoiseBuilder::ScaleInput(0.5, 0.25, z, Box::new(tree()));
+        let (noise2, noise3) = (scaled(None).build2(), scaled(None).build3());
+        let (plain2, plain3) = (tree().build2(), tree().build3());
+        for [x, z] in POINTS {
+            assert_eq!(noise2.get([x, z]), plain2.get([x * 0.5, z * 0.25]));
+            // without a z scale the y axis is left as it is
+            assert_eq!(
+                noise3.get([x, z, 3.3]),
+                plain3.get([x * 0.5, z * 0.25, 3.3])
+            );
+        }
+        let noise3 = scaled(Some(2.0)).build3();
+        for [x, z] in POINTS {
+            assert_eq!(
+                noise3.get([x, z, 3.3]),
+                plain3.get([x * 0.5, z * 0.25, 6.6])
+            );
+        }
+    }
+
+    #[test]
+    fn scalebias_parses() {
+        let noise = NoiseBuilder::parse("scalebias 2.5 -3 perlin 7").unwrap();
+        let NoiseBuilder::ScaleBias {
+            scale,
+            bias,
+            source,
+        } = &noise
+        else {
+            panic!("scalebias was not parsed");
+        };
+        assert_eq!((*scale, *bias), (2.5, -3.0));
+        assert!(matches!(**source, NoiseBuilder::Perlin(7)));
+        // written back it parses to the same noise
+        let reparsed = NoiseBuilder::parse(&noise.to_string()).unwrap();
+        assert_eq!(reparsed.to_string(), noise.to_string());
+    }
+
+    #[test]
+    fn scalebias_scales_and_biases() {
+        let noise = NoiseBuilder::parse("scalebias 2.5 -3 perlin 7")
+            .unwrap()
+            .build2();
+        let source = Perlin::new(7);
+        for point in POINTS {
+            let expected = source.get(point) * 2.5 - 3.0;
+            assert!((noise.get(point) - expected).abs() < 1e-12);
+        }
+    }
+
+    #[test]
+    fn display_parses_back() {
+        let written = [
+            "let mask = perlin 0 * 0.5 mask * 40 + mask",
+            "scalein 0.01 0.02 0.5 fbm 1 4 1 2 0.5 + select -0.5 0.5 0.1 simplex 2 c 1 worley 3 0.2 distance",
+            "curve 4 -1 -1 0 0.2 0.5 0.4 1 1 terrace 2 -0.5 0.5 spline 2 -1 0 1 1 2 0 perlin 4",
+            "remap -1 1 60 120 translate 5 -3 turbulence 6 0.1 2 3 distancefalloff 0 0 100 dome",
+            "fixed perlin 7 max lerp value 8 ridged 9 3 1 2 0.5 1 billow 10 2 1 2 0.5",
+        ];
+        for string in written {
+            let noise = NoiseBuilder::parse(string).unwrap();
+            for printed in [noise.to_string(), noise.to_config_string()] {
+                let reparsed = NoiseBuilder::parse(&printed).unwrap();
+                assert_eq!(reparsed.to_string(), noise.to_string());
+            }
+        }
+    }
+
+    #[test]
+    fn nested_let_parses_back() {
+        let scoped = |name: &str, value, body| NoiseBuilder::Let {
+            name: name.into(),
+            value: Box::new(value),
+            body: Box::new(body),
+        };
+        let var = |name: &str| NoiseBuilder::Var(name.into());
+        // the inner let is only bound on the left of the add
+        let noise = scoped(
+            "a",
+            NoiseBuilder::Perlin(0),
+            NoiseBuilder::Add(
+                Box::new(scoped("b", NoiseBuilder::Perlin(1), var("b"))),
+                Box::new(NoiseBuilder::Mul(
+                    Box::new(var("a")),
+                    Box::new(NoiseBuilder::Perlin(2)),
+                )),
+            ),
+        );
+        let reparsed = NoiseBuilder::parse(&noise.to_string()).unwrap();
+        assert_eq!(reparsed.to_string(), noise.to_string());
+        let (noise, reparsed) = (noise.build2(), reparsed.build2());
+        for point in POINTS {
+            assert_eq!(reparsed.get(point), noise.get(point));
+        }
+        assert!(NoiseBuilder::parse("(let b = perlin 1 b) + b").is_err());
+    }
+
+    #[test]
+    fn custom_does_not_parse_back() {
+        let noise = NoiseBuilder::Add(
+            Box::new(NoiseBuilder::Perlin(0)),
+            Box::new(NoiseBuilder::custom(|[x, z]| x + z)),
+        );
+        assert!(noise.to_string().contains("<custom>"));
+        assert!(NoiseBuilder::parse(&noise.to_string()).is_err());
+    }
+
+    #[test]
+    fn third_value_comes_before_expression() {
+        let scale = |string| match NoiseBuilder::parse(string).unwrap() {
+            NoiseBuilder::ScaleInput(_, _, z, source) => (z, *source),
+            _ => panic!("not a scalein"),
+        };
+        assert!(matches!(
+            scale("scalein 2 2 3 perlin 0"),
+            (Some(_), NoiseBuilder::Perlin(0))
+        ));
+        assert!(matches!(
+            scale("scalein 2 2 c 3"),
+            (None, NoiseBuilder::Constant(_))
+        ));
+        assert!(matches!(
+            scale("scalein 2 2 (3)"),
+            (None, NoiseBuilder::Constant(_))
+        ));
+        assert!(NoiseBuilder::parse("scalein 2 2 3").is_err());
+    }
+
+    #[test]
+    fn let_names_are_not_tokens() {
+        assert!(NoiseBuilder::parse("let perlin = c 1 perlin 0").is_err());
+        assert!(NoiseBuilder::parse("let hills = perlin 0 hills * 2").is_ok());
+    }
+
+    #[test]
+    fn validate_checks_nested_nodes() {
+        let spline = |points| NoiseBuilder::Spline(points, Box::new(NoiseBuilder::Perlin(0)));
+        let short = NoiseBuilder::Abs(Box::new(spline(vec![(0.0, 1.0, 0.0)])));
+        assert!(short.validate().is_err());
+        assert!(spline(vec![(0.0, 1.0, 0.0), (1.0, 2.0, 0.0)])
+            .validate()
+            .is_ok());
+        let remap = NoiseBuilder::Remap {
+            from: (1.0, 1.0),
+            to: (0.0, 10.0),
+            source: Box::new(NoiseBuilder::Perlin(0)),
+        };
+        assert!(remap.validate().is_err());
+        let terrace = NoiseBuilder::Terrace(vec![0.5], Box::new(NoiseBuilder::Perlin(0)));
+        assert!(terrace.validate().is_err());
+        let curve = NoiseBuilder::Curve(
+            vec![(-1.0, -1.0), (0.0, 0.0), (1.0, 1.0)],
+            Box::new(NoiseBuilder::Perlin(0)),
+        );
+        assert!(curve.validate().is_err());
+        let unbound = NoiseBuilder::Let {
+            name: "a".into(),
+            value: Box::new(NoiseBuilder::Var("a".into())),
+            body: Box::new(NoiseBuilder::Var("a".into())),
+        };
+        assert!(unbound.validate().is_err());
+        assert!(NoiseBuilder::parse("distancefalloff 0 0 0 linear").is_err());
+        let falloff = NoiseBuilder::DistanceFalloff {
+            center: (0.0, 0.0),
+            radius: -5.0,
+            curve: FalloffCurve::Linear,
+        };
+        assert!(falloff.validate().is_err());
+        // built anyway, the empty spline does not panic
+        assert_eq!(spline(vec![]).build2().get([0.5, 0.5]), 0.0);
+    }
+}
@@ -0,0 +1,247 @@
+//! Writing a [NoiseBuilder] back to the format read by [NoiseBuilder::parse], or as a graph for debugging
+
+use std::fmt;
+
+use super::{NoiseBuilder, WorleyReturn};
+
+/// Written in place of [Custom](NoiseBuilder::Custom) nodes, their functions can not be written
+const CUSTOM_PLACEHOLDER: &str = "<custom>";
+
+impl NoiseBuilder {
+    /// Writes the noise in the format of [parse](Self::parse) with one node per line, indented by depth, for
+    /// writing configs like 'terrain.yml'.
+    /// [Display](fmt::Display) writes the same tokens on a single line
+    pub fn to_config_string(&self) -> String {
+        let mut out = String::new();
+        self.write_tokens(&mut out, Some(0), true);
+        out
+    }
+
+    /// Writes the tree as a Graphviz DOT graph, with a node per expression labeled with its token and values.  
+    /// Render it with something like `dot -Tsvg noise.dot -o noise.svg`
+    pub fn to_dot(&self) -> String {
+        let mut out = String::from("digraph noise {\n    node [shape=box];\n");
+        self.write_dot(&mut out, &mut 0);
+        out.push_str("}\n");
+        out
+    }
+
+    /// Writes the node and its children, returning the id of the node
+    fn write_dot(&self, out: &mut String, next_id: &mut usize) -> usize {
+        let id = *next_id;
+        *next_id += 1;
+        let (label, mut children) = self.head();
+        if let Self::Let { body, .. } = self {
+            children.push(body);
+        }
+        let label = label.replace('\\', "\\\\").replace('"', "\\\"");
+        out.push_str(&format!("    n{id} [label=\"{label}\"];\n"));
+        for child in children {
+            let child_id = child.write_dot(out, next_id);
+            out.push_str(&format!("    n{id} -> n{child_id};\n"));
+        }
+        id
+    }
+
+    /// Writes the tokens of this node and its children, `depth` is `None` when writing a single line.  
+    /// `top` is set for the expression itself and the bodies of the lets at its start, other lets are put in
+    /// parentheses so their names are only bound within them
+    fn write_tokens(&self, out: &mut String, depth: Option<usize>, top: bool) {
+        let separate = |out: &mut String, depth: Option<usize>| match depth {
+            Some(depth) => {
+                out.push('\n');
+                out.push_str(&"  ".repeat(depth));
+            }
+            None => out.push(' '),
+        };
+        // lets are written before the body at the same depth
+        if let Self::Let { name, value, body } = self {
+            if !top {
+                out.push('(');
+            }
+            out.push_str(&format!("let {name} ="));
+            separate(out, depth.map(|d| d + 1));
+            value.write_tokens(out, depth.map(|d| d + 1), false);
+            separate(out, depth);
+            body.write_tokens(out, depth, true);
+            if !top {
+                out.push(')');
+            }
+            return;
+        }
+        let (head, children) = self.head();
+        out.push_str(&head);
+        for child in children {
+            separate(out, depth.map(|d| d + 1));
+            child.write_tokens(out, depth.map(|d| d + 1), false);
+        }
+    }
+
+    /// The token of the node followed by its values, and the expressions it takes after them
+    fn head(&self) -> (String, Vec<&NoiseBuilder>) {
+        let points = |points: Vec<String>| format!("{} {}", points.len(), points.join(" "));
+        let optional = |v: &Option<f64>| v.map(|v| format!(" {v}")).unwrap_or_default();
+        match self {
+            Self::Constant(v) => (format!("c {v}"), vec![]),
+            Self::Abs(a) => ("abs".into(), vec![a.as_ref()]),
+            Self::Neg(a) => ("neg".into(), vec![a.as_ref()]),
+            Self::Min(a, b) => ("min".into(), vec![a.as_ref(), b.as_ref()]),
+            Self::Max(a, b) => ("max".into(), vec![a.as_ref(), b.as_ref()]),
+            Self::Add(a, b) => ("add".into(), vec![a.as_ref(), b.as_ref()]),
+            Self::Mul(a, b) => ("mul".into(), vec![a.as_ref(), b.as_ref()]),
+            Self::Pow(a, b) => ("pow".into(), vec![a.as_ref(), b.as_ref()]),
+            Self::PowI(i, a) => (format!("powi {i}"), vec![a.as_ref()]),
+            Self::ScaleInput(x, y, z, a) => {
+                (format!("scalein {x} {y}{}", optional(z)), vec![a.as_ref()])
+            }
+            Self::Clamp(min, max, a) => (format!("clamp {min} {max}"), vec![a.as_ref()]),
+            Self::ScaleBias {
+                scale,
+                bias,
+                source,
+            } => (format!("scalebias {scale} {bias}"), vec![source.as_ref()]),
+            Self::Checkerboard => ("checkerboard".into(), vec![]),
+            Self::Perlin(seed) => (format!("perlin {seed}"), vec![]),
+            Self::Simplex(seed) => (format!("simplex {seed}"), vec![]),
+            Self::OpenSimplex(seed) => (format!("opensimplex {seed}"), vec![]),
+            Self::SuperSimplex(seed) => (format!("supersimplex {seed}"), vec![]),
+            Self::Value(seed) => (format!("value {seed}"), vec![]),
+            Self::Fbm {
+                seed,
+                octaves,
+                frequency,
+                lacunarity,
+                persistence,
+            } => (
+                format!("fbm {seed} {octaves} {frequency} {lacunarity} {persistence}"),
+                vec![],
+            ),
+            Self::Ridged {
+                seed,
+                octaves,
+                frequency,
+                lacunarity,
+                persistence,
+                attenuation,
+            } => (
+                format!(
+                    "ridged {seed} {octaves} {frequency} {lacunarity} {persistence} {attenuation}"
+                ),
+                vec![],
+            ),
+            Self::Billow {
+                seed,
+                octaves,
+                frequency,
+                lacunarity,
+                persistence,
+            } => (
+                format!("billow {seed} {octaves} {frequency} {lacunarity} {persistence}"),
+                vec![],
+            ),
+            Self::Worley {
+                seed,
+                frequency,
+                return_type,
+            } => {
+                let return_type = match return_type {
+                    WorleyReturn::Distance => "distance",
+                    WorleyReturn::Value => "value",
+                };
+                (format!("worley {seed} {frequency} {return_type}"), vec![])
+            }
+            Self::Turbulence {
+                seed,
+                frequency,
+                power,
+                roughness,
+                source,
+            } => (
+                format!("turbulence {seed} {frequency} {power} {roughness}"),
+                vec![source.as_ref()],
+            ),
+            Self::Warp(x, z, source) => {
+                ("warp".into(), vec![x.as_ref(), z.as_ref(), source.as_ref()])
+            }
+            Self::Blend(control, a, b) => (
+                "blend".into(),
+                vec![control.as_ref(), a.as_ref(), b.as_ref()],
+            ),
+            Self::Select {
+                control,
+                a,
+                b,
+                bounds,
+                falloff,
+            } => (
+                format!("select {} {} {falloff}", bounds.0, bounds.1),
+                vec![control.as_ref(), a.as_ref(), b.as_ref()],
+            ),
+            Self::Curve(curve, source) => (
+                format!(
+                    "curve {}",
+                    points(curve.iter().map(|(x, y)| format!("{x} {y}")).collect())
+                ),
+                vec![source.as_ref()],
+            ),
+            Self::Terrace(levels, source) => (
+                format!(
+                    "terrace {}",
+                    points(levels.iter().map(|v| v.to_string()).collect())
+                ),
+                vec![source.as_ref()],
+            ),
+            Self::Exponent(exponent, source) => (format!("exp {exponent}"), vec![source.as_ref()]),
+            Self::Rotate(angle, source) => (format!("rotate {angle}"), vec![source.as_ref()]),
+            Self::Translate(x, z, y, source) => (
+                format!("translate {x} {z}{}", optional(y)),
+                vec![source.as_ref()],
+            ),
+            Self::Lerp(t, a, b) => ("lerp".into(), vec![t.as_ref(), a.as_ref(), b.as_ref()]),
+            Self::Remap { from, to, source } => (
+                format!("remap {} {} {} {}", from.0, from.1, to.0, to.1),
+                vec![source.as_ref()],
+            ),
+            Self::Spline(spline, source) => (
+                format!(
+                    "spline {}",
+                    points(
+                        spline
+                            .iter()
+                            .map(|(x, y, d)| format!("{x} {y} {d}"))
+                            .collect()
+                    )
+                ),
+                vec![source.as_ref()],
+            ),
+            Self::DistanceFalloff {
+                center,
+                radius,
+                curve,
+            } => (
+                format!(
+                    "distancefalloff {} {} {radius} {}",
+                    center.0,
+                    center.1,
+                    curve.name()
+                ),
+                vec![],
+            ),
+            // custom functions only exist in Rust, the placeholder is not a token so it never parses
+            Self::Custom(_) => (CUSTOM_PLACEHOLDER.into(), vec![]),
+            Self::Let { name, value, .. } => (format!("let {name} ="), vec![value.as_ref()]),
+            Self::Var(name) => (name.clone(), vec![]),
+            Self::Fixed(a) => ("fixed".into(), vec![a.as_ref()]),
+        }
+    }
+}
+
+/// Writes the noise on a single line in the format of [NoiseBuilder::parse], which parses it back to the same tree.
+/// [Custom](NoiseBuilder::Custom) nodes are written as `<custom>`, which does not parse
+impl fmt::Display for NoiseBuilder {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut out = String::new();
+        self.write_tokens(&mut out, None, true);
+        f.write_str(&out)
+    }
+}
@@ -0,0 +1,161 @@
+//! Deriving the seeds of a [NoiseBuilder] tree from a single world seed, so configs do not need a hand picked seed for
+//! every node
+
+use super::{DynNoise, NoiseBuilder};
+
+impl NoiseBuilder {
+    /// Replaces the seed of every seedable node with one derived from `seed`, the position of the node in the tree and
+    /// the seed written in the node. Nodes in different places get different seeds even if they were all written
+    /// with seed 0, while changing a written seed still changes the node.
+    /// Nodes inside [Fixed](NoiseBuilder::Fixed) keep their seeds as written
+    pub fn seeded(mut self, seed: u64) -> Self {
+        self.reseed(mix(seed, 0));
+        self
+    }
+
+    /// Builds the noise for sampling at `[x, z]` with the seeds derived from `seed`, see [seeded](Self::seeded)
+    pub fn build_seeded(self, seed: u64) -> DynNoise {
+        self.seeded(seed).build2()
+    }
+
+    /// `path` is a hash of the seed and the child indices leading to this node
+    fn reseed(&mut self, path: u64) {
+        if let Self::Fixed(_) = self {
+            return;
+        }
+        if let Some(seed) = self.seed_mut() {
+            *seed = (mix(path, *seed as u64) >> 32) as u32;
+        }
+        for (i, child) in self.children_mut().into_iter().enumerate() {
+            child.reseed(mix(path, i as u64 + 1));
+        }
+    }
+
+    /// Seed of the node itself, `None` for nodes without randomness
+    pub(crate) fn seed_mut(&mut self) -> Option<&mut u32> {
+        match self {
+            Self::Perlin(seed)
+            | Self::Simplex(seed)
+            | Self::OpenSimplex(seed)
+            | Self::SuperSimplex(seed)
+            | Self::Value(seed)
+            | Self::Fbm { seed, .. }
+            | Self::Ridged { seed, .. }
+            | Self::Billow { seed, .. }
+            | Self::Worley { seed, .. }
+            | Self::Turbulence { seed, .. } => Some(seed),
+            _ => None,
+        }
+    }
+
+    /// Expressions the node takes, in the order they are parsed
+    pub(crate) fn children_mut(&mut self) -> Vec<&mut NoiseBuilder> {
+        match self {
+            Self::Constant(_)
+            | Self::Checkerboard
+            | Self::Perlin(_)
+            | Self::Simplex(_)
+            | Self::OpenSimplex(_)
+            | Self::SuperSimplex(_)
+            | Self::Value(_)
+            | Self::Fbm { .. }
+            | Self::Ridged { .. }
+            | Self::Billow { .. }
+            | Self::Worley { .. }
+            | Self::DistanceFalloff { .. }
+            | Self::Custom(_)
+            | Self::Var(_) => vec![],
+            Self::Abs(a)
+            | Self::Neg(a)
+            | Self::PowI(_, a)
+            | Self::ScaleInput(_, _, _, a)
+            | Self::Clamp(_, _, a)
+            | Self::ScaleBias { source: a, .. }
+            | Self::Turbulence { source: a, .. }
+            | Self::Curve(_, a)
+            | Self::Terrace(_, a)
+            | Self::Exponent(_, a)
+            | Self::Rotate(_, a)
+            | Self::Translate(_, _, _, a)
+            | Self::Remap { source: a, .. }
+            | Self::Spline(_, a)
+            | Self::Fixed(a) => vec![a.as_mut()],
+            Self::Min(a, b)
+            | Self::Max(a, b)
+            | Self::Add(a, b)
+            | Self::Mul(a, b)
+            | Self::Pow(a, b)
+            | Self::Let {
+                value: a, body: b, ..
+            } => vec![a.as_mut(), b.as_mut()],
+            Self::Warp(a, b, c) | Self::Blend(a, b, c) | Self::Lerp(a, b, c) => {
+                vec![a.as_mut(), b.as_mut(), c.as_mut()]
+            }
+            Self::Select { control, a, b, .. } => vec![control.as_mut(), a.as_mut(), b.as_mut()],
+        }
+    }
+
+    /// Like [children_mut](Self::children_mut)
+    pub(crate) fn children(&self) -> Vec<&NoiseBuilder> {
+        match self {
+            Self::Constant(_)
+            | Self::Checkerboard
+            | Self::Perlin(_)
+            | Self::Simplex(_)
+            | Self::OpenSimplex(_)
+            | Self::SuperSimplex(_)
+            | Self::Value(_)
+            | Self::Fbm { .. }
+            | Self::Ridged { .. }
+            | Self::Billow { .. }
+            | Self::Worley { .. }
+            | Self::DistanceFalloff { .. }
+            | Self::Custom(_)
+            | Self::Var(_) => vec![],
+            Self::Abs(a)
+            | Self::Neg(a)
+            | Self::PowI(_, a)
+            | Self::ScaleInput(_, _, _, a)
+            | Self::Clamp(_, _, a)
+            | Self::ScaleBias { source: a, .. }
+            | Self::Turbulence { source: a, .. }
+            | Self::Curve(_, a)
+            | Self::Terrace(_, a)
+            | Self::Exponent(_, a)
+            | Self::Rotate(_, a)
+            | Self::Translate(_, _, _, a)
+            | Self::Remap { source: a, .. }
+            | Self::Spline(_, a)
+            | Self::Fixed(a) => vec![a.as_ref()],
+            Self::Min(a, b)
+            | Self::Max(a, b)
+            | Self::Add(a, b)
+            | Self::Mul(a, b)
+            | Self::Pow(a, b)
+            | Self::Let {
+                value: a, body: b, ..
+            } => vec![a.as_ref(), b.as_ref()],
+            Self::Warp(a, b, c) | Self::Blend(a, b, c) | Self::Lerp(a, b, c) => {
+                vec![a.as_ref(), b.as_ref(), c.as_ref()]
+            }
+            Self::Select { control, a, b, .. } => vec![control.as_ref(), a.as_ref(), b.as_ref()],
+        }
+    }
+}
+
+/// Applies the world seed of a config to one of its noise trees, trees are given different salts so they are not
+/// correlated when they are written the same
+pub(crate) fn seed_tree(seed: Option<u64>, noise: NoiseBuilder, salt: u64) -> NoiseBuilder {
+    match seed {
+        Some(seed) => noise.seeded(mix(seed, salt)),
+        None => noise,
+    }
+}
+
+/// Hashes two values into one, based on splitmix64
+pub(crate) fn mix(a: u64, b: u64) -> u64 {
+    let mut z = a ^ b.wrapping_mul(0x9E37_79B9_7F4A_7C15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
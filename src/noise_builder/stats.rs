@@ -0,0 +1,67 @@
+//! Estimating the output range of a [NoiseBuilder] by sampling it at random points, for catching configs where the
+//! noise never reaches the heights of the world
+
+use noise::NoiseFn;
+use rand::{rngs::StdRng, Rng, SeedableRng};
+
+use super::NoiseBuilder;
+
+/// Points are sampled within this many blocks of the origin, far enough to cover the features of most configs
+const SAMPLE_RADIUS: f64 = 100_000.0;
+
+/// Statistics of the samples taken by [NoiseBuilder::estimate_range]
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct NoiseStats {
+    pub samples: usize,
+    pub min: f64,
+    pub max: f64,
+    pub mean: f64,
+    /// Standard deviation of the samples
+    pub std_dev: f64,
+}
+
+impl NoiseStats {
+    /// If every sample is within `min..=max`
+    pub fn within(&self, min: f64, max: f64) -> bool {
+        self.min >= min && self.max <= max
+    }
+
+    /// If no sample is within `min..=max`
+    pub fn outside(&self, min: f64, max: f64) -> bool {
+        self.max < min || self.min > max
+    }
+}
+
+impl NoiseBuilder {
+    /// Samples the noise built in 2D at `samples` random points (at least one), giving the range, mean and spread of
+    /// the output.
+    /// The points are the same on every call, so the estimate of a config does not change between runs. Values the
+    /// noise only reaches in small areas can be missed
+    pub fn estimate_range(&self, samples: usize) -> NoiseStats {
+        let noise = self.clone().build2();
+        let mut rng = StdRng::seed_from_u64(0);
+        let samples = samples.max(1);
+        let (mut min, mut max, mut sum, mut sum_sq) = (f64::INFINITY, f64::NEG_INFINITY, 0.0, 0.0);
+        for _ in 0..samples {
+            // whole blocks like the columns of the world, gradient noise is always 0 on them without scaling
+            let point = [
+                rng.gen_range(-SAMPLE_RADIUS..SAMPLE_RADIUS).floor(),
+                rng.gen_range(-SAMPLE_RADIUS..SAMPLE_RADIUS).floor(),
+            ];
+            let value = noise.get(point);
+            min = min.min(value);
+            max = max.max(value);
+            sum += value;
+            sum_sq += value * value;
+        }
+        let mean = sum / samples as f64;
+        NoiseStats {
+            samples,
+            min,
+            max,
+            mean,
+            // rounding can make the variance slightly negative for constant noise
+            std_dev: (sum_sq / samples as f64 - mean * mean).max(0.0).sqrt(),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+//! Checking a [NoiseBuilder] tree for nodes the parser would reject, as trees built in Rust are never parsed
+
+use super::{is_name, NoiseBuilder};
+
+impl NoiseBuilder {
+    /// Checks the nodes for values the parser rejects, like a spline with less than 2 points, returning what is wrong
+    /// with the first invalid node. [Parsed](Self::parse) trees are always valid
+    pub fn validate(&self) -> Result<(), String> {
+        self.validate_in(&mut vec![])
+    }
+
+    /// `names` are the names bound by the [Let](NoiseBuilder::Let)s around the node
+    fn validate_in<'a>(&'a self, names: &mut Vec<&'a str>) -> Result<(), String> {
+        match self {
+            Self::Spline(points, _) if points.len() < 2 => {
+                return Err(format!(
+                    "spline needs at least 2 points, got {}",
+                    points.len()
+                ));
+            }
+            Self::Curve(points, _) if points.len() < 4 => {
+                return Err(format!(
+                    "curve needs at least 4 points, got {}",
+                    points.len()
+                ));
+            }
+            Self::Terrace(levels, _) if levels.len() < 2 => {
+                return Err(format!(
+                    "terrace needs at least 2 levels, got {}",
+                    levels.len()
+                ));
+            }
+            Self::Remap { from, .. } if from.0 == from.1 => {
+                return Err(format!(
+                    "remap needs a range that is not empty to map from, got {} to {}",
+                    from.0, from.1
+                ));
+            }
+            Self::DistanceFalloff { radius, .. } if radius.is_nan() || *radius <= 0.0 => {
+                return Err(format!(
+                    "distancefalloff needs a positive radius, got {radius}"
+                ));
+            }
+            Self::Var(name) if !names.contains(&name.as_str()) => {
+                return Err(format!("'{name}' is used outside of its let"));
+            }
+            // the value is built before the name is bound, so it can not refer to itself
+            Self::Let { name, value, body } => {
+                if !is_name(name) {
+                    return Err(format!("'{name}' can not be bound by a let"));
+                }
+                value.validate_in(names)?;
+                names.push(name);
+                let body = body.validate_in(names);
+                names.pop();
+                return body;
+            }
+            _ => {}
+        }
+        self.children()
+            .into_iter()
+            .try_for_each(|child| child.validate_in(names))
+    }
+
+    /// If the tree contains a [Custom](NoiseBuilder::Custom) node
+    pub(crate) fn has_custom(&self) -> bool {
+        matches!(self, Self::Custom(_)) || self.children().iter().any(|child| child.has_custom())
+    }
+}
@@ -0,0 +1,82 @@
+use rand::{rngs::StdRng, Rng};
+use serde::{Deserialize, Serialize};
+use valence::prelude::*;
+
+use crate::{block_from_str, config_serde, TerrainConfigError};
+
+/// Veins of a block scattered through the base block after the terrain is filled
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct OreConfig {
+    #[serde(with = "config_serde::block")]
+    pub block: BlockState,
+    /// Amount of blocks visited by each vein, some might not be replaced if they are not the base block
+    pub vein_size: u32,
+    /// Veins attempted per chunk
+    pub attempts: u32,
+    /// World heights the veins start between
+    pub min_y: i32,
+    pub max_y: i32,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SerializableOreConfig {
+    pub block: String,
+    pub vein_size: u32,
+    pub attempts: u32,
+    pub min_y: i32,
+    pub max_y: i32,
+}
+
+impl SerializableOreConfig {
+    pub fn parse(self) -> Result<OreConfig, TerrainConfigError> {
+        if self.min_y > self.max_y {
+            return Err(TerrainConfigError::InvalidRange {
+                what: format!("ore '{}'", self.block),
+                min_field: "min_y",
+                max_field: "max_y",
+            });
+        }
+        Ok(OreConfig {
+            block: block_from_str(&self.block)?,
+            vein_size: self.vein_size,
+            attempts: self.attempts,
+            min_y: self.min_y,
+            max_y: self.max_y,
+        })
+    }
+}
+
+/// Places veins of an ore as random walks, veins are cut off at the chunk border.  
+/// `min_y` is the world height of the bottom of the chunk
+pub(crate) fn place_ore(
+    ore: &OreConfig,
+    rng: &mut StdRng,
+    base_blocks: &[BlockState],
+    min_y: i32,
+    chunk: &mut UnloadedChunk,
+) {
+    let ore_min_y = (ore.min_y - min_y).max(0);
+    let max_y = (ore.max_y - min_y).min(chunk.height() as i32 - 1);
+    if ore_min_y > max_y {
+        return;
+    }
+    for _ in 0..ore.attempts {
+        let mut pos = [
+            rng.gen_range(0..16i32),
+            rng.gen_range(ore_min_y..=max_y),
+            rng.gen_range(0..16i32),
+        ];
+        for _ in 0..ore.vein_size {
+            let in_chunk = (0..16).contains(&pos[0])
+                && (0..chunk.height() as i32).contains(&pos[1])
+                && (0..16).contains(&pos[2]);
+            if in_chunk {
+                let [x, y, z] = pos.map(|v| v as u32);
+                if base_blocks.contains(&chunk.block_state(x, y, z)) {
+                    chunk.set_block_state(x, y, z, ore.block);
+                }
+            }
+            pos[rng.gen_range(0..3)] += if rng.gen() { 1 } else { -1 };
+        }
+    }
+}
@@ -0,0 +1,102 @@
+//! Weighted random blocks replacing a single block of the terrain, so the ground and surfaces do not look like one
+//! uniform material, like stone mixed with some andesite and gravel
+
+use serde::{Deserialize, Serialize};
+use valence::prelude::*;
+
+use crate::config_serde;
+
+/// Replaces a block placed by the terrain with a random pick from `blocks`, like the base `block` of the config or the
+/// block of a surface layer. Every position always gets the same pick
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct BlockPalette {
+    #[serde(with = "config_serde::block")]
+    pub block: BlockState,
+    /// Blocks and their weights, the chance of each is its weight divided by the sum of the weights.
+    /// `block` itself can be included to keep it in some places
+    #[serde(with = "config_serde::weighted_blocks")]
+    pub blocks: Vec<(f64, BlockState)>,
+}
+
+impl BlockPalette {
+    /// Sum of the weights, negative weights count as 0
+    pub(crate) fn total_weight(&self) -> f64 {
+        self.blocks.iter().map(|(weight, _)| weight.max(0.0)).sum()
+    }
+}
+
+/// [BlockPalette]s with their weights summed up for picking
+pub(crate) struct BuiltPalettes {
+    /// Blocks with the sum of the weights up to and including them, divided by the total weight
+    palettes: Vec<(BlockState, Vec<(f64, BlockState)>)>,
+    seed: u64,
+}
+
+impl BuiltPalettes {
+    /// Palettes without any weight are left out, see [TerrainGenConfig::validate](crate::TerrainGenConfig::validate).  
+    /// `seed` is the world seed of the config
+    pub fn new(palettes: Vec<BlockPalette>, seed: u64) -> Self {
+        Self {
+            palettes: palettes
+                .into_iter()
+                .filter_map(|palette| {
+                    let total = palette.total_weight();
+                    if total <= 0.0 {
+                        return None;
+                    }
+                    let mut sum = 0.0;
+                    let blocks = palette
+                        .blocks
+                        .into_iter()
+                        .map(|(weight, block)| {
+                            sum += weight.max(0.0);
+                            (sum / total, block)
+                        })
+                        .collect();
+                    Some((palette.block, blocks))
+                })
+                .collect(),
+            seed,
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.palettes.is_empty()
+    }
+
+    /// Block placed at a world position instead of `block`, `block` itself if it has no palette
+    pub fn pick(&self, block: BlockState, pos: [i32; 3]) -> BlockState {
+        let Some((_, blocks)) = self.palettes.iter().find(|(from, _)| *from == block) else {
+            return block;
+        };
+        let roll = (position_hash(pos, self.seed) >> 11) as f64 / (1u64 << 53) as f64;
+        blocks
+            .iter()
+            .find(|(sum, _)| roll < *sum)
+            .or(blocks.last())
+            .map_or(block, |(_, block)| *block)
+    }
+
+    /// Every block `block` can be replaced with, including itself
+    pub fn variants(&self, block: BlockState) -> Vec<BlockState> {
+        let mut variants = vec![block];
+        for (from, blocks) in &self.palettes {
+            if *from == block {
+                variants.extend(blocks.iter().map(|(_, block)| *block));
+            }
+        }
+        variants
+    }
+}
+
+/// Hash of a block position, cheaper than seeding a random generator for every block
+pub(crate) fn position_hash(pos: [i32; 3], seed: u64) -> u64 {
+    // large odd constants like the ones of chunk_rng, finished with the mixing of splitmix64
+    let mut z = (pos[0] as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (pos[1] as u64).wrapping_mul(0xD6E8_FEB8_6659_FD93)
+        ^ (pos[2] as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+        ^ seed;
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
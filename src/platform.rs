@@ -0,0 +1,59 @@
+use serde::{Deserialize, Serialize};
+use valence::prelude::*;
+
+use crate::{config_serde, ChunkHeightmap};
+
+/// Square of blocks placed after the terrain, for players to spawn on in [Void](crate::TerrainMode::Void) worlds
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct SpawnPlatform {
+    #[serde(with = "config_serde::block")]
+    pub block: BlockState,
+    /// Width of the square in blocks
+    pub size: u32,
+    /// Center of the square, the y is the world height of the platform
+    pub position: [i32; 3],
+}
+
+impl Default for SpawnPlatform {
+    fn default() -> Self {
+        Self {
+            block: BlockState::GRASS_BLOCK,
+            size: 3,
+            position: [0, 64, 0],
+        }
+    }
+}
+
+impl SpawnPlatform {
+    /// If the column at `x` and `z` is part of the platform
+    pub fn contains(&self, x: i32, z: i32) -> bool {
+        let start = |center: i32| center - self.size as i32 / 2;
+        let [cx, _, cz] = self.position;
+        (start(cx)..start(cx) + self.size as i32).contains(&x)
+            && (start(cz)..start(cz) + self.size as i32).contains(&z)
+    }
+
+    /// Places the part of the platform in the chunk, nothing is placed if it is outside of the heights of the chunk
+    pub(crate) fn place(
+        &self,
+        pos: ChunkPos,
+        min_y: i32,
+        chunk: &mut UnloadedChunk,
+        heightmap: &mut ChunkHeightmap,
+    ) {
+        let y = self.position[1] - min_y;
+        if y < 0 || y >= chunk.height() as i32 {
+            return;
+        }
+        for x in 0..16 {
+            for z in 0..16 {
+                if !self.contains(pos.x * 16 + x as i32, pos.z * 16 + z as i32) {
+                    continue;
+                }
+                chunk.set_block_state(x, y as u32, z, self.block);
+                heightmap.set(x, z, heightmap.get(x, z).max(y as u32 + 1));
+            }
+        }
+    }
+}
@@ -0,0 +1,168 @@
+//! Generating chunks straight into an Anvil world without running a server, see the `pregen` binary
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    sync::Arc,
+};
+
+use flume::Receiver;
+use valence::prelude::*;
+
+use crate::{
+    pregen_area,
+    proto::{neighborhood, SurfaceInfo},
+    worker::{
+        ChunkSource, ChunkWorkerState, FeatureJob, Generated, GeneratedChunk, Job, JobQueue,
+        WorkerQueue,
+    },
+    ChunkGenError, PregenShape, TerrainGenConfig, TerrainWorkerPool,
+};
+
+/// Bottom of the vanilla overworld, which is [DEFAULT_HEIGHT](crate::DEFAULT_HEIGHT) blocks high
+const OVERWORLD_MIN_Y: i32 = -64;
+
+/// Generates the chunks within `radius` of `center` and saves them to the `anvil` world of the config.  
+/// Chunks that are already in the world are skipped, so an interrupted run can be continued.
+/// `progress` is called with the amount of chunks done and the total after each chunk, chunks that fail are logged
+/// and counted as done.  
+/// There is no layer to take the dimensions from, so a config without a `min_y` starts at the bottom of the vanilla
+/// overworld
+pub fn pregenerate_world(
+    mut config: TerrainGenConfig,
+    biomes: &BiomeRegistry,
+    center: ChunkPos,
+    radius: u32,
+    shape: PregenShape,
+    mut progress: impl FnMut(u32, u32),
+) -> Result<(), String> {
+    if config.anvil.is_none() {
+        return Err("the config has no anvil world to save to".into());
+    }
+    config.anvil_save = true;
+    config.min_y.get_or_insert(OVERWORLD_MIN_Y);
+    config.validate()?;
+    let two_pass = !config.decorations.is_empty() || !config.structures.is_empty();
+    let jobs = Arc::new(JobQueue::default());
+    let (result_sender, result_receiver) = flume::unbounded();
+    let pool = TerrainWorkerPool::new();
+    let queue = Arc::new(WorkerQueue::new(
+        ChunkWorkerState::new(config, biomes),
+        0,
+        jobs.clone(),
+        result_sender,
+    ));
+    let send = |job: Job| {
+        jobs.push(0, job);
+        pool.submit(&queue);
+    };
+
+    // split into the 32x32 chunks of region files, so the terrain is only kept in memory for one tile at a time
+    let mut tiles = BTreeMap::<_, Vec<_>>::new();
+    for pos in pregen_area(center, radius, shape) {
+        tiles.entry(tile_key(pos)).or_default().push(pos);
+    }
+    let total = tiles.values().map(|tile| tile.len() as u32).sum();
+    let mut done = 0;
+    let mut finish = |result: Result<(), ChunkGenError>| {
+        if let Err(error) = result {
+            tracing::error!("{error}");
+        }
+        done += 1;
+        progress(done, total);
+    };
+
+    // surfaces are kept while a tile that has not been generated yet borders them, chunks saved by an earlier tile
+    // would otherwise be loaded from the world without a surface, leaving seams in the features at the tile borders
+    let mut surfaces = HashMap::new();
+    for (key, tile) in &tiles {
+        if !two_pass {
+            for pos in tile {
+                send(Job::Generate(*pos, ChunkSource::Saved));
+            }
+            for _ in tile {
+                let generated = recv(&result_receiver)?;
+                let pos = generated.pos;
+                finish(
+                    generated
+                        .result
+                        .map(|_| ())
+                        .map_err(|message| ChunkGenError { pos, message }),
+                );
+            }
+            continue;
+        }
+
+        // first pass of the tile and the chunks around it
+        let inner = tile.iter().copied().collect::<HashSet<_>>();
+        let needed = tile
+            .iter()
+            .flat_map(|pos| neighborhood(*pos))
+            .filter(|pos| inner.contains(pos) || !surfaces.contains_key(pos))
+            .collect::<HashSet<_>>();
+        for pos in &needed {
+            send(Job::Terrain(*pos, ChunkSource::Saved));
+        }
+        let mut protos = vec![];
+        for _ in 0..needed.len() {
+            let generated = recv(&result_receiver)?;
+            let pos = generated.pos;
+            match generated.result {
+                Ok(Generated::Proto(proto)) => {
+                    surfaces.insert(pos, proto.surface.clone());
+                    if inner.contains(&pos) {
+                        protos.push((pos, proto));
+                    }
+                }
+                // chunks already in the world and failed chunks do not get features
+                Ok(_) => {
+                    surfaces.insert(pos, Arc::new(SurfaceInfo::empty()));
+                    if inner.contains(&pos) {
+                        finish(Ok(()));
+                    }
+                }
+                Err(message) => {
+                    surfaces.insert(pos, Arc::new(SurfaceInfo::empty()));
+                    if inner.contains(&pos) {
+                        finish(Err(ChunkGenError { pos, message }));
+                    }
+                }
+            }
+        }
+
+        // second pass, the chunks are saved by the workers
+        let count = protos.len();
+        for (pos, proto) in protos {
+            let neighbors = neighborhood(pos)
+                .map(|pos| (pos, surfaces[&pos].clone()))
+                .collect();
+            send(Job::Features(Box::new(FeatureJob {
+                pos,
+                proto,
+                neighbors,
+            })));
+        }
+        for _ in 0..count {
+            let generated = recv(&result_receiver)?;
+            let pos = generated.pos;
+            finish(
+                generated
+                    .result
+                    .map(|_| ())
+                    .map_err(|message| ChunkGenError { pos, message }),
+            );
+        }
+        surfaces.retain(|pos, _| neighborhood(*pos).any(|pos| tile_key(pos) > *key));
+    }
+    Ok(())
+}
+
+/// Position of the region file a chunk is in, tiles are generated in the order of these
+fn tile_key(pos: ChunkPos) -> (i32, i32) {
+    (pos.x.div_euclid(32), pos.z.div_euclid(32))
+}
+
+fn recv(receiver: &Receiver<GeneratedChunk>) -> Result<GeneratedChunk, String> {
+    receiver
+        .recv()
+        .map_err(|_| "the workers stopped".to_string())
+}
@@ -0,0 +1,126 @@
+use valence::prelude::*;
+
+use crate::{
+    bedrock::BedrockLayers, noise_builder::NoiseBuilder, ore::OreConfig, platform::SpawnPlatform,
+    shaper::TerrainShaper, strata::Stratum, TerrainConfigError, TerrainGenConfig, TerrainMode,
+};
+
+/// Names of the built in presets, see [TerrainGenConfig::preset]
+pub const PRESETS: &[&str] = &[
+    "default",
+    "amplified",
+    "varied",
+    "islands",
+    "flat",
+    "caves_demo",
+    "skyblock",
+    "nether",
+];
+
+impl TerrainGenConfig {
+    /// Built in config to start from, one of [PRESETS]:  
+    /// `default` rolling hills with oceans, deepslate, ores and bedrock  
+    /// `amplified` tall ridged mountains  
+    /// `varied` the default blocks with oceans, plains and mountain ranges from the default [TerrainShaper]  
+    /// `islands` mostly ocean with sandy islands  
+    /// `flat` a flat grass world at y 64  
+    /// `caves_demo` the default hills with tunnels carved by the density noise  
+    /// `skyblock` empty chunks with a 3 by 3 grass platform at y 64  
+    /// `nether` netherrack caverns with a lava sea at y 32 and bedrock at the floor and the ceiling  
+    /// In configs, `preset: islands` starts from a preset, and the other fields of the config replace its fields.  
+    /// The heights are world heights, and the height of the chunks is taken from the layer
+    pub fn preset(name: &str) -> Result<Self, TerrainConfigError> {
+        match name {
+            "default" => Ok(default_preset()),
+            "amplified" => Ok(Self {
+                noise: noise("(scalein 0.003 0.003 ridged 0 6 1 2 0.5 1 + 1) * 110 + 50"),
+                ..default_preset()
+            }),
+            "varied" => Ok(Self {
+                shaper: Some(TerrainShaper::default()),
+                ..default_preset()
+            }),
+            "islands" => Ok(Self {
+                block: BlockState::STONE,
+                surface_layers: vec![(3, BlockState::SAND)],
+                noise: noise("scalein 0.004 0.004 fbm 0 4 1 2 0.5 * 40 + 50"),
+                sea_level: Some(63),
+                bedrock_floor: Some(BedrockLayers::default()),
+                ..Default::default()
+            }),
+            "flat" => Ok(Self {
+                block: BlockState::STONE,
+                surface_layers: vec![(3, BlockState::DIRT), (1, BlockState::GRASS_BLOCK)],
+                noise: NoiseBuilder::Constant(64.0),
+                bedrock_floor: Some(BedrockLayers {
+                    thickness: 1,
+                    noisy: false,
+                    ..Default::default()
+                }),
+                ..Default::default()
+            }),
+            "caves_demo" => Ok(Self {
+                // tunnels where the 3D perlin noise is close to 0, deep enough to cut through any terrain
+                density: Some(noise(
+                    "mul c 2000 min c 0 add c -0.08 abs scalein 0.02 0.02 0.03 perlin 2",
+                )),
+                ..default_preset()
+            }),
+            "skyblock" => Ok(Self {
+                mode: TerrainMode::Void,
+                spawn_platform: Some(SpawnPlatform::default()),
+                ..Default::default()
+            }),
+            "nether" => Ok(Self {
+                mode: TerrainMode::Caverns,
+                block: BlockState::NETHERRACK,
+                surface_layers: vec![],
+                noise: noise("scalein 0.01 0.01 fbm 0 4 1 2 0.5 * 12 + 40"),
+                cavern_ceiling: noise("scalein 0.01 0.01 fbm 1 4 1 2 0.5 * 16 + 100"),
+                // pillars and ledges reaching between the floor and the ceiling
+                density: Some(noise("scalein 0.02 0.02 0.04 perlin 2 * 24")),
+                sea_level: Some(32),
+                fluid: BlockState::LAVA,
+                bedrock_floor: Some(BedrockLayers::default()),
+                bedrock_ceiling: Some(BedrockLayers::default()),
+                ..Default::default()
+            }),
+            _ => Err(TerrainConfigError::UnknownPreset(name.to_string())),
+        }
+    }
+}
+
+fn default_preset() -> TerrainGenConfig {
+    TerrainGenConfig {
+        block: BlockState::STONE,
+        surface_layers: vec![(3, BlockState::DIRT), (1, BlockState::GRASS_BLOCK)],
+        noise: noise("scalein 0.004 0.004 fbm 0 5 1 2 0.5 * 32 + 68"),
+        sea_level: Some(63),
+        strata: vec![Stratum {
+            block: BlockState::DEEPSLATE,
+            below: 0,
+            noise: Some(noise("scalein 0.1 0.1 perlin 1 * 4")),
+        }],
+        bedrock_floor: Some(BedrockLayers::default()),
+        ores: vec![
+            ore(BlockState::COAL_ORE, 12, 16, 0, 128),
+            ore(BlockState::IRON_ORE, 8, 10, -32, 64),
+            ore(BlockState::DEEPSLATE_DIAMOND_ORE, 6, 3, -64, -16),
+        ],
+        ..Default::default()
+    }
+}
+
+fn ore(block: BlockState, vein_size: u32, attempts: u32, min_y: i32, max_y: i32) -> OreConfig {
+    OreConfig {
+        block,
+        vein_size,
+        attempts,
+        min_y,
+        max_y,
+    }
+}
+
+fn noise(expression: &str) -> NoiseBuilder {
+    NoiseBuilder::parse(expression).expect("invalid noise in preset")
+}
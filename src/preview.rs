@@ -0,0 +1,181 @@
+//! Rendering the height of terrain to PNG images, for previewing configs without starting a server.
+//! See the `preview` example for rendering a config file
+
+use std::{fs, io::Write, path::Path};
+
+use flate2::{write::ZlibEncoder, Compression, Crc};
+use noise::NoiseFn;
+
+use crate::{noise_builder::NoiseBuilder, worker::TerrainShape, TerrainGenConfig};
+
+/// How heights are colored
+#[derive(Clone, Copy, PartialEq, Eq, Debug, Default)]
+pub enum PreviewStyle {
+    /// Black at `min_height` to white at `max_height`
+    Grayscale,
+    /// Green lowlands, brown mountains and white peaks, with water below the sea level
+    #[default]
+    Hypsometric,
+}
+
+/// Area and colors of a preview, every pixel is the height of one column
+#[derive(Clone, Debug)]
+pub struct Preview {
+    /// Block at the top left corner of the image
+    pub origin: [i32; 2],
+    /// Width and height of the image in pixels
+    pub size: [u32; 2],
+    /// Blocks per pixel
+    pub scale: u32,
+    /// Heights mapped to the ends of the colors
+    pub min_height: f64,
+    pub max_height: f64,
+    /// Heights below this are drawn as water in [Hypsometric](PreviewStyle::Hypsometric) previews
+    pub sea_level: Option<f64>,
+    pub style: PreviewStyle,
+}
+
+impl Default for Preview {
+    fn default() -> Self {
+        Self {
+            origin: [0, 0],
+            size: [256, 256],
+            scale: 1,
+            min_height: 0.0,
+            max_height: 256.0,
+            sea_level: None,
+            style: PreviewStyle::default(),
+        }
+    }
+}
+
+impl Preview {
+    /// Preview of the area around `center`, with the heights and sea level of the config
+    pub fn centered(
+        center: [i32; 2],
+        size: [u32; 2],
+        scale: u32,
+        config: &TerrainGenConfig,
+    ) -> Self {
+        Self {
+            origin: [
+                center[0] - (size[0] * scale / 2) as i32,
+                center[1] - (size[1] * scale / 2) as i32,
+            ],
+            size,
+            scale,
+            min_height: config.bottom_y() as f64,
+            max_height: (config.bottom_y() + config.chunk_height() as i32) as f64,
+            sea_level: config.sea_level.map(|level| level as f64),
+            ..Default::default()
+        }
+    }
+
+    /// Renders the height of the terrain of a config, like [TerrainGenerator::height_at](crate::TerrainGenerator::height_at)
+    pub fn render_config(&self, config: &TerrainGenConfig) -> Vec<u8> {
+        let shape = TerrainShape::new(config);
+        self.render(|x, z| shape.height_at(x, z) as f64)
+    }
+
+    /// Renders the raw output of noise sampled at `[x, z]`
+    pub fn render_noise(&self, noise: &NoiseBuilder) -> Vec<u8> {
+        let noise = noise.clone().build2();
+        self.render(|x, z| noise.get([x as f64, z as f64]))
+    }
+
+    /// Renders the value of `sample` at each block, the result is RGB rows from the top of the image
+    pub fn render(&self, sample: impl Fn(i32, i32) -> f64) -> Vec<u8> {
+        let [width, height] = self.size;
+        (0..height)
+            .flat_map(|row| (0..width).map(move |column| self.block(column, row)))
+            .flat_map(|[x, z]| self.color(sample(x, z)))
+            .collect()
+    }
+
+    /// Writes rendered pixels to a PNG file
+    pub fn write_png(&self, pixels: &[u8], path: impl AsRef<Path>) -> Result<(), String> {
+        let path = path.as_ref();
+        let png = encode_png(self.size, pixels)?;
+        fs::write(path, png).map_err(|e| format!("could not write {path:?}: {e}"))
+    }
+
+    fn block(&self, column: u32, row: u32) -> [i32; 2] {
+        [
+            self.origin[0] + (column * self.scale) as i32,
+            self.origin[1] + (row * self.scale) as i32,
+        ]
+    }
+
+    fn color(&self, height: f64) -> [u8; 3] {
+        let t = ((height - self.min_height) / (self.max_height - self.min_height)).clamp(0.0, 1.0);
+        match self.style {
+            PreviewStyle::Grayscale => [(t * 255.0) as u8; 3],
+            PreviewStyle::Hypsometric => match self.sea_level {
+                Some(sea_level) if height < sea_level => {
+                    let depth = ((height - self.min_height) / (sea_level - self.min_height))
+                        .clamp(0.0, 1.0);
+                    gradient(&[(0.0, [20, 40, 120]), (1.0, [70, 130, 200])], depth)
+                }
+                _ => gradient(
+                    &[
+                        (0.0, [60, 130, 60]),
+                        (0.4, [190, 180, 100]),
+                        (0.7, [140, 100, 70]),
+                        (1.0, [245, 245, 245]),
+                    ],
+                    t,
+                ),
+            },
+        }
+    }
+}
+
+/// Color at `t` of a gradient through the stops, which are sorted and span 0 to 1
+fn gradient(stops: &[(f64, [u8; 3])], t: f64) -> [u8; 3] {
+    let i = stops
+        .partition_point(|(at, _)| *at < t)
+        .clamp(1, stops.len() - 1);
+    let ((start, a), (end, b)) = (stops[i - 1], stops[i]);
+    let t = ((t - start) / (end - start)).clamp(0.0, 1.0);
+    [0, 1, 2].map(|c| (a[c] as f64 + (b[c] as f64 - a[c] as f64) * t) as u8)
+}
+
+/// Encodes 8 bit RGB pixels as a PNG, without filtering the rows
+fn encode_png([width, height]: [u32; 2], pixels: &[u8]) -> Result<Vec<u8>, String> {
+    if pixels.len() != (width * height * 3) as usize {
+        return Err(format!(
+            "expected {} bytes for a {width}x{height} image, got {}",
+            width * height * 3,
+            pixels.len()
+        ));
+    }
+    let mut encoder = ZlibEncoder::new(vec![], Compression::default());
+    for row in pixels.chunks(width as usize * 3) {
+        // filter type of the row
+        encoder.write_all(&[0]).map_err(|e| e.to_string())?;
+        encoder.write_all(row).map_err(|e| e.to_string())?;
+    }
+    let data = encoder.finish().map_err(|e| e.to_string())?;
+
+    let mut header = vec![];
+    header.extend_from_slice(&width.to_be_bytes());
+    header.extend_from_slice(&height.to_be_bytes());
+    // 8 bit depth, RGB, default compression, filtering and no interlacing
+    header.extend_from_slice(&[8, 2, 0, 0, 0]);
+
+    let mut png = b"\x89PNG\r\n\x1a\n".to_vec();
+    write_png_chunk(&mut png, b"IHDR", &header);
+    write_png_chunk(&mut png, b"IDAT", &data);
+    write_png_chunk(&mut png, b"IEND", &[]);
+    Ok(png)
+}
+
+fn write_png_chunk(png: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    png.extend_from_slice(&(data.len() as u32).to_be_bytes());
+    png.extend_from_slice(kind);
+    png.extend_from_slice(data);
+    let mut crc = Crc::new();
+    crc.update(kind);
+    crc.update(data);
+    png.extend_from_slice(&crc.sum().to_be_bytes());
+}
@@ -0,0 +1,126 @@
+//! Chunks are generated in two passes when there are features that can cross chunk borders.  
+//! The first pass generates the terrain of a chunk as a [ProtoChunk], and the second places the features of the chunk and
+//! its 8 neighbours, using the [SurfaceInfo] of the neighbours so they do not have to be generated completely.
+
+use std::{sync::Arc, time::Duration};
+
+use valence::prelude::*;
+
+use crate::ChunkHeightmap;
+
+/// Top of each column of a chunk before features are placed
+pub(crate) struct SurfaceInfo {
+    pub heightmap: ChunkHeightmap,
+    top_blocks: Box<[BlockState; 256]>,
+}
+
+impl SurfaceInfo {
+    pub fn new(chunk: &UnloadedChunk, heightmap: ChunkHeightmap) -> Self {
+        let mut top_blocks = Box::new([BlockState::AIR; 256]);
+        for x in 0..16 {
+            for z in 0..16 {
+                let top = heightmap.get(x, z);
+                if top > 0 {
+                    top_blocks[(x + z * 16) as usize] = chunk.block_state(x, top - 1, z);
+                }
+            }
+        }
+        Self {
+            heightmap,
+            top_blocks,
+        }
+    }
+
+    /// Used in place of chunks that failed to generate, nothing is placed on an empty surface
+    pub fn empty() -> Self {
+        Self {
+            heightmap: ChunkHeightmap::default(),
+            top_blocks: Box::new([BlockState::AIR; 256]),
+        }
+    }
+
+    /// Highest non-air block of a column, air if the column is empty
+    pub fn top_block(&self, x: u32, z: u32) -> BlockState {
+        self.top_blocks[(x + z * 16) as usize]
+    }
+}
+
+/// A chunk with terrain that is waiting for its features
+pub(crate) struct ProtoChunk {
+    pub chunk: UnloadedChunk,
+    pub surface: Arc<SurfaceInfo>,
+    /// Time spent on the first pass, so the stats contain the time of both passes
+    pub terrain_time: Duration,
+}
+
+/// The 3x3 chunks around a position, in the order features are placed
+pub(crate) fn neighborhood(pos: ChunkPos) -> impl Iterator<Item = ChunkPos> {
+    (-1..=1).flat_map(move |dx| (-1..=1).map(move |dz| ChunkPos::new(pos.x + dx, pos.z + dz)))
+}
+
+/// Sets blocks of a chunk while features are placed, keeping the heightmap up to date.  
+/// Positions are local to the origin chunk of the feature being placed, and blocks outside of the target chunk are skipped
+pub(crate) struct Placer<'a> {
+    chunk: &'a mut UnloadedChunk,
+    heightmap: &'a mut ChunkHeightmap,
+    target: ChunkPos,
+    offset: [i32; 2],
+}
+
+impl<'a> Placer<'a> {
+    pub fn new(
+        target: ChunkPos,
+        chunk: &'a mut UnloadedChunk,
+        heightmap: &'a mut ChunkHeightmap,
+    ) -> Self {
+        Self {
+            chunk,
+            heightmap,
+            target,
+            offset: [0, 0],
+        }
+    }
+
+    pub fn set_origin(&mut self, origin: ChunkPos) {
+        self.offset = [
+            (origin.x - self.target.x) * 16,
+            (origin.z - self.target.z) * 16,
+        ];
+    }
+
+    /// Places the block if the current block is air
+    pub fn place(&mut self, pos: [i32; 3], block: BlockState) {
+        self.set_if(pos, block, true);
+    }
+
+    /// Places the block no matter what is there, used for structures
+    pub fn replace(&mut self, pos: [i32; 3], block: BlockState) {
+        self.set_if(pos, block, false);
+    }
+
+    fn set_if(&mut self, [x, y, z]: [i32; 3], block: BlockState, only_air: bool) {
+        let (x, z) = (x + self.offset[0], z + self.offset[1]);
+        if !(0..16).contains(&x) || !(0..16).contains(&z) {
+            return;
+        }
+        if !(0..self.chunk.height() as i32).contains(&y) {
+            return;
+        }
+        let (x, y, z) = (x as u32, y as u32, z as u32);
+        if only_air && !self.chunk.block_state(x, y, z).is_air() {
+            return;
+        }
+        self.chunk.set_block_state(x, y, z, block);
+        let top = self.heightmap.get(x, z);
+        if !block.is_air() && top <= y {
+            self.heightmap.set(x, z, y + 1);
+        } else if block.is_air() && top == y + 1 {
+            // structures can replace the top of a column with air
+            let top = (0..y)
+                .rev()
+                .find(|y| !self.chunk.block_state(x, *y, z).is_air())
+                .map_or(0, |y| y + 1);
+            self.heightmap.set(x, z, top);
+        }
+    }
+}
@@ -0,0 +1,116 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{config_serde, noise_builder::NoiseBuilder, TerrainConfigError};
+
+/// Varied terrain like the vanilla overworld since 1.18, made from three named parameter noises that are each mapped
+/// to heights by a [spline](NoiseBuilder::Spline) with `(input, output, slope)` points.
+/// The height is `continentalness_spline(continentalness) + erosion_spline(erosion) * peaks_spline(peaks_valleys)`,
+/// so fields that are left out use a default that already gives oceans, plains and mountain ranges
+#[derive(Serialize, Deserialize, Clone, Debug)]
+#[serde(default)]
+pub struct TerrainShaper {
+    /// Large scale noise deciding between oceans, coasts and inland
+    #[serde(with = "config_serde::noise")]
+    pub continentalness: NoiseBuilder,
+    /// How worn down the terrain is, low erosion gives mountains and high erosion flat lands
+    #[serde(with = "config_serde::noise")]
+    pub erosion: NoiseBuilder,
+    /// Local peaks and valleys, scaled by the erosion
+    #[serde(with = "config_serde::noise")]
+    pub peaks_valleys: NoiseBuilder,
+    /// Continentalness to the world height of the base terrain
+    pub continentalness_spline: Vec<(f64, f64, f64)>,
+    /// Erosion to the factor the peaks and valleys are multiplied by
+    pub erosion_spline: Vec<(f64, f64, f64)>,
+    /// Peaks and valleys to the blocks added to the base terrain
+    pub peaks_spline: Vec<(f64, f64, f64)>,
+}
+
+impl Default for TerrainShaper {
+    fn default() -> Self {
+        Self {
+            continentalness: fbm(0, 5, 1.0 / 512.0),
+            erosion: fbm(1, 4, 1.0 / 384.0),
+            peaks_valleys: NoiseBuilder::ScaleInput(
+                1.0 / 128.0,
+                1.0 / 128.0,
+                None,
+                Box::new(NoiseBuilder::Ridged {
+                    seed: 2,
+                    octaves: 4,
+                    frequency: 1.0,
+                    lacunarity: 2.0,
+                    persistence: 0.5,
+                    attenuation: 1.0,
+                }),
+            ),
+            continentalness_spline: vec![
+                (-1.0, 20.0, 0.0),
+                (-0.4, 35.0, 0.0),
+                (-0.15, 60.0, 60.0),
+                (0.05, 68.0, 20.0),
+                (0.4, 85.0, 30.0),
+                (1.0, 100.0, 0.0),
+            ],
+            erosion_spline: vec![
+                (-1.0, 1.0, 0.0),
+                (-0.2, 0.6, -0.8),
+                (0.3, 0.25, 0.0),
+                (1.0, 0.1, 0.0),
+            ],
+            peaks_spline: vec![
+                (-1.0, -20.0, 0.0),
+                (-0.3, -5.0, 20.0),
+                (0.3, 15.0, 40.0),
+                (1.0, 60.0, 0.0),
+            ],
+        }
+    }
+}
+
+fn fbm(seed: u32, octaves: usize, scale: f64) -> NoiseBuilder {
+    NoiseBuilder::ScaleInput(
+        scale,
+        scale,
+        None,
+        Box::new(NoiseBuilder::Fbm {
+            seed,
+            octaves,
+            frequency: 1.0,
+            lacunarity: 2.0,
+            persistence: 0.5,
+        }),
+    )
+}
+
+impl TerrainShaper {
+    /// The height noise combining the parameter noises, used instead of the `noise` of the config
+    pub fn noise(&self) -> NoiseBuilder {
+        let spline = |points: &[(f64, f64, f64)], noise: &NoiseBuilder| {
+            Box::new(NoiseBuilder::Spline(
+                points.to_vec(),
+                Box::new(noise.clone()),
+            ))
+        };
+        NoiseBuilder::Add(
+            spline(&self.continentalness_spline, &self.continentalness),
+            Box::new(NoiseBuilder::Mul(
+                spline(&self.erosion_spline, &self.erosion),
+                spline(&self.peaks_spline, &self.peaks_valleys),
+            )),
+        )
+    }
+
+    pub(crate) fn validate(&self) -> Result<(), TerrainConfigError> {
+        for (spline, name) in [
+            (&self.continentalness_spline, "continentalness_spline"),
+            (&self.erosion_spline, "erosion_spline"),
+            (&self.peaks_spline, "peaks_spline"),
+        ] {
+            if spline.is_empty() {
+                return Err(TerrainConfigError::Empty(name));
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+use noise::NoiseFn;
+use serde::{Deserialize, Serialize};
+use valence::prelude::*;
+
+use crate::{
+    config_serde,
+    noise_builder::{DynNoise, NoiseBuilder},
+};
+
+/// Base block used below a height instead of the base block of the column, like deepslate deep underground
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Stratum {
+    #[serde(with = "config_serde::block")]
+    pub block: BlockState,
+    /// Base blocks below this world height are replaced
+    pub below: i32,
+    /// If set, this noise sampled at `[x, z]` is added to `below`, so the boundary is not a flat line
+    #[serde(default, with = "config_serde::option_noise")]
+    pub noise: Option<NoiseBuilder>,
+}
+
+/// [Stratum] with the noise built
+pub(crate) struct BuiltStratum {
+    block: BlockState,
+    below: f64,
+    noise: Option<DynNoise>,
+}
+
+impl BuiltStratum {
+    pub fn new(stratum: Stratum) -> Self {
+        Self {
+            block: stratum.block,
+            below: stratum.below as f64,
+            noise: stratum.noise.map(NoiseBuilder::build2),
+        }
+    }
+
+    pub fn block(&self) -> BlockState {
+        self.block
+    }
+
+    /// Height of the boundary at a column
+    pub fn boundary(&self, x: i32, z: i32) -> i32 {
+        let offset = match &self.noise {
+            Some(noise) => noise.get([x as f64, z as f64]),
+            None => 0.0,
+        };
+        (self.below + offset).round() as i32
+    }
+}
+
+/// Base block at a height of a column, `boundaries` are the boundaries of the strata at the column in the same
+/// heights as `y`.  
+/// When a block is below several strata the one with the lowest boundary is used
+pub(crate) fn stratum_block(
+    base: BlockState,
+    boundaries: &[(i32, BlockState)],
+    y: i32,
+) -> BlockState {
+    boundaries
+        .iter()
+        .filter(|(boundary, _)| y < *boundary)
+        .min_by_key(|(boundary, _)| *boundary)
+        .map_or(base, |(_, block)| *block)
+}
@@ -0,0 +1,248 @@
+use std::{fs, io::Read, path::PathBuf};
+
+use flate2::read::GzDecoder;
+use rand::{rngs::StdRng, seq::SliceRandom, Rng};
+use serde::{Deserialize, Serialize};
+use valence::{
+    nbt::{from_binary, Compound, List, Value},
+    prelude::*,
+};
+
+use crate::{
+    block_from_str, config_serde,
+    proto::{Placer, SurfaceInfo},
+    TerrainConfigError,
+};
+
+/// Structure templates stamped onto the surface
+#[derive(Serialize, Deserialize, Clone)]
+pub struct StructureConfig {
+    /// Path to a vanilla `.nbt` structure file, or a directory of them where one is picked at random
+    pub path: PathBuf,
+    /// Chance of a structure being placed in a chunk
+    pub chance: f64,
+    /// Structures with different salts are placed independently of eachother
+    #[serde(default)]
+    pub salt: u64,
+    /// Blocks the structure can be placed on, any block if empty
+    #[serde(default, with = "config_serde::blocks")]
+    pub on: Vec<BlockState>,
+}
+
+#[derive(Serialize, Deserialize)]
+pub struct SerializableStructureConfig {
+    pub path: PathBuf,
+    pub chance: f64,
+    #[serde(default)]
+    pub salt: u64,
+    #[serde(default)]
+    pub on: Vec<String>,
+}
+
+impl SerializableStructureConfig {
+    pub fn parse(self) -> Result<StructureConfig, TerrainConfigError> {
+        Ok(StructureConfig {
+            path: self.path,
+            chance: self.chance,
+            salt: self.salt,
+            on: self
+                .on
+                .iter()
+                .map(|block| block_from_str(block))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+}
+
+/// Blocks of a vanilla structure template, block entities and entities are ignored
+#[derive(Clone, Debug)]
+pub struct StructureTemplate {
+    pub size: [u32; 3],
+    /// Positions relative to the lowest corner, structure voids are left out
+    pub blocks: Vec<([u32; 3], BlockState)>,
+}
+
+impl StructureTemplate {
+    /// Loads a gzipped `.nbt` file as saved by structure blocks
+    pub fn load(path: &PathBuf) -> Result<Self, String> {
+        let file = fs::read(path).map_err(|e| format!("could not read {path:?}: {e}"))?;
+        let mut data = vec![];
+        GzDecoder::new(file.as_slice())
+            .read_to_end(&mut data)
+            .map_err(|e| format!("could not decompress {path:?}: {e}"))?;
+        let (root, _): (Compound, String) = from_binary(&mut data.as_slice())
+            .map_err(|e| format!("invalid nbt in {path:?}: {e}"))?;
+        Self::from_nbt(&root).map_err(|e| format!("invalid structure {path:?}: {e}"))
+    }
+
+    pub fn from_nbt(root: &Compound) -> Result<Self, String> {
+        let size = match root.get("size") {
+            Some(Value::List(List::Int(size))) => int_pos(size).ok_or("invalid size")?,
+            _ => return Err("missing size".into()),
+        };
+        let palette = match root.get("palette") {
+            Some(Value::List(List::Compound(palette))) => palette
+                .iter()
+                .map(palette_entry)
+                .collect::<Result<Vec<_>, _>>()?,
+            Some(Value::List(List::End)) => vec![],
+            _ => return Err("missing palette".into()),
+        };
+        let mut blocks = vec![];
+        if let Some(Value::List(List::Compound(entries))) = root.get("blocks") {
+            for entry in entries {
+                let pos = match entry.get("pos") {
+                    Some(Value::List(List::Int(pos))) => int_pos(pos)
+                        .filter(|pos| (0..3).all(|i| pos[i] < size[i]))
+                        .ok_or_else(|| {
+                            format!("block position {pos:?} is outside the structure")
+                        })?,
+                    _ => return Err("block without pos".into()),
+                };
+                let state = match entry.get("state") {
+                    Some(Value::Int(state)) => *state as usize,
+                    _ => return Err("block without state".into()),
+                };
+                match palette.get(state) {
+                    Some(Some(block)) => blocks.push((pos, *block)),
+                    Some(None) => {}
+                    None => return Err(format!("state {state} is not in the palette")),
+                }
+            }
+        }
+        Ok(Self { size, blocks })
+    }
+}
+
+/// Three non-negative ints like the size and block positions of a template, None if they are not
+fn int_pos(values: &[i32]) -> Option<[u32; 3]> {
+    match values {
+        [x, y, z] => Some([
+            u32::try_from(*x).ok()?,
+            u32::try_from(*y).ok()?,
+            u32::try_from(*z).ok()?,
+        ]),
+        _ => None,
+    }
+}
+
+/// None for structure voids, which should not replace anything
+pub(crate) fn palette_entry(entry: &Compound) -> Result<Option<BlockState>, String> {
+    let name = match entry.get("Name") {
+        Some(Value::String(name)) => name.as_str(),
+        _ => return Err("palette entry without name".into()),
+    };
+    let name = name.strip_prefix("minecraft:").unwrap_or(name);
+    if name == "structure_void" {
+        return Ok(None);
+    }
+    let mut block = block_from_str(name)?;
+    if let Some(Value::Compound(props)) = entry.get("Properties") {
+        for (prop, value) in props.iter() {
+            let (Some(prop), Value::String(value)) = (PropName::from_str(prop), value) else {
+                return Err(format!("invalid property '{prop}' on {name}"));
+            };
+            let Some(value) = PropValue::from_str(value) else {
+                return Err(format!("invalid value '{value}' on {name}"));
+            };
+            block = block.set(prop, value);
+        }
+    }
+    Ok(Some(block))
+}
+
+/// [StructureConfig] with the templates loaded
+pub(crate) struct LoadedStructure {
+    templates: Vec<StructureTemplate>,
+    chance: f64,
+    salt: u64,
+    on: Vec<BlockState>,
+}
+
+impl LoadedStructure {
+    /// Loads the templates of a config, a directory loads every `.nbt` file inside it
+    pub fn load(config: StructureConfig) -> Result<Self, String> {
+        let templates = if config.path.is_dir() {
+            let mut paths = fs::read_dir(&config.path)
+                .map_err(|e| format!("could not read {:?}: {e}", config.path))?
+                .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+                .filter(|path| path.extension().is_some_and(|ext| ext == "nbt"))
+                .collect::<Vec<_>>();
+            // sorted so the same template is picked no matter the order of the directory
+            paths.sort();
+            paths
+                .iter()
+                .map(StructureTemplate::load)
+                .collect::<Result<Vec<_>, _>>()?
+        } else {
+            vec![StructureTemplate::load(&config.path)?]
+        };
+        if templates.is_empty() {
+            return Err(format!("no structures in {:?}", config.path));
+        }
+        Ok(Self {
+            templates,
+            chance: config.chance,
+            salt: config.salt,
+            on: config.on,
+        })
+    }
+
+    pub fn salt(&self) -> u64 {
+        self.salt
+    }
+
+    /// Maybe places a structure in the origin chunk, on top of the surface at a random column.  
+    /// Structures can reach into the chunks next to the origin, but are cut off beyond those
+    pub fn place(&self, surface: &SurfaceInfo, rng: &mut StdRng, placer: &mut Placer) {
+        if rng.gen::<f64>() >= self.chance {
+            return;
+        }
+        let template = self.templates.choose(rng).unwrap();
+        let (x, z) = (rng.gen_range(0..16), rng.gen_range(0..16));
+        let y = surface.heightmap.get(x, z);
+        if y == 0 {
+            return;
+        }
+        if !self.on.is_empty() && !self.on.contains(&surface.top_block(x, z)) {
+            return;
+        }
+        for ([bx, by, bz], block) in &template.blocks {
+            placer.replace([(x + bx) as i32, (y + by) as i32, (z + bz) as i32], *block);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn template(size: Vec<i32>, pos: Vec<i32>) -> Compound {
+        let mut block = Compound::new();
+        block.insert("pos", Value::List(List::Int(pos)));
+        block.insert("state", Value::Int(0));
+        let mut stone = Compound::new();
+        stone.insert("Name", Value::String("minecraft:stone".into()));
+        let mut root = Compound::new();
+        root.insert("size", Value::List(List::Int(size)));
+        root.insert("palette", Value::List(List::Compound(vec![stone])));
+        root.insert("blocks", Value::List(List::Compound(vec![block])));
+        root
+    }
+
+    #[test]
+    fn loads_template() {
+        let template =
+            StructureTemplate::from_nbt(&template(vec![2, 3, 4], vec![1, 2, 3])).unwrap();
+        assert_eq!(template.size, [2, 3, 4]);
+        assert_eq!(template.blocks, vec![([1, 2, 3], BlockState::STONE)]);
+    }
+
+    #[test]
+    fn rejects_negative_values() {
+        assert!(StructureTemplate::from_nbt(&template(vec![2, -3, 4], vec![0, 0, 0])).is_err());
+        assert!(StructureTemplate::from_nbt(&template(vec![2, 3, 4], vec![0, -1, 0])).is_err());
+        // outside of the size
+        assert!(StructureTemplate::from_nbt(&template(vec![2, 3, 4], vec![0, 3, 0])).is_err());
+    }
+}
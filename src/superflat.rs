@@ -0,0 +1,76 @@
+//! Vanilla superflat codes, like `minecraft:bedrock,2*minecraft:dirt,minecraft:grass_block;minecraft:plains`
+
+use valence::prelude::*;
+
+use crate::{
+    biome::BiomeSource, block_from_str, noise_builder::NoiseBuilder, TerrainConfigError,
+    TerrainGenConfig, TerrainMode,
+};
+
+/// Layers of a superflat code from the bottom up, and its biome if it has one.  
+/// Anything after the biome, like the structures of old codes, is ignored
+pub(crate) fn parse_superflat(
+    code: &str,
+) -> Result<(Vec<(u16, BlockState)>, Option<String>), TerrainConfigError> {
+    let invalid = |reason: String| TerrainConfigError::InvalidSuperflat {
+        code: code.to_string(),
+        reason,
+    };
+    let mut parts = code.split(';').map(str::trim);
+    let layers = parts
+        .next()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|layer| !layer.is_empty())
+        .map(|layer| {
+            let (count, block) = match layer.split_once('*') {
+                Some((count, block)) => match count.trim().parse::<u16>() {
+                    Ok(count) if count > 0 => (count, block),
+                    _ => return Err(invalid(format!("invalid count in '{layer}'"))),
+                },
+                None => (1, layer),
+            };
+            Ok((count, block_from_str(block.trim())?))
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    if layers.is_empty() {
+        return Err(invalid("no layers".into()));
+    }
+    let biome = parts
+        .next()
+        .filter(|biome| !biome.is_empty())
+        .map(str::to_string);
+    Ok((layers, biome))
+}
+
+impl TerrainGenConfig {
+    /// The config with the fields replaced by its [superflat](Self::superflat) code, `None` if it has none.  
+    /// Codes that do not parse are logged and ignored, [validate](Self::validate) reports them before this
+    pub(crate) fn expanded_superflat(&self) -> Option<Self> {
+        let code = self.superflat.as_ref()?;
+        let (mut layers, biome) = match parse_superflat(code) {
+            Ok(superflat) => superflat,
+            Err(e) => {
+                tracing::error!("ignoring superflat code: {e}");
+                return None;
+            }
+        };
+        let total = layers.iter().map(|(count, _)| *count as i32).sum::<i32>();
+        // the bottom layer is the base block, filling everything below the other layers
+        let (_, block) = layers.remove(0);
+        Some(Self {
+            mode: TerrainMode::Heightmap,
+            block,
+            surface_layers: layers,
+            noise: NoiseBuilder::Constant((self.bottom_y() + total) as f64),
+            shaper: None,
+            density: None,
+            biomes: biome
+                .map(BiomeSource::Fixed)
+                .or_else(|| self.biomes.clone()),
+            superflat: None,
+            ..self.clone()
+        })
+    }
+}
@@ -0,0 +1,553 @@
+//! Lowers vanilla worldgen JSON, like the `density_function` and `noise_settings` files of datapacks, into configs  
+//! Only a subset can be expressed, see [VanillaImporter::density_function] and [VanillaImporter::noise_settings]
+
+use std::path::PathBuf;
+
+use serde_yml::Value;
+use valence::prelude::*;
+
+use crate::{
+    biome::BiomeSurface, block_from_str, noise_builder::NoiseBuilder, TerrainConfigError,
+    TerrainGenConfig,
+};
+
+/// Depth of the layer under the top block, vanilla varies it from 3 to 6 with noise
+const SURFACE_DEPTH: u16 = 3;
+
+/// Converts vanilla worldgen JSON, references to other files like `minecraft:overworld/continents` are read from  
+/// the datapack if one is set
+#[derive(Clone, Default)]
+pub struct VanillaImporter {
+    datapack: Option<PathBuf>,
+}
+
+impl VanillaImporter {
+    /// Importer that can not follow references, everything must be inlined
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Importer reading references from a datapack, `root` is the folder containing `data`  
+    /// Pointing it at the extracted vanilla jar allows references to the vanilla files
+    pub fn with_datapack(root: impl Into<PathBuf>) -> Self {
+        Self {
+            datapack: Some(root.into()),
+        }
+    }
+
+    /// Lowers a density function into a noise expression, supported are constants, `add`, `mul`, `min`, `max`, `abs`,  
+    /// `square`, `cube`, `half_negative`, `quarter_negative`, `squeeze`, `clamp`, `range_choice`, `noise`,  
+    /// `shifted_noise` (without the shift), `spline` with constant values and the cache markers like `interpolated`  
+    /// Functions depending on y, like `y_clamped_gradient`, can not be expressed and are errors  
+    /// Noises become [Perlin](NoiseBuilder::Perlin) octaves multiplied by their amplitudes, seeded by their name
+    pub fn density_function(&self, json: &str) -> Result<NoiseBuilder, TerrainConfigError> {
+        let value = parse_json(json, "density_function")?;
+        self.lower(&value, "density_function", &[])
+    }
+
+    /// [density_function](Self::density_function) of a file of the datapack, like `minecraft:overworld/continents`
+    pub fn load_density_function(&self, id: &str) -> Result<NoiseBuilder, TerrainConfigError> {
+        self.load_function(id, &[])
+    }
+
+    /// Follows a reference, `refs` are the functions it is referenced from so cycles are errors instead of overflows
+    fn load_function(&self, id: &str, refs: &[&str]) -> Result<NoiseBuilder, TerrainConfigError> {
+        if refs.contains(&id) {
+            return Err(error(
+                id,
+                format!("references itself through {}", refs.join(" -> ")),
+            ));
+        }
+        let value = self.load("density_function", id)?;
+        self.lower(&value, id, &[refs, &[id][..]].concat())
+    }
+
+    /// Config with the default block and fluid, sea level, heights and surface rule of noise settings  
+    /// The noise router is not imported, as its functions depend on y, so [noise](TerrainGenConfig::noise) should be  
+    /// set from a [density_function](Self::density_function) afterwards  
+    /// Of the surface rule only blocks at the floor `stone_depth` are kept, the top one becomes a layer of 1 block and  
+    /// the one with `add_surface_depth` a layer of 3, rules under `biome` conditions become biome surfaces  
+    /// `water` and `above_preliminary_surface` conditions are assumed to pass, rules behind other conditions are left out
+    pub fn noise_settings(&self, json: &str) -> Result<TerrainGenConfig, TerrainConfigError> {
+        let value = parse_json(json, "noise_settings")?;
+        self.lower_settings(&value)
+    }
+
+    /// [noise_settings](Self::noise_settings) of a file of the datapack, like `minecraft:overworld`
+    pub fn load_noise_settings(&self, id: &str) -> Result<TerrainGenConfig, TerrainConfigError> {
+        let value = self.load("noise_settings", id)?;
+        self.lower_settings(&value)
+    }
+
+    fn load(&self, kind: &str, id: &str) -> Result<Value, TerrainConfigError> {
+        let Some(root) = &self.datapack else {
+            return Err(error(id, "references need a datapack"));
+        };
+        let (namespace, path) = id.split_once(':').unwrap_or(("minecraft", id));
+        let file = root
+            .join("data")
+            .join(namespace)
+            .join("worldgen")
+            .join(kind)
+            .join(format!("{path}.json"));
+        let json = std::fs::read_to_string(&file)
+            .map_err(|e| error(id, format!("could not read {}: {e}", file.display())))?;
+        parse_json(&json, id)
+    }
+
+    fn lower(
+        &self,
+        value: &Value,
+        at: &str,
+        refs: &[&str],
+    ) -> Result<NoiseBuilder, TerrainConfigError> {
+        if let Some(constant) = value.as_f64() {
+            return Ok(NoiseBuilder::Constant(constant));
+        }
+        if let Some(id) = value.as_str() {
+            return self.load_function(id, refs);
+        }
+        let kind = value.get("type").and_then(Value::as_str).ok_or_else(|| {
+            error(
+                at,
+                "expected a number, a reference or an object with a type",
+            )
+        })?;
+        let kind = kind.strip_prefix("minecraft:").unwrap_or(kind);
+        let arg = |field: &str| -> Result<Box<NoiseBuilder>, TerrainConfigError> {
+            let value = value
+                .get(field)
+                .ok_or_else(|| error(at, format!("{kind} is missing {field}")))?;
+            self.lower(value, &format!("{at}.{field}"), refs)
+                .map(Box::new)
+        };
+        let number = |field: &str| number(value, field, at);
+        Ok(match kind {
+            "constant" => NoiseBuilder::Constant(number("argument")?),
+            "add" => NoiseBuilder::Add(arg("argument1")?, arg("argument2")?),
+            "mul" => NoiseBuilder::Mul(arg("argument1")?, arg("argument2")?),
+            "min" => NoiseBuilder::Min(arg("argument1")?, arg("argument2")?),
+            "max" => NoiseBuilder::Max(arg("argument1")?, arg("argument2")?),
+            "abs" => NoiseBuilder::Abs(arg("argument")?),
+            "square" => NoiseBuilder::PowI(2, arg("argument")?),
+            "cube" => NoiseBuilder::PowI(3, arg("argument")?),
+            "half_negative" => scale_negative(arg("argument")?, 0.5),
+            "quarter_negative" => scale_negative(arg("argument")?, 0.25),
+            "squeeze" => {
+                // c / 2 - c^3 / 24 with c clamped to -1 to 1
+                let clamped = Box::new(NoiseBuilder::Clamp(-1.0, 1.0, arg("argument")?));
+                NoiseBuilder::Add(
+                    Box::new(NoiseBuilder::Mul(
+                        clamped.clone(),
+                        Box::new(NoiseBuilder::Constant(0.5)),
+                    )),
+                    Box::new(NoiseBuilder::Mul(
+                        Box::new(NoiseBuilder::PowI(3, clamped)),
+                        Box::new(NoiseBuilder::Constant(-1.0 / 24.0)),
+                    )),
+                )
+            }
+            "clamp" => NoiseBuilder::Clamp(number("min")?, number("max")?, arg("input")?),
+            "range_choice" => NoiseBuilder::Select {
+                control: arg("input")?,
+                a: arg("when_out_of_range")?,
+                b: arg("when_in_range")?,
+                bounds: (number("min_inclusive")?, number("max_exclusive")?),
+                falloff: 0.0,
+            },
+            "noise" | "shifted_noise" => {
+                let xz_scale = number("xz_scale")?;
+                let y_scale = number("y_scale")?;
+                NoiseBuilder::ScaleInput(
+                    xz_scale,
+                    xz_scale,
+                    Some(y_scale),
+                    Box::new(self.noise(value, at)?),
+                )
+            }
+            "spline" => {
+                let spline = value
+                    .get("spline")
+                    .ok_or_else(|| error(at, "spline is missing spline"))?;
+                self.spline(spline, &format!("{at}.spline"), refs)?
+            }
+            "interpolated" | "flat_cache" | "cache_2d" | "cache_once" | "cache_all_in_cell"
+            | "blend_density" => *arg("argument")?,
+            "y_clamped_gradient" => return Err(error(at, "noise expressions can not read y")),
+            _ => return Err(error(at, format!("unsupported density function {kind}"))),
+        })
+    }
+
+    /// The noise of a `noise` function, its name or an inline object with `firstOctave` and `amplitudes`
+    fn noise(&self, function: &Value, at: &str) -> Result<NoiseBuilder, TerrainConfigError> {
+        let at = format!("{at}.noise");
+        let noise = function
+            .get("noise")
+            .ok_or_else(|| error(&at, "missing noise"))?;
+        let (params, name) = match noise.as_str() {
+            Some(id) => (self.load("noise", id)?, id.to_string()),
+            None => (noise.clone(), at.clone()),
+        };
+        let first_octave = number(&params, "firstOctave", &at)?;
+        let amplitudes = params
+            .get("amplitudes")
+            .and_then(Value::as_sequence)
+            .ok_or_else(|| error(&at, "missing amplitudes"))?;
+        let amplitudes = amplitudes
+            .iter()
+            .enumerate()
+            .map(|(i, a)| {
+                a.as_f64()
+                    .ok_or_else(|| error(&at, format!("expected a number for amplitudes[{i}]")))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        // like vanilla, each octave has double the frequency and half the weight of the previous one, and the weights
+        // add up to 1 before the amplitudes
+        let count = amplitudes.len() as i32;
+        let first_weight = 2f64.powi(count - 1) / (2f64.powi(count) - 1.0);
+        let seed = name_seed(&name);
+        // octaves with no amplitude do not change the noise
+        let octaves = amplitudes
+            .iter()
+            .enumerate()
+            .filter(|(_, amplitude)| **amplitude != 0.0)
+            .map(|(i, amplitude)| {
+                let frequency = 2f64.powf(first_octave + i as f64);
+                NoiseBuilder::Mul(
+                    Box::new(NoiseBuilder::Constant(
+                        amplitude * first_weight / 2f64.powi(i as i32),
+                    )),
+                    Box::new(NoiseBuilder::ScaleInput(
+                        frequency,
+                        frequency,
+                        Some(frequency),
+                        Box::new(NoiseBuilder::Perlin(seed.wrapping_add(i as u32))),
+                    )),
+                )
+            });
+        Ok(octaves
+            .reduce(|sum, octave| NoiseBuilder::Add(Box::new(sum), Box::new(octave)))
+            .unwrap_or(NoiseBuilder::Constant(0.0)))
+    }
+
+    fn spline(
+        &self,
+        spline: &Value,
+        at: &str,
+        refs: &[&str],
+    ) -> Result<NoiseBuilder, TerrainConfigError> {
+        if let Some(constant) = spline.as_f64() {
+            return Ok(NoiseBuilder::Constant(constant));
+        }
+        let coordinate = spline
+            .get("coordinate")
+            .ok_or_else(|| error(at, "spline is missing coordinate"))?;
+        let coordinate = self.lower(coordinate, &format!("{at}.coordinate"), refs)?;
+        let points = spline
+            .get("points")
+            .and_then(Value::as_sequence)
+            .ok_or_else(|| error(at, "spline is missing points"))?
+            .iter()
+            .enumerate()
+            .map(|(i, point)| {
+                let at = format!("{at}.points[{i}]");
+                let value = point
+                    .get("value")
+                    .and_then(Value::as_f64)
+                    .ok_or_else(|| error(&at, "only splines with constant values are supported"))?;
+                Ok((
+                    number(point, "location", &at)?,
+                    value,
+                    number(point, "derivative", &at)?,
+                ))
+            })
+            .collect::<Result<Vec<_>, TerrainConfigError>>()?;
+        match points.as_slice() {
+            [] => Err(error(at, "spline has no points")),
+            [(_, value, _)] => Ok(NoiseBuilder::Constant(*value)),
+            _ => Ok(NoiseBuilder::Spline(points, Box::new(coordinate))),
+        }
+    }
+
+    fn lower_settings(&self, value: &Value) -> Result<TerrainGenConfig, TerrainConfigError> {
+        let mut config = TerrainGenConfig::default();
+        if let Some(block) = value.get("default_block") {
+            config.block = block_state(block, "noise_settings.default_block")?;
+        }
+        if let Some(fluid) = value.get("default_fluid") {
+            config.fluid = block_state(fluid, "noise_settings.default_fluid")?;
+        }
+        if let Some(sea_level) = value.get("sea_level").and_then(Value::as_i64) {
+            config.sea_level = Some(sea_level as i32);
+        }
+        if let Some(noise) = value.get("noise") {
+            config.min_y = noise.get("min_y").and_then(Value::as_i64).map(|y| y as i32);
+            config.height = noise
+                .get("height")
+                .and_then(Value::as_i64)
+                .map(|h| h as u32);
+        }
+        if let Some(rule) = value.get("surface_rule") {
+            let mut surfaces = SurfaceRules::default();
+            surfaces.add(rule, None, false, "noise_settings.surface_rule")?;
+            config.surface_layers = surfaces.default.layers();
+            config.biome_surfaces = surfaces
+                .biomes
+                .into_iter()
+                .filter(|(_, surface)| surface.layers() != config.surface_layers)
+                .map(|(biome, surface)| BiomeSurface {
+                    biome,
+                    block: None,
+                    surface_layers: Some(surface.layers()),
+                })
+                .collect();
+        }
+        config.validate()?;
+        Ok(config)
+    }
+}
+
+/// Blocks picked by the surface rule at the floor
+#[derive(Clone, Copy, Default)]
+struct Surface {
+    top: Option<BlockState>,
+    under: Option<BlockState>,
+}
+
+impl Surface {
+    /// Sets the blocks no earlier rule picked, as the first rule of a sequence that matches wins
+    fn set(&mut self, top_only: bool, block: BlockState) {
+        self.top.get_or_insert(block);
+        if !top_only {
+            self.under.get_or_insert(block);
+        }
+    }
+
+    /// Surface layers from the bottom up
+    fn layers(&self) -> Vec<(u16, BlockState)> {
+        [(SURFACE_DEPTH, self.under), (1, self.top)]
+            .into_iter()
+            .filter_map(|(count, block)| Some((count, block?)))
+            .collect()
+    }
+}
+
+#[derive(Default)]
+struct SurfaceRules {
+    default: Surface,
+    /// Biomes in the order they are first named by the rule
+    biomes: Vec<(String, Surface)>,
+}
+
+impl SurfaceRules {
+    fn add(
+        &mut self,
+        rule: &Value,
+        biomes: Option<&[String]>,
+        top_only: bool,
+        at: &str,
+    ) -> Result<(), TerrainConfigError> {
+        let kind = rule_type(rule, at)?;
+        match kind {
+            "sequence" => {
+                let sequence = rule
+                    .get("sequence")
+                    .and_then(Value::as_sequence)
+                    .ok_or_else(|| error(at, "sequence is missing sequence"))?;
+                for (i, rule) in sequence.iter().enumerate() {
+                    self.add(rule, biomes, top_only, &format!("{at}.sequence[{i}]"))?;
+                }
+            }
+            "condition" => {
+                let (Some(condition), Some(then)) = (rule.get("if_true"), rule.get("then_run"))
+                else {
+                    return Err(error(at, "condition is missing if_true or then_run"));
+                };
+                let condition_at = format!("{at}.if_true");
+                let then_at = format!("{at}.then_run");
+                match rule_type(condition, &condition_at)? {
+                    "biome" => {
+                        let names = condition
+                            .get("biome_is")
+                            .and_then(Value::as_sequence)
+                            .ok_or_else(|| error(&condition_at, "biome is missing biome_is"))?
+                            .iter()
+                            .filter_map(Value::as_str)
+                            .map(str::to_string)
+                            .collect::<Vec<_>>();
+                        self.add(then, Some(&names), top_only, &then_at)?;
+                    }
+                    "stone_depth" => {
+                        let floor = condition.get("surface_type").and_then(Value::as_str)
+                            != Some("ceiling");
+                        let add_depth = condition
+                            .get("add_surface_depth")
+                            .and_then(Value::as_bool)
+                            .unwrap_or(false);
+                        // deeper offsets are below the surface layers
+                        if floor && condition.get("offset").and_then(Value::as_i64) == Some(0) {
+                            self.add(then, biomes, top_only || !add_depth, &then_at)?;
+                        }
+                    }
+                    "water" | "above_preliminary_surface" => {
+                        self.add(then, biomes, top_only, &then_at)?
+                    }
+                    _ => {}
+                }
+            }
+            "block" => {
+                let state = rule
+                    .get("result_state")
+                    .ok_or_else(|| error(at, "block is missing result_state"))?;
+                let block = block_state(state, &format!("{at}.result_state"))?;
+                match biomes {
+                    Some(biomes) => {
+                        for biome in biomes {
+                            self.biome(biome).set(top_only, block);
+                        }
+                    }
+                    None => {
+                        self.default.set(top_only, block);
+                        for (_, surface) in &mut self.biomes {
+                            surface.set(top_only, block);
+                        }
+                    }
+                }
+            }
+            // bandlands and other rules can not be expressed
+            _ => {}
+        }
+        Ok(())
+    }
+
+    /// Surface of a biome, which starts with what the earlier rules picked for every biome
+    fn biome(&mut self, biome: &str) -> &mut Surface {
+        let index = match self.biomes.iter().position(|(name, _)| name == biome) {
+            Some(index) => index,
+            None => {
+                self.biomes.push((biome.to_string(), self.default));
+                self.biomes.len() - 1
+            }
+        };
+        &mut self.biomes[index].1
+    }
+}
+
+/// `x` where it is positive and `x * scale` where it is negative
+fn scale_negative(source: Box<NoiseBuilder>, scale: f64) -> NoiseBuilder {
+    NoiseBuilder::Max(
+        source.clone(),
+        Box::new(NoiseBuilder::Mul(
+            source,
+            Box::new(NoiseBuilder::Constant(scale)),
+        )),
+    )
+}
+
+/// Block state written like `{"Name": "minecraft:grass_block", "Properties": {"snowy": "false"}}`
+fn block_state(value: &Value, at: &str) -> Result<BlockState, TerrainConfigError> {
+    let name = value
+        .get("Name")
+        .and_then(Value::as_str)
+        .ok_or_else(|| error(at, "block state is missing Name"))?;
+    let props = value
+        .get("Properties")
+        .and_then(Value::as_mapping)
+        .map(|props| {
+            props
+                .iter()
+                .filter_map(|(name, value)| Some(format!("{}={}", name.as_str()?, value.as_str()?)))
+                .collect::<Vec<_>>()
+                .join(",")
+        })
+        .filter(|props| !props.is_empty());
+    match props {
+        Some(props) => block_from_str(&format!("{name}[{props}]")),
+        None => block_from_str(name),
+    }
+}
+
+fn rule_type<'a>(rule: &'a Value, at: &str) -> Result<&'a str, TerrainConfigError> {
+    let kind = rule
+        .get("type")
+        .and_then(Value::as_str)
+        .ok_or_else(|| error(at, "expected an object with a type"))?;
+    Ok(kind.strip_prefix("minecraft:").unwrap_or(kind))
+}
+
+fn number(value: &Value, field: &str, at: &str) -> Result<f64, TerrainConfigError> {
+    value
+        .get(field)
+        .and_then(Value::as_f64)
+        .ok_or_else(|| error(at, format!("expected a number for {field}")))
+}
+
+fn parse_json(json: &str, at: &str) -> Result<Value, TerrainConfigError> {
+    // JSON is valid YAML, so the YAML parser of the configs reads it
+    serde_yml::from_str(json).map_err(|e| error(at, e.to_string()))
+}
+
+/// Noises are seeded by their name so the same noise is the same everywhere it is used
+fn name_seed(name: &str) -> u32 {
+    // FNV-1a
+    name.bytes().fold(0x811c9dc5u32, |hash, byte| {
+        (hash ^ byte as u32).wrapping_mul(0x01000193)
+    })
+}
+
+fn error(at: &str, reason: impl Into<String>) -> TerrainConfigError {
+    TerrainConfigError::Vanilla {
+        at: at.to_string(),
+        reason: reason.into(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use noise::NoiseFn;
+
+    use super::*;
+
+    /// `noise` function with inline parameters, the amplitudes of the octaves are left to the tests
+    fn noise_json(amplitudes: &str) -> String {
+        format!(
+            r#"{{
+                "type": "minecraft:noise",
+                "noise": {{ "firstOctave": -2, "amplitudes": {amplitudes} }},
+                "xz_scale": 1.0,
+                "y_scale": 1.0
+            }}"#
+        )
+    }
+
+    #[test]
+    fn octaves_are_multiplied_by_amplitudes() {
+        let importer = VanillaImporter::new();
+        let noise = |amplitudes| {
+            importer
+                .density_function(&noise_json(amplitudes))
+                .unwrap()
+                .build2()
+        };
+        let (single, double, silent) = (noise("[1.0]"), noise("[2.0]"), noise("[0.0]"));
+        for point in [[0.3, 0.7], [-4.2, 1.9], [12.5, -3.1]] {
+            assert_eq!(double.get(point), 2.0 * single.get(point));
+            assert_eq!(silent.get(point), 0.0);
+        }
+    }
+
+    #[test]
+    fn cyclic_reference_is_an_error() {
+        let root = std::env::temp_dir().join("valence_terrain_vanilla_cycle");
+        let functions = root.join("data/test/worldgen/density_function");
+        std::fs::create_dir_all(&functions).unwrap();
+        let reference = |id: &str| format!(r#"{{ "type": "abs", "argument": "{id}" }}"#);
+        std::fs::write(functions.join("a.json"), reference("test:b")).unwrap();
+        std::fs::write(functions.join("b.json"), reference("test:a")).unwrap();
+        let importer = VanillaImporter::with_datapack(&root);
+        assert!(matches!(
+            importer.load_density_function("test:a"),
+            Err(TerrainConfigError::Vanilla { .. })
+        ));
+    }
+}
@@ -0,0 +1,1438 @@
+use std::{
+    cmp::Reverse,
+    collections::{hash_map::DefaultHasher, BinaryHeap, HashMap},
+    hash::{Hash, Hasher},
+    panic::{self, AssertUnwindSafe},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+
+use flume::{Receiver, Sender};
+use noise::NoiseFn;
+use rand::{rngs::StdRng, SeedableRng};
+use valence::prelude::*;
+
+use crate::{
+    anvil::AnvilSource,
+    bedrock::BedrockLayers,
+    biome::{biome_id, BiomePicker},
+    border::WorldBorder,
+    cache::{CacheCompression, CachedChunk},
+    decoration::BuiltDecoration,
+    islands::FloatingIslands,
+    map::{draw_map, MapJob},
+    noise_builder::{seed_tree, DynNoise, DynNoise3, NoiseBuilder},
+    ore::{place_ore, OreConfig},
+    palette::BuiltPalettes,
+    platform::SpawnPlatform,
+    proto::{neighborhood, Placer, ProtoChunk, SurfaceInfo},
+    strata::{stratum_block, BuiltStratum},
+    structure::LoadedStructure,
+    ChunkHeightmap, TerrainGenConfig, TerrainMode,
+};
+
+pub(crate) struct ChunkWorkerState {
+    pub mode: TerrainMode,
+    pub surface: SurfaceRule,
+    pub biome_surfaces: HashMap<BiomeId, SurfaceRule>,
+    pub shape: Arc<TerrainShape>,
+    pub biomes: Option<BiomePicker>,
+    pub height: u32,
+    pub min_y: i32,
+    pub surface_on_all_exposed: bool,
+    pub sea_level: Option<i32>,
+    pub fluid: BlockState,
+    pub strata: Vec<BuiltStratum>,
+    pub palettes: BuiltPalettes,
+    pub bedrock_floor: Option<BedrockLayers>,
+    pub bedrock_ceiling: Option<BedrockLayers>,
+    /// World seed of the config, 0 if not set
+    pub seed: u64,
+    pub ores: Vec<OreConfig>,
+    pub decorations: Vec<BuiltDecoration>,
+    pub structures: Vec<LoadedStructure>,
+    pub spawn_platform: Option<SpawnPlatform>,
+    pub world_border: Option<WorldBorder>,
+    pub anvil: Option<AnvilSource>,
+}
+
+impl ChunkWorkerState {
+    pub fn new(config: TerrainGenConfig, biomes: &BiomeRegistry) -> Self {
+        let shape = Arc::new(TerrainShape::new(&config));
+        Self::with_shape(config, shape, biomes)
+    }
+
+    /// Like [new](Self::new), sharing a shape already built from the same config
+    pub fn with_shape(
+        config: TerrainGenConfig,
+        shape: Arc<TerrainShape>,
+        biomes: &BiomeRegistry,
+    ) -> Self {
+        let config = match config.expanded_superflat() {
+            Some(expanded) => expanded,
+            None => config,
+        };
+        let height = config.chunk_height();
+        let min_y = config.bottom_y();
+        Self {
+            mode: config.mode,
+            biome_surfaces: config
+                .biome_surfaces
+                .into_iter()
+                .map(|surface| {
+                    let rule = SurfaceRule {
+                        block: surface.block.unwrap_or(config.block),
+                        surface_layers: surface
+                            .surface_layers
+                            .unwrap_or_else(|| config.surface_layers.clone()),
+                    };
+                    (biome_id(biomes, &surface.biome), rule)
+                })
+                .collect(),
+            surface: SurfaceRule {
+                block: config.block,
+                surface_layers: config.surface_layers,
+            },
+            shape,
+            biomes: config
+                .biomes
+                .map(|source| BiomePicker::new(source.seeded(config.seed, BIOME_SALT), biomes)),
+            height,
+            min_y,
+            surface_on_all_exposed: config.surface_on_all_exposed,
+            sea_level: config.sea_level,
+            fluid: config.fluid,
+            strata: config
+                .strata
+                .into_iter()
+                .enumerate()
+                .map(|(i, mut stratum)| {
+                    stratum.noise = stratum
+                        .noise
+                        .map(|noise| seed_tree(config.seed, noise, STRATA_NOISE_SALT + i as u64));
+                    BuiltStratum::new(stratum)
+                })
+                .collect(),
+            palettes: BuiltPalettes::new(config.palettes, config.seed.unwrap_or(0)),
+            bedrock_floor: config.bedrock_floor,
+            bedrock_ceiling: config.bedrock_ceiling,
+            seed: config.seed.unwrap_or(0),
+            ores: config.ores,
+            decorations: config
+                .decorations
+                .into_iter()
+                .enumerate()
+                .map(|(i, mut decoration)| {
+                    decoration.noise = decoration.noise.map(|noise| {
+                        seed_tree(config.seed, noise, DECORATION_NOISE_SALT + i as u64)
+                    });
+                    BuiltDecoration::new(decoration)
+                })
+                .collect(),
+            structures: config
+                .structures
+                .into_iter()
+                .filter_map(|structure| match LoadedStructure::load(structure) {
+                    Ok(structure) => Some(structure),
+                    Err(e) => {
+                        tracing::error!("skipping structure: {e}");
+                        None
+                    }
+                })
+                .collect(),
+            spawn_platform: config.spawn_platform,
+            world_border: config.world_border,
+            anvil: config
+                .anvil
+                .map(|path| AnvilSource::new(path, config.anvil_save, min_y, biomes)),
+        }
+    }
+
+    /// Biome of a column, the same as the one of its 4x4 cell in generated chunks
+    pub fn biome_at(&self, x: i32, z: i32) -> Option<BiomeId> {
+        let biomes = self.biomes.as_ref()?;
+        Some(biomes.get(
+            (x.div_euclid(4) * 4 + 2) as f64,
+            (z.div_euclid(4) * 4 + 2) as f64,
+        ))
+    }
+
+    /// See [TerrainGenerator::surface_block_at](crate::TerrainGenerator::surface_block_at)
+    pub fn surface_block_at(&self, x: i32, z: i32) -> Option<BlockState> {
+        let height = self.shape.height_at(x, z);
+        if let Some(platform) = &self.spawn_platform {
+            if platform.contains(x, z) && height == platform.position[1] + 1 {
+                return Some(platform.block);
+            }
+        }
+        if self.mode == TerrainMode::Void {
+            return None;
+        }
+        if self.sea_level.is_some_and(|level| height < level) {
+            return Some(self.fluid);
+        }
+        if height == self.min_y {
+            return None;
+        }
+        Some(self.surface_rule(self.biome_at(x, z)).top_block())
+    }
+
+    /// Surface rule used for the columns of a biome
+    pub fn surface_rule(&self, biome: Option<BiomeId>) -> &SurfaceRule {
+        biome
+            .and_then(|biome| self.biome_surfaces.get(&biome))
+            .unwrap_or(&self.surface)
+    }
+
+    /// Runs both passes on the calling thread, generating the terrain of the neighbours for their features
+    pub fn generate_inline(&self, pos: ChunkPos) -> (UnloadedChunk, ChunkHeightmap) {
+        if let Some(loaded) = load(self, pos, ChunkSource::Saved) {
+            return loaded;
+        }
+        let (chunk, heightmap) = terrain(self, pos);
+        if self.decorations.is_empty() && self.structures.is_empty() {
+            return (chunk, heightmap);
+        }
+        let surface = Arc::new(SurfaceInfo::new(&chunk, heightmap));
+        let neighbors = neighborhood(pos)
+            .map(|neighbor| {
+                if neighbor == pos {
+                    return (neighbor, surface.clone());
+                }
+                let surface = match load(self, neighbor, ChunkSource::Saved) {
+                    Some(_) => SurfaceInfo::empty(),
+                    None => {
+                        let (chunk, heightmap) = terrain(self, neighbor);
+                        SurfaceInfo::new(&chunk, heightmap)
+                    }
+                };
+                (neighbor, Arc::new(surface))
+            })
+            .collect();
+        let proto = ProtoChunk {
+            chunk,
+            surface,
+            terrain_time: Duration::ZERO,
+        };
+        features(
+            self,
+            FeatureJob {
+                pos,
+                proto,
+                neighbors,
+            },
+        )
+    }
+
+    /// Base blocks of all surface rules and strata and the blocks of their palettes, ores only replace these
+    fn base_blocks(&self) -> Vec<BlockState> {
+        let mut blocks = vec![];
+        let bases = [&self.surface]
+            .into_iter()
+            .chain(self.biome_surfaces.values())
+            .map(|rule| rule.block)
+            .chain(self.strata.iter().map(BuiltStratum::block));
+        for block in bases.flat_map(|block| self.palettes.variants(block)) {
+            if !blocks.contains(&block) {
+                blocks.push(block);
+            }
+        }
+        blocks
+    }
+}
+
+/// Salts of the noise trees of a config for [seed_tree], so trees written the same do not give the same noise.
+/// Climate noises add 0 to 2 to the biome salt, and decorations and strata add their index to theirs
+pub(crate) const NOISE_SALT: u64 = 0;
+const DENSITY_SALT: u64 = 1;
+const BIOME_SALT: u64 = 2;
+const CEILING_SALT: u64 = 3;
+const DECORATION_NOISE_SALT: u64 = 16;
+const STRATA_NOISE_SALT: u64 = 1 << 16;
+
+/// Noise deciding which blocks are solid, also used for [height_at](crate::TerrainGenerator::height_at)
+pub(crate) struct TerrainShape {
+    mode: TerrainMode,
+    noise: DynNoise,
+    density: Option<DynNoise3>,
+    /// Set in [FloatingIslands](TerrainMode::FloatingIslands) mode, where `noise` is the mask of the islands
+    islands: Option<FloatingIslands>,
+    /// Set in [Caverns](TerrainMode::Caverns) mode, where `noise` is the height of the floor
+    ceiling: Option<DynNoise>,
+    platform: Option<SpawnPlatform>,
+    height: u32,
+    min_y: i32,
+    /// See [TerrainGenConfig::noise_cell]
+    cell: Option<i32>,
+}
+
+impl TerrainShape {
+    pub fn new(config: &TerrainGenConfig) -> Self {
+        let expanded = config.expanded_superflat();
+        let config = expanded.as_ref().unwrap_or(config);
+        let islands = match config.mode {
+            TerrainMode::FloatingIslands => Some(config.floating_islands.clone()),
+            _ => None,
+        };
+        Self {
+            mode: config.mode,
+            noise: seed_tree(config.seed, config.height_noise(), NOISE_SALT).build2(),
+            density: config
+                .density
+                .clone()
+                .map(|density| seed_tree(config.seed, density, DENSITY_SALT).build3()),
+            islands,
+            ceiling: (config.mode == TerrainMode::Caverns).then(|| {
+                seed_tree(config.seed, config.cavern_ceiling.clone(), CEILING_SALT).build2()
+            }),
+            platform: config.spawn_platform.clone(),
+            height: config.chunk_height(),
+            min_y: config.bottom_y(),
+            cell: config
+                .noise_cell
+                .filter(|cell| *cell > 1 && 16 % cell == 0)
+                .map(|cell| cell as i32),
+        }
+    }
+
+    /// Whether the shape was built for the dimensions of the config, the noise does not depend on anything else
+    /// that [for_layer](TerrainGenConfig::for_layer) fills in
+    pub fn fits(&self, config: &TerrainGenConfig) -> bool {
+        self.height == config.chunk_height() && self.min_y == config.bottom_y()
+    }
+
+    /// Height in the chunk given by the noise, kept inside the chunk so columns always have ground and air
+    fn clamp_height(&self, noise: f64) -> i32 {
+        (noise as i32 - self.min_y).clamp(1, self.height as i32 - 1)
+    }
+
+    /// Value of the height noise at a column, without interpolation or clamping
+    pub fn noise_at(&self, x: i32, z: i32) -> f64 {
+        self.noise.get([x as f64, z as f64])
+    }
+
+    /// One above the highest solid block of a column before features are placed as a world height, `min_y` if the
+    /// column is empty. The spawn platform counts as part of the terrain
+    pub fn height_at(&self, x: i32, z: i32) -> i32 {
+        let height = self.terrain_height(x, z);
+        match &self.platform {
+            Some(platform) if platform.contains(x, z) => height.max(platform.position[1] + 1),
+            _ => height,
+        }
+    }
+
+    fn terrain_height(&self, x: i32, z: i32) -> i32 {
+        if self.mode == TerrainMode::Void {
+            return self.min_y;
+        }
+        // only the cell of the column is sampled
+        let cell = self.cell.unwrap_or(1);
+        let origin = [x.div_euclid(cell) * cell, z.div_euclid(cell) * cell];
+        let area = AreaNoise::new(self, origin, cell);
+        let (x, z) = (x - origin[0], z - origin[1]);
+        let column = area.column(x, z);
+        if self.mode == TerrainMode::Heightmap && self.density.is_none() {
+            return column.top as i32 + self.min_y;
+        }
+        let solid = |y: &i32| area.solid(x, z, *y, column);
+        // in caverns the top is the floor below the highest air, not the top of the roof
+        let roof = match self.mode {
+            TerrainMode::Caverns => (0..self.height as i32)
+                .rev()
+                .find(|y| !solid(y))
+                .unwrap_or(0),
+            _ => self.height as i32,
+        };
+        (0..roof).rev().find(solid).map_or(0, |y| y + 1) + self.min_y
+    }
+}
+
+/// Noise of a square area, sampled every [noise_cell](TerrainGenConfig::noise_cell) blocks and interpolated in
+/// between like vanilla does. Without a cell size every block is sampled
+struct AreaNoise<'a> {
+    shape: &'a TerrainShape,
+    /// Samples per row of the grid
+    width: usize,
+    /// Density samples per column of the grid
+    layers: usize,
+    heights: Vec<f64>,
+    /// Samples of the ceiling noise in [Caverns](TerrainMode::Caverns) mode, empty otherwise
+    ceilings: Vec<f64>,
+    /// Density samples of each grid column from the bottom up
+    densities: Vec<f64>,
+}
+
+impl<'a> AreaNoise<'a> {
+    /// `size` is the width of the area in blocks, a multiple of the cell size
+    fn new(shape: &'a TerrainShape, origin: [i32; 2], size: i32) -> Self {
+        // with cells the samples are on the corners of the cells, so there is one more per row
+        let (step, corners) = match shape.cell {
+            Some(cell) => (cell, 1),
+            None => (1, 0),
+        };
+        let width = (size / step) as usize + corners;
+        let layers = shape.height as usize / step as usize + corners;
+        let columns = (0..width * width)
+            .map(|i| {
+                let x = origin[0] + (i % width) as i32 * step;
+                let z = origin[1] + (i / width) as i32 * step;
+                [x as f64, z as f64]
+            })
+            .collect::<Vec<_>>();
+        let heights = columns
+            .iter()
+            .map(|column| shape.noise.get(*column))
+            .collect();
+        let ceilings = match &shape.ceiling {
+            Some(ceiling) => columns.iter().map(|column| ceiling.get(*column)).collect(),
+            None => vec![],
+        };
+        let mut densities = vec![];
+        if let Some(density) = &shape.density {
+            // density is sampled at world heights
+            let min_y = shape.min_y;
+            densities = columns
+                .iter()
+                .flat_map(|[x, z]| {
+                    (0..layers)
+                        .map(move |k| density.get([*x, *z, (k as i32 * step + min_y) as f64]))
+                })
+                .collect();
+        }
+        Self {
+            shape,
+            width,
+            layers,
+            heights,
+            ceilings,
+            densities,
+        }
+    }
+
+    /// Solid part of a column relative to the origin before density is added, heights are local to the chunk
+    fn column(&self, x: i32, z: i32) -> Column {
+        let noise = self.column_noise(&self.heights, x, z);
+        if self.shape.ceiling.is_some() {
+            let ceiling = self.column_noise(&self.ceilings, x, z) - self.shape.min_y as f64;
+            return Column {
+                bottom: self.shape.clamp_height(noise) as f64,
+                top: ceiling,
+                hollow: true,
+            };
+        }
+        match &self.shape.islands {
+            Some(islands) => match islands.extent(noise) {
+                Some((bottom, top)) => {
+                    let min_y = self.shape.min_y as f64;
+                    Column {
+                        bottom: bottom - min_y,
+                        top: top - min_y,
+                        hollow: false,
+                    }
+                }
+                None => Column::EMPTY,
+            },
+            // see TerrainShape::clamp_height
+            None => Column {
+                bottom: f64::NEG_INFINITY,
+                top: self.shape.clamp_height(noise) as f64,
+                hollow: false,
+            },
+        }
+    }
+
+    /// Interpolated value of 2D samples like `heights` at a column relative to the origin
+    fn column_noise(&self, samples: &[f64], x: i32, z: i32) -> f64 {
+        match self.shape.cell {
+            Some(cell) => {
+                let ((i, tx), (j, tz)) = (split(x, cell), split(z, cell));
+                let h = |i: usize, j: usize| samples[i + j * self.width];
+                lerp(
+                    lerp(h(i, j), h(i + 1, j), tx),
+                    lerp(h(i, j + 1), h(i + 1, j + 1), tx),
+                    tz,
+                )
+            }
+            None => samples[x as usize + z as usize * self.width],
+        }
+    }
+
+    fn solid(&self, x: i32, z: i32, y: i32, column: Column) -> bool {
+        // distance to the nearest of the top and bottom, positive inside the column
+        let inside = (column.top - y as f64).min(y as f64 - column.bottom);
+        let below = if column.hollow { -inside } else { inside };
+        if self.shape.density.is_none() {
+            return below > 0.0;
+        }
+        let d =
+            |i: usize, j: usize, k: usize| self.densities[(i + j * self.width) * self.layers + k];
+        let density = match self.shape.cell {
+            Some(cell) => {
+                let ((i, tx), (j, tz), (k, ty)) = (split(x, cell), split(z, cell), split(y, cell));
+                let layer = |k: usize| {
+                    lerp(
+                        lerp(d(i, j, k), d(i + 1, j, k), tx),
+                        lerp(d(i, j + 1, k), d(i + 1, j + 1, k), tx),
+                        tz,
+                    )
+                };
+                lerp(layer(k), layer(k + 1), ty)
+            }
+            None => d(x as usize, z as usize, y as usize),
+        };
+        below + density > 0.0
+    }
+}
+
+/// Blocks between `bottom` and `top` are solid before density is added, or the blocks outside of them if `hollow`
+#[derive(Clone, Copy)]
+struct Column {
+    bottom: f64,
+    top: f64,
+    hollow: bool,
+}
+
+impl Column {
+    const EMPTY: Self = Self {
+        bottom: f64::NEG_INFINITY,
+        top: f64::NEG_INFINITY,
+        hollow: false,
+    };
+}
+
+/// Index of the cell an offset is in, and how far it is into the cell from 0 to 1
+fn split(offset: i32, cell: i32) -> (usize, f64) {
+    (
+        (offset / cell) as usize,
+        (offset % cell) as f64 / cell as f64,
+    )
+}
+
+fn lerp(a: f64, b: f64, t: f64) -> f64 {
+    a + (b - a) * t
+}
+
+/// Blocks used for filling solid parts of a column
+#[derive(Clone)]
+pub(crate) struct SurfaceRule {
+    pub block: BlockState,
+    pub surface_layers: Vec<(u16, BlockState)>,
+}
+
+impl SurfaceRule {
+    /// Block at the top of a column
+    pub fn top_block(&self) -> BlockState {
+        surface_block(&self.surface_layers, 0).unwrap_or(self.block)
+    }
+}
+
+/// Surface rules of a generator from before it was reloaded, for [Job::Repaint]
+pub(crate) struct OldSurfaces {
+    surface: SurfaceRule,
+    biome_surfaces: HashMap<BiomeId, SurfaceRule>,
+}
+
+impl OldSurfaces {
+    pub fn new(state: &ChunkWorkerState) -> Self {
+        Self {
+            surface: state.surface.clone(),
+            biome_surfaces: state.biome_surfaces.clone(),
+        }
+    }
+
+    fn surface_rule(&self, biome: Option<BiomeId>) -> &SurfaceRule {
+        biome
+            .and_then(|biome| self.biome_surfaces.get(&biome))
+            .unwrap_or(&self.surface)
+    }
+}
+
+/// Blocks to change in a chunk as `[x, y, z]` in the chunk, the block it should still have and the new block
+pub(crate) type Repaint = Vec<([u32; 3], BlockState, BlockState)>;
+
+/// Biomes of the 4x4 columns of a chunk, indexed by x then z
+type BiomeGrid = [[BiomeId; 4]; 4];
+
+/// Work sent to the workers
+pub(crate) enum Job {
+    /// Generates a chunk in a single pass, used when there are no features
+    Generate(ChunkPos, ChunkSource),
+    /// First pass, generates the terrain of a [ProtoChunk]
+    Terrain(ChunkPos, ChunkSource),
+    /// Second pass, places the features of a [ProtoChunk]
+    Features(Box<FeatureJob>),
+    /// Saves an unloaded chunk or checks it for edits
+    Unload(Box<UnloadJob>),
+    /// Finds the blocks of a loaded chunk that the new surface rules paint differently, see
+    /// [reload_incremental](crate::TerrainGenerator::reload_incremental)
+    Repaint(Box<RepaintJob>),
+    /// Draws a [TerrainMap](crate::map::TerrainMap), the colors are sent to the map instead of the generator
+    Map(Box<MapJob>),
+}
+
+/// Where the chunk of a [Job::Generate] or [Job::Terrain] comes from
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub(crate) enum ChunkSource {
+    /// Loaded from the Anvil world if it is saved there, generated otherwise
+    Saved,
+    /// Generated even if it is saved, for [regenerated](crate::TerrainGenerator::regenerate) chunks
+    Generated,
+}
+
+pub(crate) struct FeatureJob {
+    pub pos: ChunkPos,
+    pub proto: ProtoChunk,
+    /// Surfaces of the 3x3 chunks around the proto chunk, in the order of [neighborhood]
+    pub neighbors: Vec<(ChunkPos, Arc<SurfaceInfo>)>,
+}
+
+pub(crate) struct RepaintJob {
+    pub pos: ChunkPos,
+    pub old: Arc<OldSurfaces>,
+}
+
+pub(crate) struct UnloadJob {
+    pub pos: ChunkPos,
+    pub chunk: UnloadedChunk,
+    /// Writes the chunk to the Anvil world
+    pub save: bool,
+    /// Sends the chunk back if it was changed after it was generated
+    pub keep_edits: bool,
+    /// Sends the chunk back compressed like this if it was not changed, for the [ChunkCache](crate::cache::ChunkCache)
+    pub cache: Option<CacheCompression>,
+    /// [block_hash] of the chunk when it was generated, None if the chunk is already known to be edited
+    pub generated_hash: Option<u64>,
+}
+
+/// Result of a job sent back from a worker
+pub(crate) struct GeneratedChunk {
+    pub pos: ChunkPos,
+    /// Epoch of the generator the job was sent by, see [WorkerQueue]
+    pub epoch: u64,
+    /// Error if the worker panicked while generating
+    pub result: Result<Generated, String>,
+    /// Wall-clock time spent generating the chunk
+    pub duration: Duration,
+}
+
+pub(crate) enum Generated {
+    Proto(ProtoChunk),
+    /// Generated chunk with its [block_hash]
+    Finished(UnloadedChunk, ChunkHeightmap, u64),
+    /// Chunk loaded from the Anvil world instead of the first pass, it does not get a second pass
+    Loaded(UnloadedChunk, ChunkHeightmap, u64),
+    /// A [Job::Unload] is done, the chunk is sent back if it should be kept
+    Unloaded(Unloaded),
+    /// Changes of a [Job::Repaint], applied to the blocks of the chunk that were not changed since
+    Repainted(Repaint),
+}
+
+pub(crate) enum Unloaded {
+    Dropped,
+    /// Chunk that was changed after it was generated
+    Edited(UnloadedChunk, ChunkHeightmap),
+    Cached(CachedChunk),
+}
+
+/// Jobs of a single generator waiting for a worker, the job with the lowest priority is taken first.  
+/// Priorities can change after the jobs are queued, so chunks near the players are generated first even if they were
+/// queued long ago
+#[derive(Default)]
+pub(crate) struct JobQueue {
+    jobs: Mutex<QueuedJobs>,
+}
+
+/// Jobs by the order they were queued in, with a heap of their priorities. Entries of the heap are not updated when a
+/// job is reprioritized or removed, the ones that do not match their job anymore are skipped when they come up
+#[derive(Default)]
+struct QueuedJobs {
+    jobs: HashMap<u64, (u64, Job)>,
+    heap: BinaryHeap<Reverse<(u64, u64)>>,
+    next_id: u64,
+}
+
+impl QueuedJobs {
+    /// Builds the heap again once most of it is skipped entries
+    fn compact(&mut self) {
+        if self.heap.len() > 2 * self.jobs.len() + 64 {
+            self.heap = self
+                .jobs
+                .iter()
+                .map(|(id, (priority, _))| Reverse((*priority, *id)))
+                .collect();
+        }
+    }
+}
+
+impl JobQueue {
+    pub fn push(&self, priority: u64, job: Job) {
+        let mut queued = self.jobs.lock().unwrap();
+        let id = queued.next_id;
+        queued.next_id += 1;
+        queued.jobs.insert(id, (priority, job));
+        queued.heap.push(Reverse((priority, id)));
+    }
+
+    /// Jobs with the same priority are taken in the order they were queued
+    fn pop(&self) -> Option<Job> {
+        let mut queued = self.jobs.lock().unwrap();
+        while let Some(Reverse((priority, id))) = queued.heap.pop() {
+            if queued
+                .jobs
+                .get(&id)
+                .is_some_and(|(current, _)| *current == priority)
+            {
+                return queued.jobs.remove(&id).map(|(_, job)| job);
+            }
+        }
+        None
+    }
+
+    pub fn len(&self) -> usize {
+        self.jobs.lock().unwrap().jobs.len()
+    }
+
+    /// Sets the priority of every queued job to `priority` of its position, jobs it returns None for are unchanged
+    pub fn reprioritize(&self, priority: impl Fn(ChunkPos) -> Option<u64>) {
+        let mut guard = self.jobs.lock().unwrap();
+        let queued = &mut *guard;
+        for (id, (old, job)) in &mut queued.jobs {
+            // unloading is not delayed, the chunk can not be loaded again until it is done, and maps are not chunks
+            if let Job::Unload(_) | Job::Map(_) = job {
+                continue;
+            }
+            match priority(job.pos()) {
+                Some(priority) if priority != *old => {
+                    *old = priority;
+                    queued.heap.push(Reverse((priority, *id)));
+                }
+                _ => {}
+            }
+        }
+        queued.compact();
+    }
+
+    /// Removes the [Job::Unload]s that save a chunk, for saving them right away
+    pub fn take_saves(&self) -> Vec<UnloadJob> {
+        let mut queued = self.jobs.lock().unwrap();
+        let mut ids = queued
+            .jobs
+            .iter()
+            .filter(|(_, (_, job))| matches!(job, Job::Unload(unload) if unload.save))
+            .map(|(id, _)| *id)
+            .collect::<Vec<_>>();
+        ids.sort_unstable();
+        let saves = ids
+            .into_iter()
+            .filter_map(|id| match queued.jobs.remove(&id) {
+                Some((_, Job::Unload(unload))) => Some(*unload),
+                _ => None,
+            })
+            .collect();
+        queued.compact();
+        saves
+    }
+
+    /// Removes the jobs generating a chunk if no worker has taken them yet, unloading and repainting are kept
+    pub fn cancel(&self, pos: ChunkPos) {
+        let mut queued = self.jobs.lock().unwrap();
+        queued.jobs.retain(|_, (_, job)| {
+            !matches!(job, Job::Generate(..) | Job::Terrain(..) | Job::Features(_))
+                || job.pos() != pos
+        });
+        queued.compact();
+    }
+}
+
+impl Job {
+    /// Name of the job in the spans of the `trace` feature
+    #[cfg(feature = "trace")]
+    fn stage(&self) -> &'static str {
+        match self {
+            Job::Generate(..) => "generate",
+            Job::Terrain(..) => "terrain",
+            Job::Features(_) => "features",
+            Job::Unload(_) => "unload",
+            Job::Repaint(_) => "repaint",
+            Job::Map(_) => "map",
+        }
+    }
+
+    pub fn pos(&self) -> ChunkPos {
+        match self {
+            Job::Generate(pos, _) | Job::Terrain(pos, _) => *pos,
+            Job::Features(job) => job.pos,
+            Job::Unload(job) => job.pos,
+            Job::Repaint(job) => job.pos,
+            Job::Map(job) => {
+                ChunkPos::new(job.center[0].div_euclid(16), job.center[1].div_euclid(16))
+            }
+        }
+    }
+}
+
+/// Queue of a single generator, workers of the [TerrainWorkerPool](crate::TerrainWorkerPool) take one job for each
+/// time the queue is submitted to the pool
+pub(crate) struct WorkerQueue {
+    pub state: ChunkWorkerState,
+    pub jobs: Arc<JobQueue>,
+    pub results: Sender<GeneratedChunk>,
+    /// Incremented on every reload of a generator, results are tagged with it so the ones from before a reload are dropped
+    pub epoch: u64,
+    /// Set when the generator is dropped, the remaining jobs are skipped except for saving chunks
+    cancelled: AtomicBool,
+}
+
+impl WorkerQueue {
+    pub fn new(
+        state: ChunkWorkerState,
+        epoch: u64,
+        jobs: Arc<JobQueue>,
+        results: Sender<GeneratedChunk>,
+    ) -> Self {
+        Self {
+            state,
+            jobs,
+            results,
+            epoch,
+            cancelled: AtomicBool::new(false),
+        }
+    }
+
+    pub fn cancel(&self) {
+        self.cancelled.store(true, Ordering::Relaxed);
+    }
+}
+
+/// Runs on the threads of the pool until the pool is dropped
+pub(crate) fn pool_worker(queues: Receiver<Arc<WorkerQueue>>) {
+    while let Ok(queue) = queues.recv() {
+        // cancelled jobs leave their submission behind
+        let Some(job) = queue.jobs.pop() else {
+            continue;
+        };
+        if queue.cancelled.load(Ordering::Relaxed) && !matches!(&job, Job::Unload(job) if job.save)
+        {
+            continue;
+        }
+        run_job(&queue, job);
+    }
+}
+
+fn run_job(queue: &WorkerQueue, job: Job) {
+    let state = &queue.state;
+    if let Job::Map(job) = job {
+        // a map that panics is drawn again once the sender is dropped
+        let _ = panic::catch_unwind(AssertUnwindSafe(|| {
+            let _ = job.sender.send(draw_map(state, job.center, job.scale));
+        }));
+        return;
+    }
+    let start = Instant::now();
+    let pos = job.pos();
+    let terrain_time = match &job {
+        Job::Features(job) => job.proto.terrain_time,
+        _ => Duration::ZERO,
+    };
+    #[cfg(feature = "trace")]
+    let span = tracing::info_span!(
+        "terrain_job",
+        x = pos.x,
+        z = pos.z,
+        stage = job.stage(),
+        duration_ms = tracing::field::Empty
+    )
+    .entered();
+    // catching the panic keeps the worker alive, so a bad chunk does not shrink the pool
+    let result = panic::catch_unwind(AssertUnwindSafe(|| match job {
+        Job::Generate(pos, source) => match load(state, pos, source) {
+            Some((chunk, heightmap)) => loaded(chunk, heightmap),
+            None => {
+                let (chunk, heightmap) = terrain(state, pos);
+                let hash = block_hash(&chunk);
+                Generated::Finished(chunk, heightmap, hash)
+            }
+        },
+        Job::Terrain(pos, source) => match load(state, pos, source) {
+            Some((chunk, heightmap)) => loaded(chunk, heightmap),
+            None => {
+                let (chunk, heightmap) = terrain(state, pos);
+                let surface = Arc::new(SurfaceInfo::new(&chunk, heightmap));
+                Generated::Proto(ProtoChunk {
+                    chunk,
+                    surface,
+                    terrain_time: start.elapsed(),
+                })
+            }
+        },
+        Job::Features(job) => {
+            let (chunk, heightmap) = features(state, *job);
+            let hash = block_hash(&chunk);
+            Generated::Finished(chunk, heightmap, hash)
+        }
+        Job::Unload(job) => Generated::Unloaded(unload(state, *job)),
+        Job::Repaint(job) => Generated::Repainted(repaint(state, &job)),
+        Job::Map(_) => unreachable!("maps are drawn before the chunk jobs"),
+    }))
+    .map_err(|payload| panic_message(payload.as_ref()));
+    if let (Ok(Generated::Finished(chunk, _, _)), Some(anvil)) = (&result, &state.anvil) {
+        if anvil.saves() {
+            anvil.save(pos, chunk);
+        }
+    }
+    #[cfg(feature = "trace")]
+    span.record("duration_ms", start.elapsed().as_secs_f64() * 1000.0);
+    let _ = queue.results.send(GeneratedChunk {
+        pos,
+        epoch: queue.epoch,
+        result,
+        duration: start.elapsed() + terrain_time,
+    });
+}
+
+/// Chunks from the Anvil world are used as they are, so they are neither saved again nor given features
+fn loaded(chunk: UnloadedChunk, heightmap: ChunkHeightmap) -> Generated {
+    let hash = block_hash(&chunk);
+    Generated::Loaded(chunk, heightmap, hash)
+}
+
+/// Loads the chunk from the Anvil world if there is one and the chunk comes from it
+fn load(
+    state: &ChunkWorkerState,
+    pos: ChunkPos,
+    source: ChunkSource,
+) -> Option<(UnloadedChunk, ChunkHeightmap)> {
+    state
+        .anvil
+        .as_ref()
+        .filter(|_| source == ChunkSource::Saved)
+        .and_then(|anvil| anvil.load(pos, state.height))
+}
+
+#[cfg_attr(feature = "trace", tracing::instrument(skip_all))]
+fn unload(state: &ChunkWorkerState, job: UnloadJob) -> Unloaded {
+    if job.save {
+        if let Some(anvil) = &state.anvil {
+            anvil.save(job.pos, &job.chunk);
+        }
+    }
+    let edited = match job.generated_hash {
+        Some(hash) => block_hash(&job.chunk) != hash,
+        None => true,
+    };
+    match (edited, job.generated_hash, job.cache) {
+        (true, _, _) if job.keep_edits => {
+            let heightmap = ChunkHeightmap::from_chunk(&job.chunk);
+            Unloaded::Edited(job.chunk, heightmap)
+        }
+        (false, Some(hash), Some(compression)) => {
+            let heightmap = ChunkHeightmap::from_chunk(&job.chunk);
+            Unloaded::Cached(CachedChunk::new(job.chunk, heightmap, hash, compression))
+        }
+        _ => Unloaded::Dropped,
+    }
+}
+
+/// Hash of the blocks of a chunk, used to find chunks that were changed after they were generated
+pub(crate) fn block_hash(chunk: &impl Chunk) -> u64 {
+    let mut hasher = DefaultHasher::new();
+    for y in 0..chunk.height() {
+        for z in 0..16 {
+            for x in 0..16 {
+                chunk.block_state(x, y, z).to_raw().hash(&mut hasher);
+            }
+        }
+    }
+    hasher.finish()
+}
+
+/// Generates everything except for features
+#[cfg_attr(feature = "trace", tracing::instrument(skip_all))]
+fn terrain(state: &ChunkWorkerState, pos: ChunkPos) -> (UnloadedChunk, ChunkHeightmap) {
+    let biomes = state.biomes.as_ref().map(|biomes| biome_grid(biomes, pos));
+    let border = state
+        .world_border
+        .as_ref()
+        .filter(|border| !border.contains(pos));
+    let (mut chunk, mut heightmap) = match (border, state.mode) {
+        (Some(border), _) => border.fill(
+            pos,
+            state.height,
+            state.min_y,
+            state.surface.block,
+            state.fluid,
+            state.sea_level,
+        ),
+        (None, TerrainMode::Heightmap | TerrainMode::FloatingIslands | TerrainMode::Caverns) => {
+            heightmap_chunk(state, pos, biomes.as_ref())
+        }
+        (None, TerrainMode::Void) => (
+            UnloadedChunk::with_height(state.height),
+            ChunkHeightmap::default(),
+        ),
+    };
+    if let Some(biomes) = &biomes {
+        set_biomes(biomes, &mut chunk);
+    }
+    if border.is_none() && state.mode != TerrainMode::Void && !state.ores.is_empty() {
+        let base_blocks = state.base_blocks();
+        for (i, ore) in state.ores.iter().enumerate() {
+            let mut rng = chunk_rng(pos, i as u64);
+            place_ore(ore, &mut rng, &base_blocks, state.min_y, &mut chunk);
+        }
+    }
+    if let (Some(platform), None) = (&state.spawn_platform, border) {
+        platform.place(pos, state.min_y, &mut chunk, &mut heightmap);
+    }
+    (chunk, heightmap)
+}
+
+/// Places the features starting in the chunk and its neighbours
+#[cfg_attr(feature = "trace", tracing::instrument(skip_all))]
+fn features(state: &ChunkWorkerState, job: FeatureJob) -> (UnloadedChunk, ChunkHeightmap) {
+    let mut chunk = job.proto.chunk;
+    let mut heightmap = job.proto.surface.heightmap.clone();
+    let outside = |pos: &ChunkPos| {
+        state
+            .world_border
+            .as_ref()
+            .is_some_and(|border| !border.contains(*pos))
+    };
+    // chunks outside of the border only contain its fill, and features starting outside of it are not placed inside
+    if outside(&job.pos) {
+        return (chunk, heightmap);
+    }
+    let neighbors = job
+        .neighbors
+        .iter()
+        .filter(|(origin, _)| !outside(origin))
+        .collect::<Vec<_>>();
+    let mut placer = Placer::new(job.pos, &mut chunk, &mut heightmap);
+    for (i, decoration) in state.decorations.iter().enumerate() {
+        for (origin, surface) in &neighbors {
+            let mut rng = chunk_rng(*origin, DECORATION_SALT + i as u64);
+            placer.set_origin(*origin);
+            decoration.place(*origin, surface, &mut rng, &mut placer);
+        }
+    }
+    for structure in &state.structures {
+        for (origin, surface) in &neighbors {
+            let mut rng = chunk_rng(*origin, STRUCTURE_SALT + structure.salt());
+            placer.set_origin(*origin);
+            structure.place(surface, &mut rng, &mut placer);
+        }
+    }
+    (chunk, heightmap)
+}
+
+/// Added to the salt of decorations so they are not correlated with the ores
+const DECORATION_SALT: u64 = 1 << 32;
+
+/// Added to the salt of structures
+const STRUCTURE_SALT: u64 = 2 << 32;
+
+/// Mixed into the seed of bedrock layers so they are not correlated with the palettes
+const BEDROCK_SALT: u64 = 3 << 32;
+
+/// Random generator that gives the same results every time a chunk is generated, salt should differ between uses
+pub(crate) fn chunk_rng(pos: ChunkPos, salt: u64) -> StdRng {
+    // large odd constants to spread out nearby chunks
+    let seed = (pos.x as u64).wrapping_mul(0x9E37_79B9_7F4A_7C15)
+        ^ (pos.z as u64).wrapping_mul(0xC2B2_AE3D_27D4_EB4F)
+        ^ salt.wrapping_mul(0x1656_67B1_9E37_79F9);
+    StdRng::seed_from_u64(seed)
+}
+
+fn panic_message(payload: &(dyn std::any::Any + Send)) -> String {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg.to_string()
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg.clone()
+    } else {
+        "worker panicked".into()
+    }
+}
+
+#[cfg_attr(feature = "trace", tracing::instrument(skip_all))]
+fn heightmap_chunk(
+    state: &ChunkWorkerState,
+    pos: ChunkPos,
+    biomes: Option<&BiomeGrid>,
+) -> (UnloadedChunk, ChunkHeightmap) {
+    let mut chunk = UnloadedChunk::with_height(state.height);
+    let mut heightmap = ChunkHeightmap::default();
+    let chunk_height = state.height as usize;
+    // reused between columns to avoid allocating
+    let mut solid = vec![false; chunk_height];
+    let mut boundaries = Vec::with_capacity(state.strata.len());
+    let area = AreaNoise::new(&state.shape, [pos.x * 16, pos.z * 16], 16);
+    // all columns are painted before they are written, so uniform sections can be filled at once
+    let mut columns = vec![BlockState::AIR; 256 * chunk_height];
+    for offset_x in 0..16 {
+        for offset_z in 0..16 {
+            let start = (offset_x + offset_z * 16) as usize * chunk_height;
+            let column = &mut columns[start..start + chunk_height];
+            let (x, z) = (offset_x as i32, offset_z as i32);
+            let column_shape = area.column(x, z);
+            for (y, solid) in solid.iter_mut().enumerate() {
+                *solid = area.solid(x, z, y as i32, column_shape);
+            }
+            let rule = match biomes {
+                Some(biomes) => state
+                    .biome_surfaces
+                    .get(&biomes[offset_x as usize / 4][offset_z as usize / 4])
+                    .unwrap_or(&state.surface),
+                None => &state.surface,
+            };
+            let (x, z) = (pos.x * 16 + x, pos.z * 16 + z);
+            paint_terrain_column(state, rule, &mut boundaries, &solid, x, z, column);
+            if let Some(floor) = &state.bedrock_floor {
+                floor.place(false, x, z, state.seed ^ BEDROCK_SALT, column);
+            }
+            if let Some(sea_level) = state.sea_level {
+                let sea_level = (sea_level - state.min_y).clamp(0, column.len() as i32) as usize;
+                for block in column[..sea_level].iter_mut() {
+                    if block.is_air() {
+                        *block = state.fluid;
+                    }
+                }
+            }
+            // in caverns the top is the floor below the highest air, not the top of the roof
+            let roof = match state.mode {
+                TerrainMode::Caverns => {
+                    column.iter().rposition(|block| block.is_air()).unwrap_or(0)
+                }
+                _ => column.len(),
+            };
+            let top = column[..roof]
+                .iter()
+                .rposition(|block| !block.is_air())
+                .map_or(0, |y| y + 1);
+            heightmap.set(offset_x, offset_z, top as u32);
+            // placed after the heightmap, so features are placed on the terrain below the ceiling
+            if let Some(ceiling) = &state.bedrock_ceiling {
+                ceiling.place(true, x, z, state.seed ^ BEDROCK_SALT, column);
+            }
+        }
+    }
+    for sect_y in 0..chunk_height / 16 {
+        let ys = sect_y * 16..sect_y * 16 + 16;
+        let first = columns[ys.start];
+        let uniform = columns
+            .chunks(chunk_height)
+            .all(|column| column[ys.clone()].iter().all(|block| *block == first));
+        // new chunks are already air
+        if uniform {
+            if !first.is_air() {
+                chunk.fill_block_state_section(sect_y as u32, first);
+            }
+            continue;
+        }
+        for (i, column) in columns.chunks(chunk_height).enumerate() {
+            for (y, block) in column[ys.clone()].iter().enumerate() {
+                if !block.is_air() {
+                    let y = (ys.start + y) as u32;
+                    chunk.set_block_state(i as u32 % 16, y, i as u32 / 16, *block);
+                }
+            }
+        }
+    }
+    (chunk, heightmap)
+}
+
+/// Paints the solid blocks of the column at world `x` and `z` with a surface rule, strata and palettes.  
+/// `boundaries` is only reused between columns to avoid allocating
+fn paint_terrain_column(
+    state: &ChunkWorkerState,
+    rule: &SurfaceRule,
+    boundaries: &mut Vec<(i32, BlockState)>,
+    solid: &[bool],
+    x: i32,
+    z: i32,
+    column: &mut [BlockState],
+) {
+    boundaries.clear();
+    boundaries.extend(
+        state
+            .strata
+            .iter()
+            .map(|stratum| (stratum.boundary(x, z) - state.min_y, stratum.block())),
+    );
+    paint_column(
+        rule,
+        boundaries,
+        state.surface_on_all_exposed,
+        state.mode == TerrainMode::Caverns,
+        solid,
+        column,
+    );
+    if !state.palettes.is_empty() {
+        for (y, block) in column.iter_mut().enumerate() {
+            if !block.is_air() {
+                let y = y as i32 + state.min_y;
+                *block = state.palettes.pick(*block, [x, y, z]);
+            }
+        }
+    }
+}
+
+/// Blocks of the terrain of a chunk that are painted differently by the surface rules from before a reload, the
+/// shape of the terrain is the same for both
+fn repaint(state: &ChunkWorkerState, job: &RepaintJob) -> Repaint {
+    let mut changes = vec![];
+    if state.mode == TerrainMode::Void {
+        return changes;
+    }
+    let pos = job.pos;
+    let biomes = state.biomes.as_ref().map(|biomes| biome_grid(biomes, pos));
+    let chunk_height = state.height as usize;
+    let mut solid = vec![false; chunk_height];
+    let mut boundaries = Vec::with_capacity(state.strata.len());
+    let (mut old, mut new) = (
+        vec![BlockState::AIR; chunk_height],
+        vec![BlockState::AIR; chunk_height],
+    );
+    let area = AreaNoise::new(&state.shape, [pos.x * 16, pos.z * 16], 16);
+    for offset_x in 0..16 {
+        for offset_z in 0..16 {
+            let (x, z) = (offset_x as i32, offset_z as i32);
+            let column_shape = area.column(x, z);
+            for (y, solid) in solid.iter_mut().enumerate() {
+                *solid = area.solid(x, z, y as i32, column_shape);
+            }
+            let biome = biomes
+                .as_ref()
+                .map(|biomes| biomes[offset_x as usize / 4][offset_z as usize / 4]);
+            let (x, z) = (pos.x * 16 + x, pos.z * 16 + z);
+            let old_rule = job.old.surface_rule(biome);
+            paint_terrain_column(state, old_rule, &mut boundaries, &solid, x, z, &mut old);
+            let new_rule = state.surface_rule(biome);
+            paint_terrain_column(state, new_rule, &mut boundaries, &solid, x, z, &mut new);
+            for (y, (old, new)) in old.iter().zip(&new).enumerate() {
+                if old != new {
+                    changes.push(([offset_x, y as u32, offset_z], *old, *new));
+                }
+            }
+        }
+    }
+    changes
+}
+
+/// Fills a column from a mask of solid blocks.  
+/// Solid blocks become a surface layer if they are close enough below an exposed surface, and the base block otherwise.
+/// The base block is replaced by the strata below their `boundaries`.
+/// Depending on `all_exposed` only the top surface or every surface is painted.
+/// If `roofed` the top of the column is not a surface, so the first floor below the roof is painted as the top one
+fn paint_column(
+    rule: &SurfaceRule,
+    boundaries: &[(i32, BlockState)],
+    all_exposed: bool,
+    roofed: bool,
+    solid: &[bool],
+    column: &mut [BlockState],
+) {
+    // depth below the surface being painted, None when surface layers no longer apply
+    let mut depth = None;
+    let mut above_solid = roofed;
+    let mut found_surface = false;
+    for y in (0..solid.len()).rev() {
+        if !solid[y] {
+            column[y] = BlockState::AIR;
+            above_solid = false;
+            continue;
+        }
+        if !above_solid {
+            depth = if all_exposed || !found_surface {
+                Some(0)
+            } else {
+                None
+            };
+            found_surface = true;
+        }
+        above_solid = true;
+        column[y] = depth
+            .and_then(|d| surface_block(&rule.surface_layers, d))
+            .unwrap_or_else(|| stratum_block(rule.block, boundaries, y as i32));
+        depth = depth.map(|d| d + 1);
+    }
+}
+
+/// Block of the surface layers at a depth below the surface, layers are listed from the bottom up
+fn surface_block(layers: &[(u16, BlockState)], depth: u32) -> Option<BlockState> {
+    let mut top = 0;
+    for (amount, block) in layers.iter().rev() {
+        top += *amount as u32;
+        if depth < top {
+            return Some(*block);
+        }
+    }
+    None
+}
+
+/// Biomes are stored in 4x4x4 cells, each cell uses the biome at its center column
+fn biome_grid(biomes: &BiomePicker, pos: ChunkPos) -> BiomeGrid {
+    let mut grid = [[BiomeId::default(); 4]; 4];
+    for (cell_x, row) in grid.iter_mut().enumerate() {
+        for (cell_z, biome) in row.iter_mut().enumerate() {
+            *biome = biomes.get(
+                (pos.x * 16 + cell_x as i32 * 4 + 2) as f64,
+                (pos.z * 16 + cell_z as i32 * 4 + 2) as f64,
+            );
+        }
+    }
+    grid
+}
+
+fn set_biomes(biomes: &BiomeGrid, chunk: &mut UnloadedChunk) {
+    for (cell_x, row) in biomes.iter().enumerate() {
+        for (cell_z, biome) in row.iter().enumerate() {
+            for cell_y in 0..chunk.height() / 4 {
+                chunk.set_biome(cell_x as u32, cell_y, cell_z as u32, *biome);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn state(config: TerrainGenConfig) -> ChunkWorkerState {
+        let config = TerrainGenConfig {
+            height: Some(32),
+            ..config
+        };
+        ChunkWorkerState::new(config, &BiomeRegistry::default())
+    }
+
+    /// Runs a job on the calling thread and returns its result
+    fn run(state: ChunkWorkerState, job: Job) -> GeneratedChunk {
+        let (sender, receiver) = flume::unbounded();
+        let queue = WorkerQueue::new(state, 0, Arc::default(), sender);
+        run_job(&queue, job);
+        receiver.try_recv().unwrap()
+    }
+
+    #[test]
+    fn generated_chunk_has_duration() {
+        let generated = run(
+            state(TerrainGenConfig::default()),
+            Job::Generate(ChunkPos::new(0, 0), ChunkSource::Saved),
+        );
+        assert!(matches!(generated.result, Ok(Generated::Finished(..))));
+        assert!(generated.duration > Duration::ZERO);
+        assert!(generated.duration.as_secs_f64().is_finite());
+    }
+
+    #[test]
+    fn void_chunk_is_air() {
+        let generated = run(
+            state(TerrainGenConfig::void(32)),
+            Job::Generate(ChunkPos::new(3, -2), ChunkSource::Saved),
+        );
+        let Ok(Generated::Finished(chunk, heightmap, _)) = generated.result else {
+            panic!("void chunk was not generated");
+        };
+        for y in 0..chunk.height() {
+            for z in 0..16 {
+                for x in 0..16 {
+                    assert!(chunk.block_state(x, y, z).is_air());
+                }
+            }
+        }
+        for z in 0..16 {
+            for x in 0..16 {
+                assert_eq!(heightmap.get(x, z), 0);
+            }
+        }
+    }
+
+    #[test]
+    fn panic_is_reported() {
+        let config = TerrainGenConfig {
+            noise: NoiseBuilder::custom(|_| panic!("boom")),
+            ..Default::default()
+        };
+        let generated = run(
+            state(config),
+            Job::Generate(ChunkPos::new(0, 0), ChunkSource::Saved),
+        );
+        match generated.result {
+            Err(message) => assert!(message.contains("boom")),
+            Ok(_) => panic!("the panic was not caught"),
+        }
+    }
+
+    #[test]
+    fn heightmap_matches_top_block() {
+        let config = TerrainGenConfig {
+            noise: NoiseBuilder::parse("scalein 0.05 0.05 perlin 0 * 10 + 16").unwrap(),
+            ..Default::default()
+        };
+        let (chunk, heightmap) = terrain(&state(config), ChunkPos::new(2, 5));
+        let mut heights = vec![];
+        for z in 0..16 {
+            for x in 0..16 {
+                let top = (0..chunk.height())
+                    .rev()
+                    .find(|y| !chunk.block_state(x, *y, z).is_air())
+                    .map_or(0, |y| y + 1);
+                assert_eq!(heightmap.get(x, z), top);
+                heights.push(top);
+            }
+        }
+        // the noise is not flat over the chunk
+        assert!(heights.iter().any(|height| *height != heights[0]));
+    }
+
+    #[test]
+    fn surface_on_all_exposed() {
+        let rule = SurfaceRule {
+            block: BlockState::STONE,
+            surface_layers: vec![(1, BlockState::DIRT), (1, BlockState::GRASS_BLOCK)],
+        };
+        // two bands of solid blocks, 0 to 5 and 8 to 11
+        let solid = (0..16)
+            .map(|y| y < 6 || (8..12).contains(&y))
+            .collect::<Vec<_>>();
+        let paint = |all_exposed| {
+            let mut column = vec![BlockState::AIR; solid.len()];
+            paint_column(&rule, &[], all_exposed, false, &solid, &mut column);
+            column
+        };
+        let column = paint(true);
+        assert_eq!(column[11], BlockState::GRASS_BLOCK);
+        assert_eq!(column[10], BlockState::DIRT);
+        assert_eq!(column[5], BlockState::GRASS_BLOCK);
+        assert_eq!(column[4], BlockState::DIRT);
+        assert_eq!(column[3], BlockState::STONE);
+        assert!(column[6].is_air() && column[12].is_air());
+        // only the top band gets surface layers otherwise
+        let column = paint(false);
+        assert_eq!(column[11], BlockState::GRASS_BLOCK);
+        assert_eq!(column[5], BlockState::STONE);
+    }
+
+    #[test]
+    fn jobs_follow_priority() {
+        let queue = JobQueue::default();
+        for x in 0..4 {
+            queue.push(
+                x as u64,
+                Job::Generate(ChunkPos::new(x, 0), ChunkSource::Saved),
+            );
+        }
+        queue.push(0, Job::Terrain(ChunkPos::new(2, 0), ChunkSource::Saved));
+        queue.cancel(ChunkPos::new(2, 0));
+        // the last chunk moved closest to the players
+        queue.reprioritize(|pos| (pos.x == 3).then_some(0));
+        let order = std::iter::from_fn(|| queue.pop())
+            .map(|job| job.pos().x)
+            .collect::<Vec<_>>();
+        assert_eq!(order, [0, 3, 1]);
+        assert_eq!(queue.len(), 0);
+    }
+}